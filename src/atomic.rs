@@ -0,0 +1,113 @@
+// Crash-safe writes for the data directory
+// Data goes to a temporary file next to the target, is flushed to disk and renamed over it, so a crash or another
+// reader sees either the old or the new contents and never half of each
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Drop-in replacement for `fs::write`; keeps the permissions of the file it replaces
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    replace(path.as_ref(), contents.as_ref(), |from, to| fs::rename(from, to))
+}
+
+/// `write` with the final rename passed in, so tests can make it fail
+fn replace(path: &Path, contents: &[u8], rename: fn(&Path, &Path) -> io::Result<()>) -> io::Result<()> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file path", path.display())))?;
+    let tmp = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    sync_dir(dir);
+    Ok(())
+}
+
+/// Appends one line, starting it on a new line when the file does not end with one
+pub fn append_line<P: AsRef<Path>>(path: P, line: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(line);
+    contents.push('\n');
+    write(path, contents)
+}
+
+/// Temporary files left behind by a write that was interrupted
+pub fn is_leftover(file_name: &str) -> bool {
+    file_name.starts_with('.') && file_name.ends_with(".tmp")
+}
+
+/// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn leftovers(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| is_leftover(name))
+            .collect()
+    }
+
+    #[test]
+    fn write_replaces_contents() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.json");
+        write(&path, "old").unwrap();
+        write(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn failed_rename_keeps_original() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.json");
+        fs::write(&path, "original").unwrap();
+
+        let result = replace(&path, b"new", |_, _| Err(io::Error::other("rename failed")));
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn append_line_starts_on_a_new_line() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session_calls.txt");
+        fs::write(&path, "POST /spells {}").unwrap();
+        append_line(&path, "DELETE /spells/1").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "POST /spells {}\nDELETE /spells/1\n");
+    }
+}
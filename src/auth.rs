@@ -1,5 +1,7 @@
 use crossterm::style::Stylize;
-use crate::{SERVER, check_setup_cmpl};
+use crate::error::Error;
+use crate::{atomic, check_setup_cmpl, client, logging, store, ui, SERVER};
+use log::{debug, info};
 
 async fn base_login(username: &str, password: &str) -> Result<(String, String), anyhow::Error> {
     let client = reqwest::Client::new();
@@ -17,24 +19,18 @@ async fn base_login(username: &str, password: &str) -> Result<(String, String),
 
         // Extract token and user id
         let token = login_response["token"].as_str()
-            .ok_or_else(|| anyhow::anyhow!("Token not found in response"))?
+            .ok_or_else(|| Error::Network("Token not found in response".to_string()))?
             .to_string();
         let user_id = login_response["id"].as_str()
-            .ok_or_else(|| anyhow::anyhow!("User ID not found in response"))?
+            .ok_or_else(|| Error::Network("User ID not found in response".to_string()))?
             .to_string();
 
         // Save token to file
-        let home_dir = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-        let auth_file_path = home_dir.join(".archerdndsys/.auth_tokens.txt");
+        client::save_session(&token, &user_id)?;
+        let data_dir = store::data_dir()?;
 
-        // Format: token,user_id
-        let auth_data = format!("{},{}", token, user_id);
-        std::fs::write(auth_file_path, auth_data)
-            .map_err(|e| anyhow::anyhow!("Failed to save auth token: {}", e))?;
-
-        println!("{}", "[INFO] Login successful. Auth token saved.".green());
-        println!("{}", "[INFO] Save info for auto login? (y/n)".yellow());
+        info!("Login successful. Auth token saved.");
+        ui::show_prompt("Save info for auto login? (y/n)")?;
 
         // Save auto login info
         let mut auto_login_choice = String::new();
@@ -42,61 +38,55 @@ async fn base_login(username: &str, password: &str) -> Result<(String, String),
         auto_login_choice = auto_login_choice.trim().to_string();
         if auto_login_choice.trim().eq_ignore_ascii_case("y") {
             let auto_login_data = format!("{},{}", username, password);
-            let auto_login_fp = home_dir.join(".archerdndsys/.auto_login.txt");
-            std::fs::write(auto_login_fp, auto_login_data)
-                .map_err(|e| anyhow::anyhow!("Failed to save auto login token: {}", e))?;
-            println!("{}", "[INFO] Auto login info saved.".green());
+            let auto_login_fp = data_dir.join(client::AUTO_LOGIN_FILE);
+            atomic::write(auto_login_fp, auto_login_data)
+                .map_err(|e| Error::Storage(format!("Failed to save auto login token: {}", e)))?;
+            info!("Auto login info saved.");
         } else {
-            println!("{}", "[INFO] Auto login info not saved. Try again later.".yellow());
+            info!("Auto login info not saved. Try again later.");
         }
 
         // Return the token and user id
         Ok((token, user_id))
     } else {
+        let status = response.status();
         let error_text = response.text().await?;
-        println!("{}", "[ERROR] Login failed.".red());
-        println!("{} {}", "[ERROR] Response: ".red(), error_text);
-        Err(anyhow::anyhow!("Login failed: {}", error_text))
+        // Any rejection short of a server error means the credentials were not accepted
+        let message = format!("Login failed: {}", error_text);
+        Err(if status.is_server_error() { Error::Network(message) } else { Error::Auth(message) }.into())
     }
 }
 
 pub async fn auto_login() -> Result<(String, String), anyhow::Error> {
     check_setup_cmpl()?;
-    let auth_fp = dirs::home_dir().unwrap().join(".archerdndsys/.auto_login.txt");
+    let auth_fp = store::data_dir()?.join(client::AUTO_LOGIN_FILE);
     if !auth_fp.exists() {
-        return Err(anyhow::anyhow!("No auto login found. Please login manually first."));
+        return Err(Error::Auth("No auto login found. Please login manually first.".to_string()).into());
     }
 
     let auth_data = std::fs::read_to_string(auth_fp)
-        .map_err(|e| anyhow::anyhow!("Failed to read auth token file: {}", e))?;
-
-    if !auth_data.contains(',') {
-        println!("{}", "[ERROR] Invalid auth token format.".red());
-        return Err(anyhow::anyhow!("Invalid auth token format."));
-    }
+        .map_err(|e| Error::Storage(format!("Failed to read auth token file: {}", e)))?;
 
-    let parts: Vec<&str> = auth_data.trim().split(',').collect();
-    if parts.len() != 2 {
-        println!("{}", "[ERROR] Invalid auth token format.".red());
-        return Err(anyhow::anyhow!("Invalid auth token format."));
-    }
+    let Some((username, password)) = client::parse_pair(&auth_data) else {
+        return Err(Error::Auth("Invalid auth token format.".to_string()).into());
+    };
 
-    println!("{}", "[INFO] Auto login found. Logging in...".green());
-    base_login(&parts[0], &parts[1]).await
+    info!("Auto login found. Logging in...");
+    base_login(&username, &password).await
 }
 
 pub async fn manual_login() -> Result<(String, String), anyhow::Error> {
     check_setup_cmpl()?;
-    println!("{}", "[INFO] Please enter your username and password to login.".yellow());
+    eprintln!("{}", logging::styled("[INPUT] Please enter your username and password to login.".yellow()));
 
     let mut username = String::new();
     let mut password = String::new();
 
-    println!("{}", "[INFO] Enter username: ".yellow());
+    ui::show_prompt("Enter username:")?;
     std::io::stdin().read_line(&mut username).expect("Failed to read username");
     username = username.trim().to_string();
 
-    println!("{}", "[INFO] Enter password: ".yellow());
+    ui::show_prompt("Enter password:")?;
     std::io::stdin().read_line(&mut password).expect("Failed to read password");
     password = password.trim().to_string();
 
@@ -108,14 +98,14 @@ pub async fn register() -> Result<(String, String), anyhow::Error> {
     let mut email = String::new();
     let mut password = String::new();
 
-    println!("{}", "[INFO] Please enter your username, email, and password to register.".yellow());
-    print!("{}", "[INFO] Enter username: ".yellow());
+    eprintln!("{}", logging::styled("[INPUT] Please enter your username, email, and password to register.".yellow()));
+    ui::show_prompt("Enter username:")?;
     std::io::stdin().read_line(&mut username).expect("Failed to read username");
     username = username.trim().to_string();
-    print!("{}", "[INFO] Enter email: ".yellow());
+    ui::show_prompt("Enter email:")?;
     std::io::stdin().read_line(&mut email).expect("Failed to read email");
     email = email.trim().to_string();
-    print!("{}", "[INFO] Enter password: ".yellow());
+    ui::show_prompt("Enter password:")?;
     std::io::stdin().read_line(&mut password).expect("Failed to read password");
     password = password.trim().to_string();
 
@@ -133,124 +123,88 @@ pub async fn register() -> Result<(String, String), anyhow::Error> {
         let register_response = response.json::<serde_json::Value>().await?;
 
         let token = register_response["token"].as_str()
-            .ok_or_else(|| anyhow::anyhow!("Token not found in response"))?
+            .ok_or_else(|| Error::Network("Token not found in response".to_string()))?
             .to_string();
 
         let user_id = register_response["id"].as_str()
-            .ok_or_else(|| anyhow::anyhow!("User ID not found in response"))?
+            .ok_or_else(|| Error::Network("User ID not found in response".to_string()))?
             .to_string();
 
         // Save token to file
-        let home_dir = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-        let auth_file_path = home_dir.join(".archerdndsys/.auth_tokens.txt");
-        // Format: token,user_id
-        let auth_data = format!("{},{}", token, user_id);
-        std::fs::write(auth_file_path, auth_data)
-            .map_err(|e| anyhow::anyhow!("Failed to save auth token: {}", e))?;
-        println!("{}", "[INFO] Registration successful. Auth token saved.".green());
+        client::save_session(&token, &user_id)?;
+        let data_dir = store::data_dir()?;
+        info!("Registration successful. Auth token saved.");
 
-        println!("{}", "[INFO] Save info for auto login?".yellow());
+        ui::show_prompt("Save info for auto login? (y/n)")?;
         // Save auto login info
         let mut auto_login_choice = String::new();
         std::io::stdin().read_line(&mut auto_login_choice).expect("Failed to read input");
         auto_login_choice = auto_login_choice.trim().to_string();
         if auto_login_choice.trim().eq_ignore_ascii_case("y") {
             let auto_login_data = format!("{},{}", username, password);
-            let auto_login_fp = home_dir.join(".archerdndsys/.auto_login.txt");
-            std::fs::write(auto_login_fp, auto_login_data)
-                .map_err(|e| anyhow::anyhow!("Failed to save auto login token: {}", e))?;
-            println!("{}", "[INFO] Auto login info saved.".green());
+            let auto_login_fp = data_dir.join(client::AUTO_LOGIN_FILE);
+            atomic::write(auto_login_fp, auto_login_data)
+                .map_err(|e| Error::Storage(format!("Failed to save auto login token: {}", e)))?;
+            info!("Auto login info saved.");
         } else {
-            println!("{}", "[INFO] Auto login info not saved. Try again later.".yellow());
+            info!("Auto login info not saved. Try again later.");
         }
 
         // Return the token and user id
         Ok((token, user_id))
     } else {
+        let status = response.status();
         let error_text = response.text().await?;
-        println!("{}", "[ERROR] Registration failed.".red());
-        println!("{} {}", "[ERROR] Response: ".red(), error_text);
-        Err(anyhow::anyhow!("Registration failed: {}", error_text))
+        Err(Error::from_status(status, format!("Registration failed: {}", error_text)).into())
     }
 }
 
 pub async fn logout() -> Result<(), anyhow::Error> {
     check_setup_cmpl()?;
-    let home_dir = dirs::home_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-    let auth_file_path = home_dir.join(".archerdndsys/.auth_tokens.txt");
+    let auth_file_path = store::data_dir()?.join(client::AUTH_TOKENS_FILE);
 
-    println!("{}", "[INFO] Logging out...".yellow());
+    info!("Logging out...");
     // Send logout request to server
     let client = reqwest::Client::new();
     let response = client.post(format!("{}/auth/logout", SERVER))
         .send()
         .await?;
     if response.status().is_success() {
-        println!("{}", "[INFO] Logout request sent successfully.".green());
+        info!("Logout request sent successfully.");
     } else {
+        let status = response.status();
         let error_text = response.text().await?;
-        println!("{}", "[ERROR] Logout failed.".red());
-        println!("{} {}", "[ERROR] Response: ".red(), error_text);
-        return Err(anyhow::anyhow!("Logout failed: {}", error_text));
+        return Err(Error::from_status(status, format!("Logout failed: {}", error_text)).into());
     }
 
     if auth_file_path.exists() {
         std::fs::remove_file(auth_file_path)
-            .map_err(|e| anyhow::anyhow!("Failed to remove auth token file: {}", e))?;
-        println!("{}", "[INFO] Logout successful. Auth token removed.".green());
+            .map_err(|e| Error::Storage(format!("Failed to remove auth token file: {}", e)))?;
+        info!("Logout successful. Auth token removed.");
     } else {
-        println!("{}", "[INFO] No auth token found. Already logged out.".yellow());
+        info!("No auth token found. Already logged out.");
     }
 
     Ok(())
 }
 
 pub async fn is_signed_in() -> bool {
-    let home_dir = dirs::home_dir().unwrap_or_else(|| {
-        println!("{}", "[ERROR] Could not find home directory.".red());
-        std::process::exit(1);
-    });
-    let auth_file_path = home_dir.join(".archerdndsys/.auth_tokens.txt");
-    if auth_file_path.exists() {
-        match std::fs::read_to_string(auth_file_path) {
-            Ok(data) => {
-                if data.contains(',') {
-                    let parts: Vec<&str> = data.trim().split(',').collect();
-                    if parts.len() == 2 {
-                        let token = parts[0].trim();
-                        let user_id = parts[1].trim();
-                        let client = reqwest::Client::new();
-                        let response = client.get(format!("{}/auth/is-logged-in", SERVER))
-                            .json(&serde_json::json!({
-                                "token": token,
-                                "user_id": user_id
-                            }))
-                            .send()
-                            .await;
-                        if response.unwrap().status().is_success() {
-                            println!("{}", "[INFO] User is signed in.".green());
-                            true
-                        } else {
-                            println!("{}", "[ERROR] User is not signed in.".red());
-                            false
-                        }
-                    } else {
-                        println!("{}", "[ERROR] Invalid auth token format.".red());
-                        false
-                    }
-                } else {
-                    println!("{}", "[ERROR] Invalid auth token format.".red());
-                    false
-                }
-            },
-            Err(e) => {
-                println!("{} {}", "[ERROR] Failed to read auth token file:".red(), e);
-                false
-            }
-        }
+    let Ok((token, user_id)) = client::load_session() else {
+        return false;
+    };
+    let client = reqwest::Client::new();
+    let response = client.get(format!("{}/auth/is-logged-in", SERVER))
+        .json(&serde_json::json!({
+            "token": token,
+            "user_id": user_id
+        }))
+        .send()
+        .await;
+    if response.is_ok_and(|r| r.status().is_success()) {
+        info!("User is signed in.");
+        true
     } else {
+        debug!("User is not signed in.");
         false
     }
 }
@@ -0,0 +1,517 @@
+// Snapshots of ~/.archerdndsys/ as compressed archives in ~/.archerdndsys/backups/
+// Each archive is a .tar.gz holding manifest.json and the backed up files under data/. The manifest records a SHA-256
+// per file so restore can verify the archive before touching anything. Credentials are left out unless asked for
+
+use crate::error::Error;
+use crate::resource::ResourceKind;
+use crate::{atomic, client, lock, logging, migrate, store, ui};
+use chrono::{DateTime, Local};
+use crossterm::style::Stylize;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use log::{error, info};
+
+pub const BACKUP_DIR: &str = "backups";
+const MANIFEST_FILE: &str = "manifest.json";
+const DATA_PREFIX: &str = "data/";
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+const MANIFEST_FORMAT: u32 = 1;
+/// Automatic backups kept per reason; manual backups are never pruned
+pub const AUTO_BACKUP_KEEP: usize = 10;
+
+/// Never backed up: other snapshots, rebuildable indexes and per-process state
+const EXCLUDED: [&str; 7] = [BACKUP_DIR, "migration_backups", "doctor_quarantine", logging::LOG_DIR, lock::LOCK_FILE, "search_index.json", ".request_times.txt"];
+const CREDENTIALS: [&str; 3] = [client::AUTH_TOKENS_FILE, client::AUTO_LOGIN_FILE, ".session_id.txt"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Relative to ~/.archerdndsys/
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub created: DateTime<Local>,
+    pub client_version: String,
+    /// Data directory layout, see migrate.rs
+    pub layout_version: u32,
+    /// "manual", or the operation an automatic backup was taken before, e.g. "pre-sync"
+    pub reason: String,
+    pub label: Option<String>,
+    pub includes_credentials: bool,
+    /// Object files per content type
+    pub objects: BTreeMap<String, usize>,
+    pub files: Vec<ManifestFile>,
+}
+
+fn backup_dir() -> Result<PathBuf, anyhow::Error> {
+    Ok(store::data_dir()?.join(BACKUP_DIR))
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_credential(relative: &str) -> bool {
+    CREDENTIALS.contains(&relative)
+}
+
+/// Files that belong in a backup, relative to the data directory with '/' separators
+fn collect_files(dir: &Path, relative: &str, include_credentials: bool, files: &mut Vec<String>) -> Result<(), anyhow::Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = format!("{}{}", relative, name);
+        if (relative.is_empty() && EXCLUDED.contains(&name.as_str())) || atomic::is_leftover(&name) {
+            continue;
+        }
+        if !include_credentials && is_credential(&path) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", path), include_credentials, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn current_files(include_credentials: bool) -> Result<Vec<String>, anyhow::Error> {
+    let mut files = Vec::new();
+    collect_files(&store::data_dir()?, "", include_credentials, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn object_counts(files: &[String]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for file in files {
+        let mut parts = file.split('/');
+        if let (Some("saved_objs"), Some(kind), Some(name)) = (parts.next(), parts.next(), parts.next()) {
+            if ResourceKind::from_name(kind).is_some() && name.ends_with(".json") {
+                *counts.entry(kind.to_string()).or_default() += 1;
+            }
+        }
+    }
+    counts
+}
+
+fn archive_name(created: &DateTime<Local>, reason: &str, label: Option<&str>) -> String {
+    let mut name = format!("archerdndsys-{}-{}", created.format("%Y%m%d-%H%M%S"), reason);
+    if let Some(label) = label {
+        let slug: String = label.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' }).collect();
+        name.push('-');
+        name.push_str(slug.trim_matches('-'));
+    }
+    name + ARCHIVE_EXTENSION
+}
+
+/// Writes a backup archive and returns its path
+pub fn create(reason: &str, label: Option<&str>, include_credentials: bool) -> Result<PathBuf, anyhow::Error> {
+    let data_dir = store::data_dir()?;
+    if !data_dir.exists() {
+        return Err(Error::Setup(format!("Nothing to back up, {} does not exist.", data_dir.display())).into());
+    }
+    let paths = current_files(include_credentials)?;
+    let mut contents = Vec::new();
+    let mut files = Vec::new();
+    for path in &paths {
+        let bytes = fs::read(data_dir.join(path))
+            .map_err(|e| Error::Storage(format!("Could not read {}: {}", path, e)))?;
+        files.push(ManifestFile { path: path.clone(), bytes: bytes.len() as u64, sha256: sha256(&bytes) });
+        contents.push(bytes);
+    }
+    let manifest = Manifest {
+        format: MANIFEST_FORMAT,
+        created: Local::now(),
+        client_version: env!("CARGO_PKG_VERSION").to_string(),
+        layout_version: migrate::read_version(&data_dir)?,
+        reason: reason.to_string(),
+        label: label.map(|l| l.to_string()),
+        includes_credentials: include_credentials,
+        objects: object_counts(&paths),
+        files,
+    };
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    append(&mut builder, MANIFEST_FILE, &manifest_json)?;
+    for (file, bytes) in manifest.files.iter().zip(&contents) {
+        append(&mut builder, &format!("{}{}", DATA_PREFIX, file.path), bytes)?;
+    }
+    let archive = builder.into_inner()?.finish()?;
+
+    let dir = backup_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(archive_name(&manifest.created, reason, label));
+    atomic::write(&path, archive)?;
+    Ok(path)
+}
+
+fn append(builder: &mut tar::Builder<GzEncoder<Vec<u8>>>, path: &str, bytes: &[u8]) -> Result<(), anyhow::Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(Local::now().timestamp().max(0) as u64);
+    builder.append_data(&mut header, path, bytes)?;
+    Ok(())
+}
+
+/// Takes an automatic backup before a risky operation and prunes older ones taken for the same reason.
+/// Failing to back up stops the operation
+pub fn automatic(reason: &str) -> Result<(), anyhow::Error> {
+    let path = create(reason, None, false)
+        .map_err(|e| Error::Storage(format!("Automatic {} backup failed, nothing was changed: {}", reason, e)))?;
+    info!("Automatic {} backup: {}", reason, path.display());
+    let mut automatic: Vec<(PathBuf, Manifest)> = list_archives()?.into_iter()
+        .filter_map(|(path, manifest)| manifest.ok().map(|m| (path, m)))
+        .filter(|(_, m)| m.reason == reason)
+        .collect();
+    automatic.sort_by_key(|(_, m)| m.created);
+    let excess = automatic.len().saturating_sub(AUTO_BACKUP_KEEP);
+    for (path, _) in automatic.into_iter().take(excess) {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// Reads an archive fully into memory: its manifest and file contents keyed by relative path
+fn read_archive(path: &Path) -> Result<(Manifest, BTreeMap<String, Vec<u8>>), anyhow::Error> {
+    let file = fs::File::open(path).map_err(|e| Error::Storage(format!("Could not open {}: {}", path.display(), e)))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut manifest = None;
+    let mut files = BTreeMap::new();
+    let unreadable = |e: std::io::Error| Error::Storage(format!("{} is not a readable backup archive: {}", path.display(), e));
+    for entry in archive.entries().map_err(unreadable)? {
+        let mut entry = entry.map_err(unreadable)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(unreadable)?;
+        if name == MANIFEST_FILE {
+            manifest = Some(serde_json::from_slice::<Manifest>(&bytes)
+                .map_err(|e| Error::Storage(format!("{} has an unreadable manifest: {}", path.display(), e)))?);
+        } else if let Some(relative) = name.strip_prefix(DATA_PREFIX) {
+            files.insert(relative.to_string(), bytes);
+        }
+    }
+    let manifest = manifest.ok_or_else(|| Error::Storage(format!("{} has no manifest, it is not an archerdndsys backup", path.display())))?;
+    Ok((manifest, files))
+}
+
+/// Only reads the manifest, which is the first entry of every archive
+fn read_manifest(path: &Path) -> Result<Manifest, anyhow::Error> {
+    let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(path)?));
+    let mut entries = archive.entries()?;
+    let mut entry = entries.next().ok_or_else(|| anyhow::anyhow!("empty archive"))??;
+    if entry.path()?.to_string_lossy() != MANIFEST_FILE {
+        return Err(anyhow::anyhow!("no manifest"));
+    }
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Checks that every file in the manifest is present with the recorded checksum, and nothing else is
+fn verify(manifest: &Manifest, files: &BTreeMap<String, Vec<u8>>) -> Vec<String> {
+    let mut problems = Vec::new();
+    if manifest.format > MANIFEST_FORMAT {
+        problems.push(format!("manifest format {} is newer than this archerdndsys understands", manifest.format));
+    }
+    for file in &manifest.files {
+        if file.path.split('/').any(|part| part == ".." || part.is_empty()) {
+            problems.push(format!("{} is not a safe path", file.path));
+            continue;
+        }
+        match files.get(&file.path) {
+            None => problems.push(format!("{} is missing", file.path)),
+            Some(bytes) if sha256(bytes) != file.sha256 => problems.push(format!("{} does not match its checksum", file.path)),
+            Some(_) => {}
+        }
+    }
+    for path in files.keys().filter(|p| !manifest.files.iter().any(|f| &f.path == *p)) {
+        problems.push(format!("{} is not listed in the manifest", path));
+    }
+    problems
+}
+
+/// An archive with its manifest, or why the manifest could not be read
+type Archive = (PathBuf, Result<Manifest, anyhow::Error>);
+
+/// Every archive in the backup directory, oldest first
+fn list_archives() -> Result<Vec<Archive>, anyhow::Error> {
+    let dir = backup_dir()?;
+    let Ok(entries) = fs::read_dir(&dir) else { return Ok(Vec::new()) };
+    let mut archives: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.to_string_lossy().ends_with(ARCHIVE_EXTENSION))
+        .collect();
+    archives.sort();
+    Ok(archives.into_iter().map(|p| {
+        let manifest = read_manifest(&p);
+        (p, manifest)
+    }).collect())
+}
+
+pub fn list() -> Result<(), anyhow::Error> {
+    let archives = list_archives()?;
+    if archives.is_empty() {
+        info!("No backups yet. Create one with `archerdndsys backup create`.");
+        return Ok(());
+    }
+    let file_name = |path: &Path| path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let width = archives.iter().map(|(p, _)| file_name(p).len()).max().unwrap_or(0);
+    println!("{}", logging::bold(format!("{:<width$} {:<17} {:<11} {:>7} {:>10}  {}", "BACKUP", "CREATED", "REASON", "OBJECTS", "SIZE", "NOTES")));
+    for (path, manifest) in archives {
+        let name = file_name(&path);
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        match manifest {
+            Ok(m) => {
+                let mut notes = Vec::new();
+                if let Some(label) = &m.label {
+                    notes.push(label.clone());
+                }
+                if m.includes_credentials {
+                    notes.push("includes credentials".to_string());
+                }
+                notes.push(format!("layout v{}", m.layout_version));
+                println!(
+                    "{:<width$} {:<17} {:<11} {:>7} {:>10}  {}",
+                    name,
+                    m.created.format("%Y-%m-%d %H:%M").to_string(),
+                    m.reason,
+                    m.objects.values().sum::<usize>(),
+                    crate::cache::format_size(size),
+                    notes.join(", ")
+                );
+            }
+            Err(e) => println!("{:<width$} {}", name, logging::styled(format!("unreadable: {}", e).red())),
+        }
+    }
+    Ok(())
+}
+
+/// Finds a backup by path, file name, file name without extension, or "latest"
+fn resolve(query: &str) -> Result<PathBuf, anyhow::Error> {
+    let as_path = PathBuf::from(query);
+    if as_path.is_file() {
+        return Ok(as_path);
+    }
+    let archives = list_archives()?;
+    if query == "latest" {
+        return archives.last().map(|(p, _)| p.clone()).ok_or_else(|| Error::NotFound("There are no backups to restore.".to_string()).into());
+    }
+    archives.into_iter()
+        .map(|(p, _)| p)
+        .find(|p| {
+            let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            name == query || name.trim_end_matches(ARCHIVE_EXTENSION) == query
+        })
+        .ok_or_else(|| Error::NotFound(format!("No backup named '{}'. See `archerdndsys backup list`.", query)).into())
+}
+
+/// Replaces the data set with a verified backup. Files added since the backup are removed, credentials are only
+/// replaced when the backup holds them. A pre-restore backup is taken first so the restore can be undone
+pub fn restore(query: &str, dry_run: bool, yes: bool) -> Result<(), anyhow::Error> {
+    let path = resolve(query)?;
+    let (manifest, files) = read_archive(&path)?;
+    let problems = verify(&manifest, &files);
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        return Err(Error::Storage(format!("{} failed verification, nothing was restored.", path.display())).into());
+    }
+    if manifest.layout_version > migrate::CURRENT_VERSION {
+        return Err(Error::Setup(format!(
+            "The backup uses layout v{}, this archerdndsys only knows up to v{}. Upgrade archerdndsys to restore it.",
+            manifest.layout_version, migrate::CURRENT_VERSION
+        )).into());
+    }
+    info!("Verified {} ({} files, {} objects, taken {})", path.display(), manifest.files.len(), manifest.objects.values().sum::<usize>(), manifest.created.format("%Y-%m-%d %H:%M"));
+
+    let data_dir = store::data_dir()?;
+    let current = if data_dir.exists() { current_files(manifest.includes_credentials)? } else { Vec::new() };
+    let mut added = Vec::new();
+    let mut replaced = Vec::new();
+    for file in &manifest.files {
+        match fs::read(data_dir.join(&file.path)) {
+            Ok(bytes) if sha256(&bytes) == file.sha256 => {}
+            Ok(_) => replaced.push(file.path.as_str()),
+            Err(_) => added.push(file.path.as_str()),
+        }
+    }
+    let removed: Vec<&str> = current.iter().map(|p| p.as_str())
+        .filter(|p| !files.contains_key(*p) && !is_credential(p))
+        .collect();
+
+    for (label, paths) in [("Restore", &added), ("Replace", &replaced), ("Remove", &removed)] {
+        for path in paths.iter() {
+            println!("  {} {}", label, path);
+        }
+    }
+    info!("{} to restore, {} to replace, {} to remove", added.len(), replaced.len(), removed.len());
+    if dry_run {
+        info!("Dry run, nothing was changed.");
+        return Ok(());
+    }
+    if added.is_empty() && replaced.is_empty() && removed.is_empty() {
+        info!("The data directory already matches the backup.");
+        return Ok(());
+    }
+    if !yes {
+        ui::show_prompt("Restore this backup over the current data? (y/n)")?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            info!("Restore cancelled.");
+            return Ok(());
+        }
+    }
+
+    if data_dir.exists() {
+        automatic("pre-restore")?;
+    }
+    for path in added.iter().chain(&replaced) {
+        let target = data_dir.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic::write(&target, &files[*path])?;
+    }
+    for path in &removed {
+        fs::remove_file(data_dir.join(path))?;
+    }
+    // Archives only hold files, so empty content type directories and left out credentials come back empty
+    for kind in ResourceKind::ALL {
+        fs::create_dir_all(kind.dir()?)?;
+    }
+    for credential in CREDENTIALS {
+        let path = data_dir.join(credential);
+        if !path.exists() {
+            fs::File::create(path)?;
+        }
+    }
+    info!("Restored {}", path.display());
+    // Backups from an older release come back in their old layout
+    migrate::on_startup()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{with_setup, write_object};
+    use serde_json::json;
+
+    fn manifest(files: &[(&str, &[u8])]) -> (Manifest, BTreeMap<String, Vec<u8>>) {
+        let manifest = Manifest {
+            format: MANIFEST_FORMAT,
+            created: Local::now(),
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            layout_version: migrate::CURRENT_VERSION,
+            reason: "manual".to_string(),
+            label: None,
+            includes_credentials: false,
+            objects: BTreeMap::new(),
+            files: files.iter().map(|(path, bytes)| ManifestFile { path: path.to_string(), bytes: bytes.len() as u64, sha256: sha256(bytes) }).collect(),
+        };
+        let contents = files.iter().map(|(path, bytes)| (path.to_string(), bytes.to_vec())).collect();
+        (manifest, contents)
+    }
+
+    #[test]
+    fn verify_accepts_a_matching_archive() {
+        let (manifest, files) = manifest(&[("saved_objs/Spells/1.json", b"{}")]);
+        assert!(verify(&manifest, &files).is_empty());
+    }
+
+    #[test]
+    fn verify_rejects_checksum_mismatch() {
+        let (manifest, mut files) = manifest(&[("saved_objs/Spells/1.json", b"{}")]);
+        files.insert("saved_objs/Spells/1.json".to_string(), b"{\"name\":\"changed\"}".to_vec());
+        assert_eq!(verify(&manifest, &files), ["saved_objs/Spells/1.json does not match its checksum"]);
+    }
+
+    #[test]
+    fn verify_rejects_unsafe_paths() {
+        for path in ["../outside.json", "saved_objs/../../outside.json", "/etc/passwd"] {
+            let (manifest, files) = manifest(&[(path, b"x")]);
+            assert_eq!(verify(&manifest, &files), [format!("{} is not a safe path", path)]);
+        }
+    }
+
+    #[test]
+    fn verify_rejects_files_missing_from_the_manifest() {
+        let (manifest, mut files) = manifest(&[("saved_objs/Spells/1.json", b"{}")]);
+        files.insert("saved_objs/Spells/2.json".to_string(), b"{}".to_vec());
+        assert_eq!(verify(&manifest, &files), ["saved_objs/Spells/2.json is not listed in the manifest"]);
+    }
+
+    #[test]
+    fn credentials_are_left_out_unless_asked_for() {
+        with_setup(|data_dir| {
+            fs::write(data_dir.join(client::AUTH_TOKENS_FILE), "token,user").unwrap();
+
+            let (manifest, files) = read_archive(&create("manual", Some("without"), false).unwrap()).unwrap();
+            assert!(!manifest.includes_credentials);
+            assert!(CREDENTIALS.iter().all(|c| !files.contains_key(*c)));
+
+            let (manifest, files) = read_archive(&create("manual", Some("with"), true).unwrap()).unwrap();
+            assert!(manifest.includes_credentials);
+            assert_eq!(files[client::AUTH_TOKENS_FILE], b"token,user");
+        });
+    }
+
+    #[test]
+    fn restore_round_trip() {
+        with_setup(|data_dir| {
+            let edited = write_object("Spells", "1", json!({ "name": "Homebrew", "local_id": "1" }));
+            let deleted = write_object("Items", "_a1", json!({ "name": "Synced" }));
+            let archive = create("manual", None, false).unwrap();
+
+            fs::write(&edited, json!({ "name": "Changed", "local_id": "1" }).to_string()).unwrap();
+            fs::remove_file(&deleted).unwrap();
+            let added = write_object("Spells", "2", json!({ "name": "New", "local_id": "2" }));
+
+            restore(&archive.to_string_lossy(), false, true).unwrap();
+
+            assert_eq!(fs::read_to_string(&edited).unwrap(), json!({ "name": "Homebrew", "local_id": "1" }).to_string());
+            assert!(deleted.exists());
+            assert!(!added.exists());
+            // The pre-restore snapshot keeps what the restore replaced
+            let snapshots = fs::read_dir(data_dir.join(BACKUP_DIR)).unwrap().count();
+            assert_eq!(snapshots, 2);
+        });
+    }
+
+    #[test]
+    fn restore_never_removes_credentials() {
+        with_setup(|data_dir| {
+            let session = data_dir.join(".session_id.txt");
+            fs::remove_file(&session).unwrap();
+            let archive = create("manual", None, true).unwrap();
+
+            fs::write(&session, "session").unwrap();
+            fs::write(data_dir.join(client::AUTH_TOKENS_FILE), "token,user").unwrap();
+            write_object("Spells", "1", json!({ "name": "New", "local_id": "1" }));
+
+            restore(&archive.to_string_lossy(), false, true).unwrap();
+
+            assert_eq!(fs::read_to_string(&session).unwrap(), "session");
+            // Held by the backup, so it is replaced rather than removed
+            assert_eq!(fs::read_to_string(data_dir.join(client::AUTH_TOKENS_FILE)).unwrap(), "");
+        });
+    }
+}
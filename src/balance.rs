@@ -0,0 +1,231 @@
+// Balance analysis for homebrew Spells and Items against the cached reference content
+// Everything here works on the local saved_objs cache only
+
+use crate::dice::DiceExpr;
+use crate::error::{self, Error, ErrorKind};
+use crate::models::{Item, Spell};
+use crate::store::{self, StoredObject};
+use log::{info, warn};
+
+/// A metric is flagged when it sits this many standard deviations above the reference mean
+const OUTLIER_STDDEVS: f64 = 1.5;
+/// Fewer reference objects than this and the comparison is reported as unreliable
+const MIN_REFERENCES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub metric: Metric,
+    pub references: usize,
+    pub mean: f64,
+    pub max: f64,
+    pub outlier: Option<String>,
+}
+
+/// First "XdY(+Z)" found in free text
+fn find_dice(text: &str) -> Option<DiceExpr> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')' || c == '.')
+        .filter(|w| w.contains('d'))
+        .find_map(|w| DiceExpr::parse(w).filter(|d| d.count > 0))
+}
+
+/// First number directly followed by "foot"/"feet", e.g. "20-foot radius" or "150 feet"
+fn find_feet(text: &str) -> Option<f64> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    for (i, word) in words.iter().enumerate() {
+        let lower = word.to_lowercase();
+        if let Some(n) = lower.strip_suffix("-foot").or_else(|| lower.strip_suffix("-feet")) {
+            if let Ok(n) = n.replace(',', "").parse() {
+                return Some(n);
+            }
+        }
+        if let Some(next) = words.get(i + 1) {
+            if next.to_lowercase().starts_with("feet") || next.to_lowercase().starts_with("foot") {
+                if let Ok(n) = lower.replace(',', "").parse() {
+                    return Some(n);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn spell_damage(spell: &Spell) -> Option<f64> {
+    spell.damage.as_ref()
+        .and_then(|d| DiceExpr::parse(&d.dice))
+        .or_else(|| find_dice(&spell.description))
+        .map(|d| d.average())
+}
+
+fn spell_range(spell: &Spell) -> Option<f64> {
+    let range = spell.range.to_lowercase();
+    if range.starts_with("touch") {
+        return Some(5.0);
+    }
+    if range.starts_with("self") && !range.contains("foot") && !range.contains("feet") {
+        return Some(0.0);
+    }
+    if range.contains("mile") {
+        return Some(5280.0);
+    }
+    find_feet(&range)
+}
+
+/// Relative value of the casting time, higher is cheaper for the caster
+fn action_economy(casting_time: &str) -> Option<f64> {
+    let casting_time = casting_time.to_lowercase();
+    if casting_time.contains("bonus action") {
+        Some(1.5)
+    } else if casting_time.contains("reaction") {
+        Some(1.25)
+    } else if casting_time.contains("action") {
+        Some(1.0)
+    } else if casting_time.contains("minute") || casting_time.contains("hour") {
+        Some(0.5)
+    } else {
+        None
+    }
+}
+
+pub fn spell_metrics(spell: &Spell) -> Vec<Metric> {
+    let mut metrics = Vec::new();
+    if let Some(damage) = spell_damage(spell) {
+        metrics.push(Metric { name: "average damage", value: damage, unit: "hp" });
+        // Cantrips are compared per cast, leveled spells per slot level spent
+        let per_slot = damage / spell.level.max(1) as f64;
+        metrics.push(Metric { name: "damage per slot level", value: per_slot, unit: "hp" });
+    }
+    if let Some(area) = spell.area.as_deref().and_then(find_feet).or_else(|| find_feet(&spell.description).filter(|_| {
+        let d = spell.description.to_lowercase();
+        d.contains("radius") || d.contains("cone") || d.contains("cube") || d.contains("line") || d.contains("sphere")
+    })) {
+        metrics.push(Metric { name: "area size", value: area, unit: "ft" });
+    }
+    if let Some(range) = spell_range(spell) {
+        metrics.push(Metric { name: "range", value: range, unit: "ft" });
+    }
+    if let Some(economy) = action_economy(&spell.casting_time) {
+        metrics.push(Metric { name: "action economy", value: economy, unit: "x" });
+    }
+    metrics
+}
+
+pub fn item_metrics(item: &Item) -> Vec<Metric> {
+    let mut metrics = Vec::new();
+    if let Some(bonus) = item.bonus {
+        metrics.push(Metric { name: "bonus", value: bonus as f64, unit: "+" });
+    }
+    if let Some(damage) = item.damage.as_ref().and_then(|d| DiceExpr::parse(&d.dice)).or_else(|| find_dice(&item.description)) {
+        metrics.push(Metric { name: "average damage", value: damage.average(), unit: "hp" });
+    }
+    if let Some(charges) = item.charges {
+        metrics.push(Metric { name: "charges", value: charges as f64, unit: "" });
+    }
+    // Items without attunement are effectively stronger since they do not take up a slot
+    metrics.push(Metric { name: "attunement free", value: if item.requires_attunement { 0.0 } else { 1.0 }, unit: "" });
+    metrics
+}
+
+fn compare(target: &[Metric], references: &[Vec<Metric>]) -> Vec<Comparison> {
+    target.iter().map(|metric| {
+        let values: Vec<f64> = references.iter()
+            .filter_map(|r| r.iter().find(|m| m.name == metric.name).map(|m| m.value))
+            .collect();
+        let n = values.len();
+        let mean = if n > 0 { values.iter().sum::<f64>() / n as f64 } else { 0.0 };
+        let max = values.iter().cloned().fold(f64::MIN, f64::max);
+        let stddev = if n > 1 {
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        let outlier = if n < MIN_REFERENCES {
+            None
+        } else if metric.value > max {
+            Some(format!("{} {:.1}{} is higher than every reference (max {:.1}{}, mean {:.1}{})", metric.name, metric.value, metric.unit, max, metric.unit, mean, metric.unit))
+        } else if stddev > 0.0 && metric.value > mean + OUTLIER_STDDEVS * stddev {
+            Some(format!("{} {:.1}{} is {:.1} standard deviations above the reference mean of {:.1}{}", metric.name, metric.value, metric.unit, (metric.value - mean) / stddev, mean, metric.unit))
+        } else {
+            None
+        };
+
+        Comparison { metric: metric.clone(), references: n, mean, max: if n > 0 { max } else { 0.0 }, outlier }
+    }).collect()
+}
+
+/// Compares a Spell against cached spells of the same level
+pub fn analyze_spell(target: &StoredObject) -> Result<Vec<Comparison>, anyhow::Error> {
+    let spell: Spell = target.parse()?;
+    let references: Vec<Vec<Metric>> = store::load_all("Spells")?
+        .iter()
+        .filter(|obj| obj.id != target.id)
+        .filter_map(|obj| obj.parse::<Spell>().ok())
+        .filter(|s| s.level == spell.level)
+        .map(|s| spell_metrics(&s))
+        .collect();
+    Ok(compare(&spell_metrics(&spell), &references))
+}
+
+/// Compares an Item against cached items of the same rarity
+pub fn analyze_item(target: &StoredObject) -> Result<Vec<Comparison>, anyhow::Error> {
+    let item: Item = target.parse()?;
+    let references: Vec<Vec<Metric>> = store::load_all("Items")?
+        .iter()
+        .filter(|obj| obj.id != target.id)
+        .filter_map(|obj| obj.parse::<Item>().ok())
+        .filter(|i| i.rarity.eq_ignore_ascii_case(&item.rarity))
+        .map(|i| item_metrics(&i))
+        .collect();
+    Ok(compare(&item_metrics(&item), &references))
+}
+
+/// Looks the target up among Spells, then Items, and prints the comparison report
+pub fn analyze(query: &str, kind: Option<&str>) -> Result<(), anyhow::Error> {
+    let target = match kind {
+        Some(kind) => store::find(kind, query)?,
+        // Only a missing spell falls through to Items; an ambiguous name must not analyse something else
+        None => match store::find("Spells", query) {
+            Err(e) if error::classify(&e) == ErrorKind::NotFound => store::find("Items", query)?,
+            found => found?,
+        },
+    };
+    let comparisons = match target.kind.as_str() {
+        "Spells" => analyze_spell(&target)?,
+        "Items" => analyze_item(&target)?,
+        other => return Err(Error::Usage(format!("Balance analysis supports Spells and Items, not {}", other)).into()),
+    };
+
+    info!("Balance report for {} ({}/{})", target.name(), target.kind, target.id);
+    if comparisons.is_empty() {
+        info!("No measurable metrics found (damage, area, range, casting time, bonus...).");
+        return Ok(());
+    }
+
+    for c in &comparisons {
+        println!(
+            "  {:<22} {:>8.1}{:<3} reference mean {:>7.1} max {:>7.1} (n={})",
+            c.metric.name, c.metric.value, c.metric.unit, c.mean, c.max, c.references
+        );
+    }
+
+    let outliers: Vec<&String> = comparisons.iter().filter_map(|c| c.outlier.as_ref()).collect();
+    if comparisons.iter().all(|c| c.references < MIN_REFERENCES) {
+        warn!("Fewer than {} comparable objects in the cache, results are not reliable.", MIN_REFERENCES);
+    }
+    if outliers.is_empty() {
+        info!("No outliers found.");
+    } else {
+        for outlier in outliers {
+            warn!("Possibly overpowered: {}", outlier);
+        }
+    }
+
+    Ok(())
+}
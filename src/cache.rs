@@ -0,0 +1,492 @@
+// Size reporting and eviction of cache entries in saved_objs/
+// Only `_(server id).json` objects without pending journal operations are ever removed, since they can be fetched again.
+// Journals, local objects and reference content are never touched. Last access is tracked in cache_index.json,
+// not filesystem atime, which many systems do not update
+
+use crate::error::Error;
+use crate::resource::ResourceKind;
+use crate::store::{self, StoredObject};
+use crate::{atomic, listing, logging};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{info, warn};
+
+const INDEX_FILE: &str = "cache_index.json";
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AccessIndex {
+    /// "Kind/_id" -> last access in ms since the epoch
+    entries: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub kind: ResourceKind,
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// ms since the epoch, from the access index or the file's modification time, whichever is later
+    pub last_access: u64,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn index_path() -> Result<PathBuf, anyhow::Error> {
+    Ok(store::data_dir()?.join(INDEX_FILE))
+}
+
+fn index_key(kind: &str, id: &str) -> String {
+    format!("{}/{}", kind, id)
+}
+
+fn load_index() -> Result<AccessIndex, anyhow::Error> {
+    // A missing or damaged index only loses access times, modification times stand in for them
+    Ok(fs::read_to_string(index_path()?).ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default())
+}
+
+fn save_index(index: &AccessIndex) -> Result<(), anyhow::Error> {
+    atomic::write(index_path()?, serde_json::to_string(index)?)?;
+    Ok(())
+}
+
+/// Records that a synced object was used. Failures are ignored, the access index is only a hint for eviction
+pub fn touch(obj: &StoredObject) {
+    if !obj.is_synced() {
+        return;
+    }
+    let Ok(mut index) = load_index() else { return };
+    index.entries.insert(index_key(&obj.kind, &obj.id), now_ms());
+    let _ = save_index(&index);
+}
+
+/// Parses sizes such as 50MB, 1.5G or 4096 (bytes); units are powers of 1024
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("'{}' is not a size, e.g. 50MB", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        other => return Err(format!("unknown size unit '{}', use B, KB, MB or GB", other)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn dir_size(path: &Path) -> Result<u64, anyhow::Error> {
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() { dir_size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(total)
+}
+
+/// Total size of saved_objs/, evictable or not
+pub fn saved_objs_size() -> Result<u64, anyhow::Error> {
+    let dir = store::data_dir()?.join("saved_objs");
+    if !dir.exists() {
+        return Err(Error::Setup(format!("Saved objects directory does not exist: {}", dir.display())).into());
+    }
+    dir_size(&dir)
+}
+
+/// Synced objects that are safe to remove, least recently used first
+pub fn evictable() -> Result<Vec<CacheEntry>, anyhow::Error> {
+    let index = load_index()?;
+    let mut entries = Vec::new();
+    for kind in ResourceKind::ALL {
+        let dir = kind.dir()?;
+        let Ok(files) = fs::read_dir(&dir) else { continue };
+        // Synced objects with journaled edits hold the only copy of those edits
+        let pending = listing::pending_ops(kind.name())?;
+        for file in files {
+            let path = file?.path();
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string()) else { continue };
+            if path.extension().and_then(|e| e.to_str()) != Some("json") || !id.starts_with('_') || pending.contains_key(&id) {
+                continue;
+            }
+            let Some(data) = fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok()) else { continue };
+            if data.get("read_only").and_then(|r| r.as_bool()).unwrap_or(false) {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            let modified = metadata.modified().ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            let accessed = index.entries.get(&index_key(kind.name(), &id)).copied().unwrap_or(0);
+            entries.push(CacheEntry {
+                kind,
+                name: data.get("name").and_then(|n| n.as_str()).unwrap_or("[unnamed]").to_string(),
+                id,
+                path,
+                size: metadata.len(),
+                last_access: accessed.max(modified),
+            });
+        }
+    }
+    entries.sort_by_key(|e| e.last_access);
+    Ok(entries)
+}
+
+/// Evicts synced entries not used in `older_than_days` (0 evicts all of them), then least recently used entries until
+/// saved_objs/ fits in `max_size` bytes
+pub fn evict(older_than_days: Option<u64>, max_size: Option<u64>, dry_run: bool) -> Result<(), anyhow::Error> {
+    let candidates = evictable()?;
+    let total = saved_objs_size()?;
+    let now = now_ms();
+
+    let mut chosen: Vec<&CacheEntry> = Vec::new();
+    if let Some(days) = older_than_days {
+        let cutoff = now.saturating_sub(days.saturating_mul(DAY_MS));
+        chosen.extend(candidates.iter().filter(|e| days == 0 || e.last_access < cutoff));
+    }
+    if let Some(limit) = max_size {
+        let mut remaining = total - chosen.iter().map(|e| e.size).sum::<u64>();
+        for entry in &candidates {
+            if remaining <= limit {
+                break;
+            }
+            if !chosen.iter().any(|c| c.path == entry.path) {
+                remaining -= entry.size;
+                chosen.push(entry);
+            }
+        }
+        if remaining > limit {
+            warn!("Only {} can be freed: the remaining {} are journals, local objects, pending edits or reference content.", format_size(total - remaining), format_size(remaining));
+        }
+    }
+
+    let verb = if dry_run { "Would evict" } else { "Evicted" };
+    let mut freed = 0;
+    let mut index = load_index()?;
+    for entry in &chosen {
+        if !dry_run {
+            fs::remove_file(&entry.path)
+                .map_err(|e| Error::Storage(format!("Could not remove {}: {}", entry.path.display(), e)))?;
+            index.entries.remove(&index_key(entry.kind.name(), &entry.id));
+        }
+        freed += entry.size;
+        info!("{} {}/{} ({}), {}", verb, entry.kind.name(), entry.id, entry.name, format_size(entry.size));
+    }
+    if !dry_run {
+        // Drop access times of files that are gone
+        index.entries.retain(|key, _| {
+            key.split_once('/')
+                .and_then(|(kind, id)| store::kind_dir(kind).ok().map(|dir| dir.join(format!("{}.json", id))))
+                .is_some_and(|path| path.exists())
+        });
+        save_index(&index)?;
+    }
+
+    info!(
+        "{} {} cache entries, freeing {}, cache is {} (journals, local objects, pending edits and reference content are always kept)",
+        verb,
+        chosen.len(),
+        format_size(freed),
+        format_size(total - freed)
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// `_(server id).json`, can be evicted and fetched again
+    Synced,
+    /// Created locally and not yet pushed
+    Local,
+    /// Read-only reference content such as the SRD
+    Reference,
+    Journal,
+    /// Anything else, e.g. stray or leftover temporary files
+    Other,
+}
+
+impl Category {
+    const ALL: [Category; 5] = [Category::Synced, Category::Local, Category::Reference, Category::Journal, Category::Other];
+
+    fn label(self) -> &'static str {
+        match self {
+            Category::Synced => "synced",
+            Category::Local => "local",
+            Category::Reference => "reference",
+            Category::Journal => "journal",
+            Category::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Usage {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl Usage {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeUsage {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Synced, local and reference objects
+    pub objects: u64,
+    pub bytes: u64,
+    pub categories: BTreeMap<Category, Usage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileUsage {
+    pub path: String,
+    pub name: Option<String>,
+    pub category: Option<Category>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheReport {
+    /// Everything under ~/.archerdndsys/
+    pub total_bytes: u64,
+    pub saved_objs_bytes: u64,
+    /// What `--clear-cache 0` would free
+    pub evictable_bytes: u64,
+    pub types: Vec<TypeUsage>,
+    pub largest: Vec<FileUsage>,
+    /// Indexes, backups and other files outside saved_objs/
+    pub other: Vec<FileUsage>,
+}
+
+const LARGEST_SHOWN: usize = 5;
+
+fn categorize(path: &Path, journal: &Path) -> (Category, Option<String>) {
+    if path == journal {
+        return (Category::Journal, None);
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let data = (path.extension().and_then(|e| e.to_str()) == Some("json"))
+        .then(|| fs::read_to_string(path).ok().and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok()))
+        .flatten();
+    let Some(data) = data else { return (Category::Other, None) };
+    let name = data.get("name").and_then(|n| n.as_str()).map(|n| n.to_string());
+    let category = if data.get("read_only").and_then(|r| r.as_bool()).unwrap_or(false) {
+        Category::Reference
+    } else if stem.starts_with('_') {
+        Category::Synced
+    } else if stem.parse::<u64>().is_ok() {
+        Category::Local
+    } else {
+        Category::Other
+    };
+    (category, name)
+}
+
+/// Sizes of the data directory by content type and category. `files` collects every object file for --verbose
+pub fn usage(files: &mut Vec<FileUsage>) -> Result<CacheReport, anyhow::Error> {
+    let data_dir = store::data_dir()?;
+    let saved_objs_bytes = saved_objs_size()?;
+    let relative = |path: &Path| path.strip_prefix(&data_dir).unwrap_or(path).display().to_string();
+
+    let mut types = Vec::new();
+    for kind in ResourceKind::ALL {
+        let dir = kind.dir()?;
+        let journal = kind.journal_path()?;
+        let mut categories: BTreeMap<Category, Usage> = Category::ALL.iter().map(|c| (*c, Usage::default())).collect();
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries {
+                let entry = entry?;
+                let path = entry.path();
+                let bytes = if path.is_dir() { dir_size(&path)? } else { entry.metadata()?.len() };
+                let (category, name) = categorize(&path, &journal);
+                if let Some(usage) = categories.get_mut(&category) {
+                    usage.add(bytes);
+                }
+                files.push(FileUsage { path: relative(&path), name, category: Some(category), bytes });
+            }
+        }
+        let objects = [Category::Synced, Category::Local, Category::Reference].iter().map(|c| categories[c].files).sum();
+        let bytes = categories.values().map(|u| u.bytes).sum();
+        types.push(TypeUsage { kind: kind.name(), objects, bytes, categories });
+    }
+
+    let mut largest: Vec<FileUsage> = files.iter()
+        .filter(|f| matches!(f.category, Some(Category::Synced | Category::Local | Category::Reference)))
+        .cloned()
+        .collect();
+    largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    largest.truncate(LARGEST_SHOWN);
+
+    let mut other = Vec::new();
+    let mut total_bytes = 0;
+    for entry in fs::read_dir(&data_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let bytes = if path.is_dir() { dir_size(&path)? } else { entry.metadata()?.len() };
+        total_bytes += bytes;
+        if entry.file_name() != "saved_objs" {
+            other.push(FileUsage { path: relative(&path), name: None, category: None, bytes });
+        }
+    }
+    other.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let evictable_bytes = evictable()?.iter().map(|e| e.size).sum();
+    Ok(CacheReport { total_bytes, saved_objs_bytes, evictable_bytes, types, largest, other })
+}
+
+/// Prints the size breakdown as a table, or as JSON. `verbose` adds every object file
+pub fn print_usage(json: bool, verbose: bool) -> Result<(), anyhow::Error> {
+    let mut files = Vec::new();
+    let report = usage(&mut files)?;
+    if json {
+        let mut value = serde_json::to_value(&report)?;
+        if verbose {
+            value["files"] = serde_json::to_value(&files)?;
+        }
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    if verbose {
+        for file in &files {
+            let category = file.category.map(|c| c.label()).unwrap_or_default();
+            println!("  {:<56} {:<10} {:>10}", file.path, category, format_size(file.bytes));
+        }
+        println!();
+    }
+
+    let header = format!("{:<12} {:>7}", "TYPE", "OBJECTS");
+    let header = Category::ALL.iter().fold(header, |h, c| format!("{} {:>10}", h, c.label().to_uppercase()));
+    println!("{}", logging::bold(format!("{} {:>10}", header, "TOTAL")));
+    for usage in report.types.iter().filter(|t| t.bytes > 0) {
+        let row = format!("{:<12} {:>7}", usage.kind, usage.objects);
+        let row = Category::ALL.iter().fold(row, |r, c| format!("{} {:>10}", r, format_size(usage.categories[c].bytes)));
+        println!("{} {:>10}", row, format_size(usage.bytes));
+    }
+    let objects: u64 = report.types.iter().map(|t| t.objects).sum();
+    let row = format!("{:<12} {:>7}", "Total", objects);
+    let row = Category::ALL.iter().fold(row, |r, c| {
+        format!("{} {:>10}", r, format_size(report.types.iter().map(|t| t.categories[c].bytes).sum()))
+    });
+    println!("{}", logging::bold(format!("{} {:>10}", row, format_size(report.saved_objs_bytes))));
+
+    if !report.largest.is_empty() {
+        println!("\nLargest objects:");
+        for file in &report.largest {
+            println!("  {:<48} {:<28} {:>10}", file.path, file.name.as_deref().unwrap_or("[unnamed]"), format_size(file.bytes));
+        }
+    }
+    if !report.other.is_empty() {
+        println!("\nOutside saved_objs/:");
+        for file in &report.other {
+            println!("  {:<48} {:>10}", file.path, format_size(file.bytes));
+        }
+    }
+    println!();
+    info!("Data directory total: {}", format_size(report.total_bytes));
+    info!("Evictable with `--clear-cache 0`: {}", format_size(report.evictable_bytes));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{with_setup, write_object};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn set_modified(path: &Path, secs_ago: u64) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(secs_ago)).unwrap();
+    }
+
+    #[test]
+    fn evicting_everything_keeps_unsynced_data() {
+        with_setup(|_| {
+            let synced = write_object("Spells", "_a1", json!({ "name": "Synced" }));
+            let pending = write_object("Spells", "_b2", json!({ "name": "Edited" }));
+            let local = write_object("Spells", "1", json!({ "name": "Homebrew", "local_id": "1" }));
+            let reference = write_object("Spells", "srd_fireball", json!({ "name": "Fireball", "read_only": true }));
+            let synced_reference = write_object("Spells", "_c3", json!({ "name": "Shared", "read_only": true }));
+            let journal = store::journal_path("Spells").unwrap();
+            fs::write(&journal, "PUT /spells/b2 {\"name\":\"Edited\"}\n").unwrap();
+
+            evict(Some(0), None, false).unwrap();
+
+            assert!(!synced.exists());
+            for kept in [&pending, &local, &reference, &synced_reference] {
+                assert!(kept.exists(), "{} was evicted", kept.display());
+            }
+            assert_eq!(fs::read_to_string(&journal).unwrap(), "PUT /spells/b2 {\"name\":\"Edited\"}\n");
+        });
+    }
+
+    #[test]
+    fn max_size_evicts_least_recently_used_first() {
+        with_setup(|_| {
+            let oldest = write_object("Items", "_old", json!({ "name": "Old" }));
+            let middle = write_object("Items", "_mid", json!({ "name": "Middle" }));
+            let newest = write_object("Items", "_new", json!({ "name": "New" }));
+            set_modified(&oldest, 3 * 86_400);
+            set_modified(&middle, 2 * 86_400);
+            set_modified(&newest, 86_400);
+
+            let order: Vec<String> = evictable().unwrap().into_iter().map(|e| e.id).collect();
+            assert_eq!(order, ["_old", "_mid", "_new"]);
+
+            let limit = saved_objs_size().unwrap() - fs::metadata(&oldest).unwrap().len();
+            evict(None, Some(limit), false).unwrap();
+
+            assert!(!oldest.exists());
+            assert!(middle.exists() && newest.exists());
+        });
+    }
+
+    #[test]
+    fn access_index_outranks_modification_time() {
+        with_setup(|_| {
+            let used = write_object("Items", "_used", json!({ "name": "Used" }));
+            let unused = write_object("Items", "_unused", json!({ "name": "Unused" }));
+            set_modified(&used, 3 * 86_400);
+            set_modified(&unused, 2 * 86_400);
+            touch(&store::find("Items", "_used").unwrap());
+
+            evict(Some(1), None, false).unwrap();
+
+            assert!(used.exists());
+            assert!(!unused.exists());
+        });
+    }
+}
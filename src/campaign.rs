@@ -0,0 +1,196 @@
+// Campaign commands, sent straight to the server through the rate-limited direct request layer
+// The server's copy is authoritative; saved_objs/Campaigns/ only caches the last response per campaign
+
+use crate::client::{self, direct_request};
+use crate::error::Error;
+use crate::models::{Campaign, CampaignRole};
+use crate::store::{self, StoredObject};
+use reqwest::Method;
+use serde_json::{json, Value};
+use url::Url;
+use log::{info, warn};
+use crate::logging;
+
+const KIND: &str = "Campaigns";
+
+fn server_id(obj: &StoredObject) -> &str {
+    obj.id.trim_start_matches('_')
+}
+
+/// Caches a campaign returned by the server and returns it
+fn cache(data: Value) -> Result<StoredObject, anyhow::Error> {
+    let id = data.get("_id").or(data.get("id")).and_then(|id| id.as_str())
+        .ok_or_else(|| Error::Network("Server response has no campaign ID".to_string()))?
+        .to_string();
+    store::save_synced(KIND, &id, data)
+}
+
+fn find(query: &str) -> Result<StoredObject, anyhow::Error> {
+    store::find(KIND, query).map_err(|e| Error::NotFound(format!("{} (run `archerdndsys campaign list` to refresh the cache)", e)).into())
+}
+
+/// Fails unless the logged in user holds `role` in the cached campaign
+fn require_role(obj: &StoredObject, role: CampaignRole, action: &str) -> Result<Campaign, anyhow::Error> {
+    let campaign: Campaign = obj.parse()?;
+    let (_, user) = client::load_session()?;
+    match campaign.role_of(&user) {
+        Some(r) if r == role || r == CampaignRole::Dm => Ok(campaign),
+        Some(_) => Err(Error::Auth(format!("Only the DM of {} can {}", campaign.name, action)).into()),
+        None => Err(Error::Auth(format!("You are not a member of {}", campaign.name)).into()),
+    }
+}
+
+/// Resolves a character to its server ID; only synced characters can be attached
+fn character_id(query: &str) -> Result<String, anyhow::Error> {
+    let character = store::find("Characters", query)?;
+    if !character.is_synced() {
+        return Err(Error::Conflict(format!("{} has not been pushed yet. Run `archerdndsys --push-load` first.", character.name())).into());
+    }
+    Ok(character.id.trim_start_matches('_').to_string())
+}
+
+fn print_summary(obj: &StoredObject) -> Result<(), anyhow::Error> {
+    let campaign: Campaign = obj.parse()?;
+    println!("{} ({})", logging::bold(&campaign.name), obj.id);
+    if !campaign.description.is_empty() {
+        println!("  {}", campaign.description);
+    }
+    if let Some(code) = &campaign.invite_code {
+        println!("  Invite code: {}", code);
+    }
+    for member in &campaign.members {
+        let role = match member.role {
+            CampaignRole::Dm => "DM",
+            CampaignRole::Player => "player",
+        };
+        println!("  - {} [{}]", member.username.as_deref().unwrap_or(&member.user), role);
+    }
+    if !campaign.characters.is_empty() {
+        let names: Vec<String> = campaign.characters.iter()
+            .map(|id| store::find("Characters", id).map(|c| c.name().to_string()).unwrap_or_else(|_| id.clone()))
+            .collect();
+        println!("  Characters: {}", names.join(", "));
+    }
+    if !campaign.collections.is_empty() {
+        println!("  Collections: {}", campaign.collections.join(", "));
+    }
+    println!("  Notes: {}", campaign.notes.len());
+    Ok(())
+}
+
+pub async fn create(name: &str, description: Option<&str>) -> Result<(), anyhow::Error> {
+    let body = json!({ "name": name, "description": description.unwrap_or_default() });
+    let obj = cache(direct_request(Method::POST, "campaigns", Some(&body)).await?)?;
+    info!("Campaign created, you are its DM: {}", obj.name());
+    print_summary(&obj)
+}
+
+pub async fn join(invite_code: &str) -> Result<(), anyhow::Error> {
+    let body = json!({ "invite_code": invite_code });
+    let obj = cache(direct_request(Method::POST, "campaigns/join", Some(&body)).await?)?;
+    info!("Joined campaign {}", obj.name());
+    print_summary(&obj)
+}
+
+pub async fn leave(query: &str) -> Result<(), anyhow::Error> {
+    let obj = find(query)?;
+    direct_request(Method::POST, &format!("campaigns/{}/leave", server_id(&obj)), None).await?;
+    store::forget(&obj)?;
+    info!("Left campaign {}", obj.name());
+    Ok(())
+}
+
+/// Refreshes the cache with every campaign the user belongs to and prints them
+pub async fn list() -> Result<(), anyhow::Error> {
+    let response = direct_request(Method::GET, "campaigns", None).await?;
+    let campaigns = response.as_array().cloned().unwrap_or_default();
+
+    // Campaigns the user was removed from disappear from the cache too
+    let current: Vec<String> = campaigns.iter()
+        .filter_map(|c| c.get("_id").or(c.get("id")).and_then(|id| id.as_str()).map(|id| format!("_{}", id)))
+        .collect();
+    for stale in store::load_all(KIND)?.iter().filter(|o| !current.contains(&o.id)) {
+        store::forget(stale)?;
+    }
+
+    if campaigns.is_empty() {
+        info!("You are not in any campaign.");
+    }
+    for data in campaigns {
+        print_summary(&cache(data)?)?;
+    }
+    Ok(())
+}
+
+/// Prints the cached campaign without contacting the server
+pub fn show(query: &str) -> Result<(), anyhow::Error> {
+    let obj = find(query)?;
+    print_summary(&obj)?;
+    let campaign: Campaign = obj.parse()?;
+    for note in &campaign.notes {
+        println!("  [{}] {}: {}", note.created, note.author, note.text);
+    }
+    Ok(())
+}
+
+pub async fn set_role(query: &str, user: &str, role: CampaignRole) -> Result<(), anyhow::Error> {
+    let obj = find(query)?;
+    let campaign = require_role(&obj, CampaignRole::Dm, "change roles")?;
+    let member = campaign.members.iter()
+        .find(|m| m.user == user || m.username.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(user)))
+        .ok_or_else(|| Error::NotFound(format!("{} is not a member of {}", user, campaign.name)))?;
+    let path = format!("campaigns/{}/members/{}", server_id(&obj), member.user);
+    let obj = cache(direct_request(Method::PUT, &path, Some(&json!({ "role": role }))).await?)?;
+    info!("Updated roles in {}", obj.name());
+    Ok(())
+}
+
+/// Attaches (or with `detach`, removes) a character and/or a homebrew collection
+pub async fn attach(query: &str, character: Option<&str>, collection: Option<&str>, detach: bool) -> Result<(), anyhow::Error> {
+    let mut obj = find(query)?;
+    if character.is_none() && collection.is_none() {
+        return Err(Error::Usage("Give --character and/or --collection".to_string()).into());
+    }
+    if let Some(character) = character {
+        require_role(&obj, CampaignRole::Player, "manage characters")?;
+        let id = character_id(character)?;
+        let path = format!("campaigns/{}/characters", server_id(&obj));
+        obj = if detach {
+            cache(direct_request(Method::DELETE, &format!("{}/{}", path, id), None).await?)?
+        } else {
+            cache(direct_request(Method::POST, &path, Some(&json!({ "character": id }))).await?)?
+        };
+    }
+    if let Some(collection) = collection {
+        require_role(&obj, CampaignRole::Dm, "manage homebrew collections")?;
+        let path = format!("campaigns/{}/collections", server_id(&obj));
+        obj = if detach {
+            cache(direct_request(Method::DELETE, &format!("{}/{}", path, path_segment(collection)), None).await?)?
+        } else {
+            let known = crate::export::EXPORT_TYPES.iter().any(|kind| store::load_all(kind).unwrap_or_default().iter()
+                .any(|o| o.data.get("source").and_then(|s| s.as_str()).is_some_and(|s| s.eq_ignore_ascii_case(collection))));
+            if !known {
+                warn!("No cached content has source {}", collection);
+            }
+            cache(direct_request(Method::POST, &path, Some(&json!({ "collection": collection }))).await?)?
+        };
+    }
+    info!("Updated campaign {}", obj.name());
+    print_summary(&obj)
+}
+
+/// Percent-encodes a user-given name for use as a single URL path segment, so a '/', '?' or space stays part of it
+fn path_segment(segment: &str) -> String {
+    let mut url = Url::parse("http://localhost/").expect("static URL parses");
+    url.path_segments_mut().expect("http URLs have a path").push(segment);
+    url.path().trim_start_matches('/').to_string()
+}
+
+pub async fn add_note(query: &str, text: &str) -> Result<(), anyhow::Error> {
+    let obj = find(query)?;
+    require_role(&obj, CampaignRole::Player, "add notes")?;
+    let path = format!("campaigns/{}/notes", server_id(&obj));
+    let obj = cache(direct_request(Method::POST, &path, Some(&json!({ "text": text }))).await?)?;
+    info!("Note added to {}", obj.name());
+    Ok(())
+}
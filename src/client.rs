@@ -1,13 +1,22 @@
-use crate::check_setup_cmpl;
-use crossterm::style::Stylize;
-use tokio::sync::Semaphore;
 use std::sync::Arc;
-use futures::future::join_all;
 use reqwest::Client;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
-use anyhow;
+use crate::atomic;
+use crate::error::Error;
+use log::{debug, info, trace};
+
+/// Identifies the resource a call acts on; creates of unsynced objects are told apart by their local_id
+pub fn resource_key(endpoint: &str, data: Option<&str>) -> String {
+    let local_id = data
+        .and_then(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+        .and_then(|v| v.get("local_id").and_then(|id| id.as_str()).map(|id| id.to_string()));
+    match local_id {
+        Some(id) => format!("{}#local:{}", endpoint, id),
+        None => endpoint.to_string(),
+    }
+}
 
 /// Parses a line like: "POST /api/resource {"key":"value"}"
 pub fn parse_line(line: &str) -> Option<(String, String, Option<String>)> {
@@ -55,10 +64,14 @@ pub fn clean_session_calls(session_calls: PathBuf) -> Result<(), anyhow::Error>
 
     let contents = fs::read_to_string(&session_calls_path)?;
     let mut ops_by_resource: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut resource_order: Vec<String> = Vec::new();
 
     for line in contents.lines() {
         if let Some((method, endpoint, data)) = parse_line(line) {
-            let key = endpoint.clone();
+            let key = resource_key(&endpoint, data.as_deref());
+            if !ops_by_resource.contains_key(&key) {
+                resource_order.push(key.clone());
+            }
             let stack = ops_by_resource.entry(key).or_default();
 
             match method.as_str() {
@@ -69,10 +82,10 @@ pub fn clean_session_calls(session_calls: PathBuf) -> Result<(), anyhow::Error>
                             "POST" => { stack.pop(); } // keep latest create only
                             "DELETE" => {} // DELETE + POST = valid
                             "PUT" => {
-                                return Err(anyhow::anyhow!(
+                                return Err(Error::Conflict(format!(
                                     "Invalid sequence: UPDATE followed by CREATE for {}",
                                     endpoint
-                                ));
+                                )).into());
                             }
                             _ => {}
                         }
@@ -112,21 +125,31 @@ pub fn clean_session_calls(session_calls: PathBuf) -> Result<(), anyhow::Error>
     }
 
     let mut cleaned_lines = Vec::new();
-    for stack in ops_by_resource.values() {
-        for (_, line) in stack {
+    for key in &resource_order {
+        for (_, line) in &ops_by_resource[key] {
             cleaned_lines.push(line.clone());
         }
     }
 
-    fs::write(&session_calls_path, cleaned_lines.join("\n"))?;
+    // Keep the final newline so the next appended call starts on its own line
+    let mut cleaned = cleaned_lines.join("\n");
+    if !cleaned.is_empty() {
+        cleaned.push('\n');
+    }
+    atomic::write(&session_calls_path, cleaned)?;
 
     Ok(())
 }
 
+/// Calls carrying read-only reference content (e.g. the SRD baseline) are never uploaded
+pub fn is_reference_call(call: &[String]) -> bool {
+    call.iter().skip(2).any(|part| part.contains("\"read_only\":true"))
+}
+
 pub fn collect_session_calls(fp: PathBuf) -> Result<Vec<Vec<String>>, anyhow::Error> {
     let session_calls_path = fp;
     if !session_calls_path.exists() && !session_calls_path.is_file() {
-        return Err(anyhow::anyhow!("[ERROR] Session calls file does not exist: {}", session_calls_path.display()));
+        return Err(Error::Journal(format!("Session calls file does not exist: {}", session_calls_path.display())).into());
     }
     // Read file line by line
     let contents = fs::read_to_string(&session_calls_path)?;
@@ -134,11 +157,14 @@ pub fn collect_session_calls(fp: PathBuf) -> Result<Vec<Vec<String>>, anyhow::Er
     for line in contents.lines() {
         let line = line.trim();
         if !line.is_empty() {
-            let parts: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
-            if parts.len() >= 2 {
-                calls.push(parts);
-            } else {
-                return Err(anyhow::anyhow!("[ERROR] Invalid session call format: {}", line));
+            // JSON data may contain spaces, so only the method and endpoint are split off
+            match parse_line(line) {
+                Some((method, endpoint, data)) => {
+                    let mut parts = vec![method, endpoint];
+                    parts.extend(data);
+                    calls.push(parts);
+                }
+                None => return Err(Error::Journal(format!("Invalid session call format: {}", line)).into()),
             }
         }
     }
@@ -146,196 +172,193 @@ pub fn collect_session_calls(fp: PathBuf) -> Result<Vec<Vec<String>>, anyhow::Er
     Ok(calls)
 }
 
-pub async fn calculate_cache_size() -> Result<(u64), anyhow::Error> {
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        anyhow::anyhow!("Could not find home directory")
-    })?;
-    let archerdndsys_dir = home_dir.join(".archerdndsys");
-    let saved_objs_dir = archerdndsys_dir.join("saved_objs");
-    
-    if !saved_objs_dir.exists() {
-        return Err(anyhow::anyhow!("[ERROR] Saved objects directory does not exist: {}", saved_objs_dir.display()));
-    }
-    
-    let mut total_size = 0;
-    for entry in fs::read_dir(saved_objs_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            let subdir_path = entry.path();
-            if subdir_path.is_dir() {
-                for sub_entry in fs::read_dir(subdir_path)? {
-                    let sub_entry = sub_entry?;
-                    if sub_entry.file_type()?.is_file() {
-                        total_size += sub_entry.metadata()?.len();
-                        println!("[INFO] {} {}: {} bytes", "Found file:".green(), sub_entry.path().display().to_string().bold(), sub_entry.metadata()?.len());
-                    }
-                }
-            }
-        }
-        if entry.file_type()?.is_file() {
-            total_size += entry.metadata()?.len();
-        }
+/// Login token and user ID, one "token,user_id" line
+pub const AUTH_TOKENS_FILE: &str = ".auth_tokens.txt";
+/// Saved credentials for --auto-login, one "username,password" line
+pub const AUTO_LOGIN_FILE: &str = ".auto_login.txt";
+
+/// Parses the one-line "a,b" format shared by the token and auto login files
+pub fn parse_pair(contents: &str) -> Option<(String, String)> {
+    let contents = contents.trim();
+    if contents.lines().count() != 1 {
+        return None;
     }
-    
-    Ok(total_size)
+    contents.split_once(',')
+        .map(|(a, b)| (a.trim().to_string(), b.trim().to_string()))
+        .filter(|(a, b)| !a.is_empty() && !b.is_empty())
 }
 
-pub async fn clear_cache(days: u64) -> Result<(), anyhow::Error> {
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        anyhow::anyhow!("Could not find home directory")
-    })?;
-    let archerdndsys_dir = home_dir.join(".archerdndsys");
-    let saved_objs_dir = archerdndsys_dir.join("saved_objs");
+/// Saves the token and user ID returned by login or registration
+pub fn save_session(token: &str, user_id: &str) -> Result<(), anyhow::Error> {
+    let path = crate::store::data_dir()?.join(AUTH_TOKENS_FILE);
+    atomic::write(&path, format!("{},{}", token, user_id))
+        .map_err(|e| Error::Storage(format!("Failed to save auth token: {}", e)).into())
+}
 
-    if !saved_objs_dir.exists() {
-        return Err(anyhow::anyhow!("[ERROR] {} {}", "Saved objects directory does not exist:".red(), saved_objs_dir.display().to_string().bold()));
+/// Sends a request and returns its status and body, traced for sync debugging: method, URL and status at debug level,
+/// bodies at trace level. `redact` keeps bodies holding passwords or tokens out of the log
+pub async fn send(request: reqwest::RequestBuilder, redact: bool) -> Result<(reqwest::StatusCode, String), anyhow::Error> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let (method, url) = (request.method().clone(), request.url().clone());
+    debug!("{} {}", method, url);
+    if let Some(body) = request.body().and_then(|b| b.as_bytes()).filter(|b| !redact && !b.is_empty()) {
+        trace!("{} {} request: {}", method, url, String::from_utf8_lossy(body));
     }
 
-    let cutoff_time = std::time::SystemTime::now() - std::time::Duration::from_secs(days * 24 * 60 * 60);
-    
-    // Check last modified/created/accessed time of each file
-    for dir in fs::read_dir(saved_objs_dir)? {
-        let dir = dir?;
-        if dir.file_type()?.is_file() {
-            let path = dir.path();
-            fs::remove_file(&path)?;
-            println!("[INFO] {} {}", "Deleted file:".green(), path.display().to_string().bold());
-            continue;
-        }
-        for entry in fs::read_dir(dir.path())? {
-            let entry = entry?;
-            let metadata = entry.metadata()?;
-            let modified_time = metadata.modified()?;
-            let created_time = metadata.created()?;
-            let accessed_time = metadata.accessed()?;
-            let should_delete = modified_time < cutoff_time && created_time < cutoff_time && accessed_time < cutoff_time;
-
-            if should_delete {
-                let path = entry.path();
-                if path.is_file() {
-                    fs::remove_file(&path)?;
-                    println!("[INFO] {} {}", "Deleted file:".green(), path.display().to_string().bold());
-                } else if path.is_dir() {
-                    println!("[ERROR] {} {}", "Unexpected directory in saved_objs:".red(), path.display().to_string().bold());
-                }
-            }
-        }
+    let start = std::time::Instant::now();
+    let response = client.execute(request).await?;
+    let status = response.status();
+    let text = response.text().await?;
+    debug!("{} {} -> {} in {}ms", method, url, status, start.elapsed().as_millis());
+    if !redact && !text.is_empty() {
+        trace!("{} {} response: {}", method, url, text);
     }
-    
-    Ok(())
+    Ok((status, text))
 }
 
-pub async fn clear_all_cache() -> Result<(), anyhow::Error> {
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        anyhow::anyhow!("Could not find home directory")
-    })?;
-    
-    let archerdndsys_dir = home_dir.join(".archerdndsys");
-    let saved_objs_dir = archerdndsys_dir.join("saved_objs");
-    
-    if !saved_objs_dir.exists() {
-        return Err(anyhow::anyhow!("[ERROR] {} {}", "Saved objects directory does not exist:".red(), saved_objs_dir.display().to_string().bold()));
+pub async fn process_call(call: Vec<String>, client: Arc<Client>, auth_tokens: (String, String)) -> Result<(), anyhow::Error> {
+    if call.len() < 2 {
+        return Err(Error::Journal(format!("Invalid session call format: {:?}", call)).into());
     }
-    
-    for dir in fs::read_dir(saved_objs_dir)? {
-        let dir = dir?;
-        if !dir.file_type()?.is_dir() {
-            let path = dir.path();
-            fs::remove_file(&path)?;
-            println!("[INFO] {} {}", "Deleted file:".green(), path.display().to_string().bold());
-        }
-        let dir_path = dir.path();
-        for entry in fs::read_dir(dir_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                fs::remove_file(&path)?;
-                println!("[INFO] {} {}", "Deleted file:".green(), path.display().to_string().bold());
-            } else if path.is_dir() {
-                fs::remove_dir_all(&path)?;
-                println!("[INFO] {} {}", "Deleted directory:".green(), path.display().to_string().bold());
-            }
-        }
+
+    let method = call[0].clone();
+    let endpoint = call[1].clone();
+
+    let request = match method.as_str() {
+        "POST" | "PUT" => {
+            let data: serde_json::Value = serde_json::from_str(call.get(2).map(|d| d.as_str()).unwrap_or("{}"))
+                .map_err(|e| Error::Journal(format!("Invalid JSON in call to {}: {}", endpoint, e)))?;
+            let request = if method == "POST" { client.post(&endpoint) } else { client.put(&endpoint) };
+            request.json(&data)
+        },
+        "DELETE" => client.delete(&endpoint),
+        _ => return Err(Error::Journal(format!("Unsupported HTTP method: {}", method)).into())
+    };
+    let (status, _) = send(request.bearer_auth(&auth_tokens.0), false).await?;
+    if !status.is_success() {
+        return Err(Error::from_status(status, format!("{} request to {} failed with status: {}", method, endpoint, status)).into());
     }
-    
+    info!("{} request to {} succeeded.", method, endpoint);
+
     Ok(())
 }
 
-pub async fn load_auth_tokens() -> Result<(String, String), anyhow::Error> {
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        anyhow::anyhow!("Could not find home directory")
-    })?;
-    let auth_tokens_path = home_dir.join(".archerdndsys/.auth_tokens.txt");
+// All Campaign Commands can be made directly to the server; rate limit to 15 per minute.
+pub const DIRECT_REQUESTS_PER_MINUTE: usize = 15;
+/// Timestamps (ms) of recent direct requests, shared between invocations so the limit holds across commands
+const REQUEST_LOG: &str = ".archerdndsys/.request_times.txt";
+
+/// Token and user ID as saved by login ("token,user_id")
+pub fn load_session() -> Result<(String, String), anyhow::Error> {
+    let path = crate::store::data_dir()?.join(AUTH_TOKENS_FILE);
+    let contents = fs::read_to_string(&path).unwrap_or_default();
+    parse_pair(&contents).ok_or_else(|| Error::Auth("Not logged in. Please run `archerdndsys --login` first.".to_string()).into())
+}
 
+/// Sleeps until another direct request fits in the per-minute budget, then records it
+async fn wait_for_rate_limit() -> Result<(), anyhow::Error> {
+    let path = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
+        .join(REQUEST_LOG);
+    let now = || std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
 
-    if !auth_tokens_path.exists() {
-        return Err(anyhow::anyhow!("[ERROR] Authorization tokens file not found. Please run `archerdndsys --setup` to initialize the client."));
+    let mut times: Vec<u64> = fs::read_to_string(&path).unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.trim().parse().ok())
+        .filter(|t| now().saturating_sub(*t) < 60_000)
+        .collect();
+    times.sort();
+    if times.len() >= DIRECT_REQUESTS_PER_MINUTE {
+        let oldest = times[times.len() - DIRECT_REQUESTS_PER_MINUTE];
+        let wait = 60_000u64.saturating_sub(now().saturating_sub(oldest));
+        info!("Rate limit reached, waiting {}s", wait.div_ceil(1000));
+        tokio::time::sleep(std::time::Duration::from_millis(wait)).await;
+        times.retain(|t| now().saturating_sub(*t) < 60_000);
     }
+    times.push(now());
+    atomic::write(&path, times.iter().map(|t| t.to_string()).collect::<Vec<_>>().join("\n"))?;
+    Ok(())
+}
 
-    let contents = fs::read_to_string(auth_tokens_path)?;
-    let mut lines = contents.lines();
+/// Sends a request straight to the server (no journal), subject to the direct request rate limit.
+/// Returns the response body as JSON (Null when empty)
+pub async fn direct_request(method: reqwest::Method, path: &str, body: Option<&serde_json::Value>) -> Result<serde_json::Value, anyhow::Error> {
+    let (token, _) = load_session()?;
+    wait_for_rate_limit().await?;
 
-    let access_token = lines.next().ok_or_else(|| {
-        anyhow::anyhow!("Access token not found in auth tokens file.")
-    })?.to_string();
+    let url = format!("{}/{}", crate::SERVER, path.trim_start_matches('/'));
+    let mut request = Client::new().request(method.clone(), &url).bearer_auth(token);
+    if let Some(body) = body {
+        request = request.json(body);
+    }
+    let (status, text) = send(request, false).await?;
+    if !status.is_success() {
+        return Err(Error::from_status(status, format!("{} request to {} failed with status {}: {}", method, url, status, text.trim())).into());
+    }
+    if text.trim().is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+    serde_json::from_str(&text).map_err(|e| Error::Network(format!("Server returned invalid JSON for {}: {}", url, e)).into())
+}
 
-    let refresh_token = lines.next().ok_or_else(|| {
-        anyhow::anyhow!("Refresh token not found in auth tokens file.")
-    })?.to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
 
-    Ok((access_token, refresh_token))
-}
+    /// Writes the journal lines, cleans them and returns what is left
+    fn clean(lines: &[&str]) -> Result<Vec<String>, anyhow::Error> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session_calls.txt");
+        fs::write(&path, lines.join("\n")).unwrap();
+        clean_session_calls(path.clone())?;
+        Ok(fs::read_to_string(&path).unwrap().lines().map(|l| l.to_string()).collect())
+    }
 
-pub async fn process_call(call: Vec<String>, client: Arc<Client>, authTokens: (String, String)) -> Result<(), anyhow::Error> {
-    if call.len() < 2 {
-        return Err(anyhow::anyhow!("[ERROR] Invalid session call format: {:?}", call));
+    #[test]
+    fn resource_key_separates_local_objects() {
+        assert_eq!(resource_key("/spells", Some(r#"{"local_id":"3"}"#)), "/spells#local:3");
+        assert_eq!(resource_key("/spells/abc", Some(r#"{"name":"x"}"#)), "/spells/abc");
+        assert_eq!(resource_key("/spells/abc", None), "/spells/abc");
     }
 
-    let method = call[0].clone();
-    let endpoint = call[1].clone();
+    #[test]
+    fn keeps_latest_create_per_local_object() {
+        let cleaned = clean(&[
+            r#"POST /spells {"local_id":"1","v":1}"#,
+            r#"POST /spells {"local_id":"2","v":1}"#,
+            r#"POST /spells {"local_id":"1","v":2}"#,
+        ]).unwrap();
+        assert_eq!(cleaned, [r#"POST /spells {"local_id":"1","v":2}"#, r#"POST /spells {"local_id":"2","v":1}"#]);
+    }
 
-    match method.as_str() {
-        "POST" => {
-            let data = call.get(2).cloned().unwrap_or_default();
-            let response = client.post(&endpoint)
-                .bearer_auth(&authTokens.0)
-                .json(&data)
-                .send()
-                .await?;
-            if response.status().is_success() {
-                println!("{} {} {}", "[INFO] POST request to".green(), endpoint.bold(), "succeeded.".green());
-            } else {
-                println!("{} {} {} {}", "[ERROR] POST request to".red(), endpoint.bold(), "failed with status:".red(), response.status());
-            }
-        },
-        "PUT" => {
-            let data = call.get(2).cloned().unwrap_or_default();
-            let response = client.put(&endpoint)
-                .bearer_auth(&authTokens.0)
-                .json(&data)
-                .send()
-                .await?;
-            if response.status().is_success() {
-                println!("[INFO] PUT request to {} succeeded.", endpoint);
-            } else {
-                println!("[ERROR] PUT request to {} failed with status: {}", endpoint, response.status());
-            }
-        },
-        "DELETE" => {
-            let response = client.delete(&endpoint)
-                .bearer_auth(&authTokens.0)
-                .send()
-                .await?;
-            if response.status().is_success() {
-                println!("[INFO] DELETE request to {} succeeded.", endpoint);
-            } else {
-                println!("[ERROR] DELETE request to {} failed with status: {}", endpoint, response.status());
-            }
-        },
-        _ => return Err(anyhow::anyhow!("[ERROR] Unsupported HTTP method: {}", method))
+    #[test]
+    fn keeps_last_update_and_first_seen_order() {
+        let cleaned = clean(&[
+            r#"PUT /spells/a {"v":1}"#,
+            r#"PUT /items/b {"v":1}"#,
+            "GET /spells/a",
+            r#"PUT /spells/a {"v":2}"#,
+        ]).unwrap();
+        assert_eq!(cleaned, [r#"PUT /spells/a {"v":2}"#, r#"PUT /items/b {"v":1}"#]);
     }
 
-    Ok(())
-}
+    #[test]
+    fn delete_wins_over_updates() {
+        let cleaned = clean(&[r#"PUT /spells/a {"v":1}"#, "DELETE /spells/a", r#"PUT /spells/a {"v":2}"#]).unwrap();
+        assert_eq!(cleaned, ["DELETE /spells/a"]);
+    }
 
-// All Campaign Commands can be made directly to the server; rate limit to 15 per minute.
+    #[test]
+    fn create_after_delete_is_kept() {
+        let cleaned = clean(&["DELETE /spells/a", r#"POST /spells/a {"v":1}"#]).unwrap();
+        assert_eq!(cleaned, ["DELETE /spells/a", r#"POST /spells/a {"v":1}"#]);
+    }
+
+    #[test]
+    fn create_after_update_is_a_conflict() {
+        let err = clean(&[r#"PUT /spells/a {"v":1}"#, r#"POST /spells/a {"v":2}"#]).unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Conflict(_))));
+    }
+}
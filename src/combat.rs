@@ -0,0 +1,574 @@
+// Initiative and combat tracker
+// One combat at a time, saved to ~/.archerdndsys/combat.json after every change so it survives closing the terminal
+// Every operation returns log lines so the CLI and the TUI report the same thing
+
+use crate::atomic;
+use crate::dice::{roll_die, DiceExpr};
+use crate::error::Error;
+use crate::models::{AbilityScores, Character, Creature};
+use crate::store;
+use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use log::info;
+use crate::logging;
+
+const COMBAT_FILE: &str = "combat.json";
+/// Log lines kept in the saved combat
+const LOG_LIMIT: usize = 200;
+/// Hit points of a monster added without --hp or a cached stat block
+const DEFAULT_MONSTER_HP: i32 = 10;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeathSaves {
+    pub successes: u8,
+    pub failures: u8,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActiveCondition {
+    pub name: String,
+    /// Remaining rounds, counted down at the end of the affected combatant's turns; None lasts until removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounds: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Combatant {
+    pub name: String,
+    /// "Characters/<id>" or "Creatures/<id>" when pulled from the cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub player: bool,
+    pub initiative: i32,
+    /// Initiative modifier, also breaks initiative ties
+    pub init_bonus: i32,
+    pub max_hp: i32,
+    pub hp: i32,
+    pub temp_hp: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub armor_class: Option<i32>,
+    /// Constitution saving throw modifier used for concentration checks
+    pub con_save: i32,
+    pub conditions: Vec<ActiveCondition>,
+    /// Spell being concentrated on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concentration: Option<String>,
+    pub death_saves: DeathSaves,
+    pub stable: bool,
+    pub dead: bool,
+}
+
+impl Combatant {
+    pub fn is_down(&self) -> bool {
+        self.hp <= 0
+    }
+
+    pub fn status(&self) -> String {
+        if self.dead {
+            "dead".to_string()
+        } else if self.is_down() && self.stable {
+            "stable".to_string()
+        } else if self.is_down() && self.player {
+            format!("dying ({}/3 ok, {}/3 failed)", self.death_saves.successes, self.death_saves.failures)
+        } else {
+            String::new()
+        }
+    }
+
+    fn has_condition(&self, name: &str) -> bool {
+        self.conditions.iter().any(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    fn remove_condition(&mut self, name: &str) {
+        self.conditions.retain(|c| !c.name.eq_ignore_ascii_case(name));
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Combat {
+    pub round: u32,
+    /// Index into `combatants` of whose turn it is
+    pub turn: usize,
+    pub combatants: Vec<Combatant>,
+    pub log: Vec<String>,
+}
+
+fn combat_path() -> Result<PathBuf, anyhow::Error> {
+    Ok(store::data_dir()?.join(COMBAT_FILE))
+}
+
+fn roll_d20() -> i32 {
+    roll_die(20) as i32
+}
+
+impl Combat {
+    pub fn load() -> Result<Combat, anyhow::Error> {
+        let path = combat_path()?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|_| Error::NotFound("No combat in progress. Run `archerdndsys combat start` first.".to_string()))?;
+        serde_json::from_str(&contents).map_err(|e| Error::Storage(format!("{} is corrupted: {}", path.display(), e)).into())
+    }
+
+    pub fn exists() -> bool {
+        combat_path().is_ok_and(|p| p.exists())
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        atomic::write(combat_path()?, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn record(&mut self, messages: &[String]) {
+        self.log.extend(messages.iter().cloned());
+        if self.log.len() > LOG_LIMIT {
+            self.log.drain(..self.log.len() - LOG_LIMIT);
+        }
+    }
+
+    /// Finds a combatant by list number (1-based), exact name or unique name prefix
+    pub fn find(&self, who: &str) -> Result<usize, anyhow::Error> {
+        if let Ok(n) = who.parse::<usize>() {
+            if n >= 1 && n <= self.combatants.len() {
+                return Ok(n - 1);
+            }
+        }
+        if let Some(i) = self.combatants.iter().position(|c| c.name.eq_ignore_ascii_case(who)) {
+            return Ok(i);
+        }
+        let matches: Vec<usize> = self.combatants.iter().enumerate()
+            .filter(|(_, c)| c.name.to_lowercase().starts_with(&who.to_lowercase()))
+            .map(|(i, _)| i)
+            .collect();
+        match matches.as_slice() {
+            [i] => Ok(*i),
+            [] => Err(Error::NotFound(format!("No combatant matches '{}'", who)).into()),
+            _ => Err(Error::Usage(format!("'{}' matches several combatants, use the list number", who)).into()),
+        }
+    }
+
+    /// Sorts by initiative, then initiative bonus, keeping whoever currently has the turn
+    fn sort(&mut self) {
+        let current = self.combatants.get(self.turn).map(|c| c.name.clone());
+        self.combatants.sort_by(|a, b| b.initiative.cmp(&a.initiative).then(b.init_bonus.cmp(&a.init_bonus)));
+        if let Some(current) = current {
+            self.turn = self.combatants.iter().position(|c| c.name == current).unwrap_or(0);
+        }
+    }
+
+    fn unique_name(&self, name: &str) -> String {
+        if !self.combatants.iter().any(|c| c.name.eq_ignore_ascii_case(name)) {
+            return name.to_string();
+        }
+        (2..).map(|n| format!("{} {}", name, n))
+            .find(|candidate| !self.combatants.iter().any(|c| c.name.eq_ignore_ascii_case(candidate)))
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    pub fn add(&mut self, mut combatant: Combatant, initiative: Option<i32>) -> Vec<String> {
+        combatant.name = self.unique_name(&combatant.name);
+        let message = match initiative {
+            Some(initiative) => {
+                combatant.initiative = initiative;
+                format!("{} joins with initiative {}", combatant.name, initiative)
+            }
+            None => {
+                let roll = roll_d20();
+                combatant.initiative = roll + combatant.init_bonus;
+                format!("{} rolls initiative: {} ({:+}) = {}", combatant.name, roll, combatant.init_bonus, combatant.initiative)
+            }
+        };
+        self.combatants.push(combatant);
+        self.sort();
+        let messages = vec![message];
+        self.record(&messages);
+        messages
+    }
+
+    pub fn remove(&mut self, index: usize) -> Vec<String> {
+        let removed = self.combatants.remove(index);
+        if index < self.turn {
+            self.turn -= 1;
+        } else if self.turn >= self.combatants.len() {
+            self.turn = 0;
+        }
+        let messages = vec![format!("{} leaves the combat", removed.name)];
+        self.record(&messages);
+        messages
+    }
+
+    /// Ends the current turn (ticking its conditions down) and moves to the next living combatant
+    pub fn next_turn(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        if self.combatants.is_empty() {
+            return vec!["No combatants".to_string()];
+        }
+        if self.round == 0 {
+            self.round = 1;
+            self.turn = 0;
+        } else {
+            let current = &mut self.combatants[self.turn];
+            for condition in current.conditions.iter_mut() {
+                if let Some(rounds) = condition.rounds.as_mut() {
+                    *rounds = rounds.saturating_sub(1);
+                }
+            }
+            let expired: Vec<String> = current.conditions.iter().filter(|c| c.rounds == Some(0)).map(|c| c.name.clone()).collect();
+            current.conditions.retain(|c| c.rounds != Some(0));
+            for name in expired {
+                messages.push(format!("{} is no longer {}", current.name, name));
+            }
+
+            // Skip the dead, wrapping into a new round
+            for _ in 0..self.combatants.len() {
+                self.turn += 1;
+                if self.turn >= self.combatants.len() {
+                    self.turn = 0;
+                    self.round += 1;
+                    messages.push(format!("Round {}", self.round));
+                }
+                if !self.combatants[self.turn].dead {
+                    break;
+                }
+            }
+        }
+
+        let current = &self.combatants[self.turn];
+        messages.push(format!("Round {}, {}'s turn", self.round, current.name));
+        if current.is_down() && current.player && !current.stable && !current.dead {
+            messages.push(format!("{} is dying and must make a death saving throw", current.name));
+        }
+        self.record(&messages);
+        messages
+    }
+
+    pub fn damage(&mut self, index: usize, amount: i32, critical: bool, roll_concentration: bool) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        if c.dead {
+            let messages = vec![format!("{} is already dead", c.name)];
+            self.record(&messages);
+            return messages;
+        }
+        let mut messages = Vec::new();
+        let absorbed = amount.min(c.temp_hp);
+        c.temp_hp -= absorbed;
+        let remaining = amount - absorbed;
+        let was_down = c.is_down();
+        c.hp -= remaining;
+        if absorbed > 0 {
+            messages.push(format!("{} takes {} damage ({} absorbed by temporary HP), {}/{} HP", c.name, amount, absorbed, c.hp.max(0), c.max_hp));
+        } else {
+            messages.push(format!("{} takes {} damage, {}/{} HP", c.name, amount, c.hp.max(0), c.max_hp));
+        }
+
+        if c.hp <= 0 {
+            let overflow = -c.hp;
+            c.hp = 0;
+            if !c.player {
+                c.dead = true;
+                c.concentration = None;
+                messages.push(format!("{} is defeated", c.name));
+            } else if overflow >= c.max_hp {
+                c.dead = true;
+                c.concentration = None;
+                messages.push(format!("{} is killed outright by massive damage", c.name));
+            } else if was_down && remaining > 0 {
+                // Damage while at 0 HP counts as failed death saves
+                c.stable = false;
+                c.death_saves.failures += if critical { 2 } else { 1 };
+                messages.push(format!("{} fails {} death save(s) from the hit", c.name, if critical { 2 } else { 1 }));
+                if c.death_saves.failures >= 3 {
+                    c.dead = true;
+                    messages.push(format!("{} dies", c.name));
+                }
+            } else {
+                if !c.has_condition("unconscious") {
+                    c.conditions.push(ActiveCondition { name: "unconscious".to_string(), rounds: None });
+                }
+                if let Some(spell) = c.concentration.take() {
+                    messages.push(format!("{} loses concentration on {}", c.name, spell));
+                }
+                messages.push(format!("{} falls unconscious", c.name));
+            }
+        } else if amount > 0 {
+            // Damage soaked by temporary HP still forces the save, its DC comes from the full hit
+            if let Some(spell) = c.concentration.clone() {
+                let dc = (amount / 2).max(10);
+                if roll_concentration {
+                    let roll = roll_d20();
+                    let total = roll + c.con_save;
+                    if total >= dc {
+                        messages.push(format!("{} keeps concentration on {}: {} ({:+}) = {} vs DC {}", c.name, spell, roll, c.con_save, total, dc));
+                    } else {
+                        c.concentration = None;
+                        messages.push(format!("{} loses concentration on {}: {} ({:+}) = {} vs DC {}", c.name, spell, roll, c.con_save, total, dc));
+                    }
+                } else {
+                    messages.push(format!("{} must make a DC {} Constitution save to keep concentrating on {}", c.name, dc, spell));
+                }
+            }
+        }
+        self.record(&messages);
+        messages
+    }
+
+    pub fn heal(&mut self, index: usize, amount: i32) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        if c.dead {
+            let messages = vec![format!("{} is dead and cannot be healed", c.name)];
+            self.record(&messages);
+            return messages;
+        }
+        let was_down = c.is_down();
+        c.hp = (c.hp + amount).min(c.max_hp);
+        let mut messages = vec![format!("{} heals {}, {}/{} HP", c.name, amount, c.hp, c.max_hp)];
+        if was_down && c.hp > 0 {
+            c.death_saves = DeathSaves::default();
+            c.stable = false;
+            c.remove_condition("unconscious");
+            messages.push(format!("{} regains consciousness", c.name));
+        }
+        self.record(&messages);
+        messages
+    }
+
+    /// Temporary HP do not stack, the higher value is kept
+    pub fn temp_hp(&mut self, index: usize, amount: i32) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        let messages = if amount > c.temp_hp {
+            c.temp_hp = amount;
+            vec![format!("{} gains {} temporary HP", c.name, amount)]
+        } else {
+            vec![format!("{} keeps their {} temporary HP", c.name, c.temp_hp)]
+        };
+        self.record(&messages);
+        messages
+    }
+
+    pub fn add_condition(&mut self, index: usize, name: &str, rounds: Option<u32>) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        c.remove_condition(name);
+        c.conditions.push(ActiveCondition { name: name.to_lowercase(), rounds });
+        let mut messages = vec![match rounds {
+            Some(r) => format!("{} is {} for {} round(s)", c.name, name.to_lowercase(), r),
+            None => format!("{} is {}", c.name, name.to_lowercase()),
+        }];
+        // Incapacitating conditions end concentration
+        if ["incapacitated", "paralyzed", "petrified", "stunned", "unconscious"].contains(&name.to_lowercase().as_str()) {
+            if let Some(spell) = c.concentration.take() {
+                messages.push(format!("{} loses concentration on {}", c.name, spell));
+            }
+        }
+        self.record(&messages);
+        messages
+    }
+
+    pub fn remove_condition(&mut self, index: usize, name: &str) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        let messages = if c.has_condition(name) {
+            c.remove_condition(name);
+            vec![format!("{} is no longer {}", c.name, name.to_lowercase())]
+        } else {
+            vec![format!("{} is not {}", c.name, name.to_lowercase())]
+        };
+        self.record(&messages);
+        messages
+    }
+
+    pub fn concentrate(&mut self, index: usize, spell: Option<&str>) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        let mut messages = Vec::new();
+        if let Some(previous) = c.concentration.take() {
+            messages.push(format!("{} stops concentrating on {}", c.name, previous));
+        }
+        if let Some(spell) = spell {
+            c.concentration = Some(spell.to_string());
+            messages.push(format!("{} concentrates on {}", c.name, spell));
+        }
+        self.record(&messages);
+        messages
+    }
+
+    /// Rolls (or records `roll`) a death saving throw for a dying player character
+    pub fn death_save(&mut self, index: usize, roll: Option<i32>) -> Vec<String> {
+        let c = &mut self.combatants[index];
+        if !c.is_down() || c.dead || c.stable || !c.player {
+            let messages = vec![format!("{} does not need to make death saves", c.name)];
+            self.record(&messages);
+            return messages;
+        }
+        let roll = roll.unwrap_or_else(roll_d20);
+        let mut messages = Vec::new();
+        match roll {
+            20 => {
+                c.hp = 1;
+                c.death_saves = DeathSaves::default();
+                c.remove_condition("unconscious");
+                messages.push(format!("{} rolls a natural 20 and regains 1 HP", c.name));
+            }
+            1 => {
+                c.death_saves.failures += 2;
+                messages.push(format!("{} rolls a natural 1: two failures", c.name));
+            }
+            r if r >= 10 => {
+                c.death_saves.successes += 1;
+                messages.push(format!("{} succeeds a death save ({})", c.name, r));
+            }
+            r => {
+                c.death_saves.failures += 1;
+                messages.push(format!("{} fails a death save ({})", c.name, r));
+            }
+        }
+        if c.death_saves.failures >= 3 {
+            c.dead = true;
+            messages.push(format!("{} dies", c.name));
+        } else if c.death_saves.successes >= 3 {
+            c.stable = true;
+            c.death_saves = DeathSaves::default();
+            messages.push(format!("{} is stable", c.name));
+        }
+        self.record(&messages);
+        messages
+    }
+}
+
+/// Builds a combatant from a cached Character
+pub fn character_combatant(query: &str) -> Result<Combatant, anyhow::Error> {
+    let obj = store::find("Characters", query)?;
+    let character: Character = obj.parse()?;
+    let dex = AbilityScores::modifier(character.abilities.dexterity);
+    let max_hp = character.max_hp.max(1);
+    Ok(Combatant {
+        name: character.name.clone(),
+        source: Some(format!("{}/{}", obj.kind, obj.id)),
+        player: true,
+        init_bonus: dex,
+        max_hp,
+        hp: if character.current_hp > 0 { character.current_hp.min(max_hp) } else { max_hp },
+        armor_class: character.extra.get("armor_class").and_then(|ac| ac.as_i64()).map(|ac| ac as i32),
+        con_save: AbilityScores::modifier(character.abilities.constitution),
+        ..Default::default()
+    })
+}
+
+/// Options given on the command line when adding a monster, each overriding the cached stat block
+#[derive(Debug, Clone, Default)]
+pub struct MonsterOptions {
+    /// HP value or dice expression ("7" or "2d6")
+    pub hp: Option<String>,
+    pub init_bonus: Option<i32>,
+    pub armor_class: Option<i32>,
+    pub con_save: Option<i32>,
+}
+
+/// Builds a monster, taking its stats from a cached Creature of that name when there is one
+pub fn monster_combatant(name: &str, options: &MonsterOptions) -> Result<Combatant, anyhow::Error> {
+    let found = store::find("Creatures", name).ok()
+        .and_then(|obj| obj.parse::<Creature>().ok().map(|creature| (format!("{}/{}", obj.kind, obj.id), creature)));
+    let (source, creature) = match found {
+        Some((source, creature)) => (Some(source), Some(creature)),
+        None => (None, None),
+    };
+    let hp = match (&options.hp, &creature) {
+        (Some(hp), _) => DiceExpr::parse(hp)
+            .ok_or_else(|| Error::Usage(format!("'{}' is not a number or dice expression", hp)))?
+            .roll(),
+        (None, Some(creature)) => creature.hit_points,
+        (None, None) => DEFAULT_MONSTER_HP,
+    }.max(1);
+    Ok(Combatant {
+        name: creature.as_ref().map(|c| c.name.clone()).unwrap_or_else(|| name.to_string()),
+        source,
+        init_bonus: options.init_bonus
+            .or(creature.as_ref().map(|c| AbilityScores::modifier(c.abilities.dexterity)))
+            .unwrap_or(0),
+        max_hp: hp,
+        hp,
+        armor_class: options.armor_class.or(creature.as_ref().map(|c| c.armor_class).filter(|ac| *ac > 0)),
+        con_save: options.con_save.or(creature.as_ref().map(|c| c.save("constitution"))).unwrap_or(0),
+        ..Default::default()
+    })
+}
+
+pub fn print_status(combat: &Combat) {
+    if combat.round == 0 {
+        info!("Combat not started yet, run `archerdndsys combat next` for the first turn.");
+    } else {
+        println!("{}", logging::bold(format!("Round {}", combat.round)));
+    }
+    for (i, c) in combat.combatants.iter().enumerate() {
+        let marker = if combat.round > 0 && i == combat.turn { ">" } else { " " };
+        let mut hp = format!("{}/{}", c.hp, c.max_hp);
+        if c.temp_hp > 0 {
+            hp.push_str(&format!(" +{}", c.temp_hp));
+        }
+        let mut extras = Vec::new();
+        if let Some(ac) = c.armor_class {
+            extras.push(format!("AC {}", ac));
+        }
+        for condition in &c.conditions {
+            extras.push(match condition.rounds {
+                Some(r) => format!("{} ({}r)", condition.name, r),
+                None => condition.name.clone(),
+            });
+        }
+        if let Some(spell) = &c.concentration {
+            extras.push(format!("concentrating: {}", spell));
+        }
+        let status = c.status();
+        if !status.is_empty() {
+            extras.push(status);
+        }
+        let line = format!("{} {:>2}. {:<20} init {:>3}  HP {:<10} {}", marker, i + 1, c.name, c.initiative, hp, extras.join(", "));
+        if c.dead {
+            println!("{}", logging::styled(line.dark_grey()));
+        } else if marker == ">" {
+            println!("{}", logging::bold(line));
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Loads the combat, applies `action`, saves and prints the resulting log lines
+pub fn with_combat(action: impl FnOnce(&mut Combat) -> Result<Vec<String>, anyhow::Error>) -> Result<(), anyhow::Error> {
+    let mut combat = Combat::load()?;
+    let messages = action(&mut combat)?;
+    combat.save()?;
+    for message in messages {
+        info!("{}", message);
+    }
+    Ok(())
+}
+
+pub fn start(force: bool) -> Result<(), anyhow::Error> {
+    if Combat::exists() && !force {
+        return Err(Error::Conflict("A combat is already in progress. End it first or pass --force to discard it.".to_string()).into());
+    }
+    Combat::default().save()?;
+    info!("New combat started. Add combatants, then run `archerdndsys combat next`.");
+    Ok(())
+}
+
+/// Ends the combat; with `save_hp`, current HP is written back to the cached Characters
+pub fn end(save_hp: bool) -> Result<(), anyhow::Error> {
+    let combat = Combat::load()?;
+    if save_hp {
+        for c in combat.combatants.iter().filter(|c| c.player) {
+            let Some((kind, id)) = c.source.as_deref().and_then(|s| s.split_once('/')) else { continue };
+            let obj = store::find(kind, id)?;
+            let mut data = obj.data.clone();
+            data["current_hp"] = serde_json::json!(if c.dead { 0 } else { c.hp });
+            store::update(&obj, data)?;
+            info!("Saved HP for {} {}", c.name, c.hp);
+        }
+    }
+    fs::remove_file(combat_path()?)?;
+    info!("Combat ended after {} round(s).", combat.round);
+    Ok(())
+}
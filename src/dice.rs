@@ -0,0 +1,50 @@
+// Dice expressions in the usual "XdY+Z" notation
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceExpr {
+    pub count: u32,
+    pub sides: u32,
+    pub modifier: i32,
+}
+
+impl DiceExpr {
+    /// Parses "8d6", "1d8+3", "d20-1" or a flat number such as "5"
+    pub fn parse(expr: &str) -> Option<DiceExpr> {
+        let expr: String = expr.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+        if expr.is_empty() {
+            return None;
+        }
+        let Some(d_pos) = expr.find('d') else {
+            return expr.parse::<i32>().ok().map(|modifier| DiceExpr { count: 0, sides: 0, modifier });
+        };
+
+        let count = match &expr[..d_pos] {
+            "" => 1,
+            n => n.parse().ok()?,
+        };
+        let rest = &expr[d_pos + 1..];
+        let (sides, modifier) = match rest.find(['+', '-']) {
+            Some(pos) => (rest[..pos].parse().ok()?, rest[pos..].parse().ok()?),
+            None => (rest.parse().ok()?, 0),
+        };
+        if sides == 0 {
+            return None;
+        }
+
+        Some(DiceExpr { count, sides, modifier })
+    }
+
+    pub fn average(&self) -> f64 {
+        self.count as f64 * (self.sides as f64 + 1.0) / 2.0 + self.modifier as f64
+    }
+
+    pub fn roll(&self) -> i32 {
+        (0..self.count).map(|_| roll_die(self.sides) as i32).sum::<i32>() + self.modifier
+    }
+}
+
+pub fn roll_die(sides: u32) -> u32 {
+    rand::rng().random_range(1..=sides.max(1))
+}
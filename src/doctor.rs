@@ -0,0 +1,519 @@
+// `doctor`: checks the data directory, credentials, journals, cached objects and the server, and with --fix repairs what it can
+// Nothing is deleted: files and journal lines that cannot be repaired are moved to ~/.archerdndsys/doctor_quarantine/(time)/
+
+use crate::combat::Combat;
+use crate::error::Error;
+use crate::resource::ResourceKind;
+use crate::{atomic, client, migrate, required_files, store, SERVER};
+use chrono::Local;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::{error, info, warn};
+
+const QUARANTINE_DIR: &str = "doctor_quarantine";
+/// Files other commands keep directly in saved_objs/
+const OBJECTS_ROOT_FILES: [&str; 1] = ["synced.txt"];
+const JOURNAL_METHODS: [&str; 4] = ["POST", "PUT", "DELETE", "GET"];
+const SERVER_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Warn,
+    Error,
+}
+
+#[derive(Debug)]
+enum Repair {
+    /// Creates missing setup files, relative to the data directory (a trailing '/' marks a directory)
+    Setup(Vec<String>),
+    /// Upgrades an older layout, see migrate.rs
+    Migrate,
+    /// Moves a setup file aside and creates it again empty
+    Replace(String),
+    /// Moves a stray or unreadable file aside
+    Quarantine(PathBuf),
+    /// Empties a malformed credentials file
+    Truncate(PathBuf),
+    /// Makes a credentials file readable by its owner only
+    #[cfg(unix)]
+    Restrict(PathBuf),
+    /// Rewrites a journal with its good lines, quarantining the rejected ones
+    Journal { path: PathBuf, lines: Vec<String>, rejected: Vec<String> },
+    /// Writes back a local object from the data of its pending create
+    Restore { path: PathBuf, data: Value },
+    /// Journals the creation of a local object so --push-load uploads it
+    Rejournal { kind: ResourceKind, path: PathBuf },
+}
+
+#[derive(Debug)]
+struct Finding {
+    section: &'static str,
+    severity: Severity,
+    message: String,
+    repair: Option<Repair>,
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    fn add(&mut self, section: &'static str, severity: Severity, message: String, repair: Option<Repair>) {
+        self.findings.push(Finding { section, severity, message, repair });
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|f| f.severity == severity).count()
+    }
+}
+
+fn check_setup(report: &mut Report, data_dir: &Path) {
+    if !data_dir.exists() {
+        report.add("Setup", Severity::Error, format!("Data directory {} does not exist", data_dir.display()), Some(Repair::Setup(required_files())));
+        return;
+    }
+
+    let mut missing = Vec::new();
+    for file in required_files() {
+        let path = data_dir.join(&file);
+        if !path.exists() {
+            missing.push(file);
+        } else if file.ends_with('/') != path.is_dir() {
+            let expected = if file.ends_with('/') { "directory" } else { "file" };
+            report.add("Setup", Severity::Error, format!("{} should be a {}", file, expected), Some(Repair::Replace(file)));
+        }
+    }
+    if !missing.is_empty() {
+        report.add("Setup", Severity::Error, format!("{} setup file(s) missing: {}", missing.len(), missing.join(", ")), Some(Repair::Setup(missing)));
+    }
+
+    match migrate::read_version(data_dir) {
+        Ok(version) if version < migrate::CURRENT_VERSION => {
+            let message = format!("Data directory uses layout v{}, this release uses v{}", version, migrate::CURRENT_VERSION);
+            report.add("Setup", Severity::Error, message, Some(Repair::Migrate));
+        }
+        Ok(version) if version > migrate::CURRENT_VERSION => {
+            let message = format!("Data directory uses layout v{}, written by a newer release (this one knows up to v{})", version, migrate::CURRENT_VERSION);
+            report.add("Setup", Severity::Error, message, None);
+        }
+        Ok(_) => {}
+        Err(e) => report.add("Setup", Severity::Error, e.to_string(), None),
+    }
+}
+
+/// Checks the token and auto login files, returning the saved token and user ID when they look usable
+fn check_credentials(report: &mut Report, data_dir: &Path) -> Option<(String, String)> {
+    let mut session = None;
+    for (file, required) in [(client::AUTH_TOKENS_FILE, true), (client::AUTO_LOGIN_FILE, false)] {
+        let path = data_dir.join(file);
+        if !path.is_file() {
+            continue;
+        }
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                report.add("Credentials", Severity::Error, format!("{} is unreadable: {}", file, e), Some(Repair::Replace(file.to_string())));
+                continue;
+            }
+        };
+        if contents.trim().is_empty() {
+            if required {
+                report.add("Credentials", Severity::Warn, "Not logged in, run `archerdndsys --login`".to_string(), None);
+            }
+            continue;
+        }
+        match client::parse_pair(&contents) {
+            Some(pair) if required => session = Some(pair),
+            Some(_) => {}
+            None => {
+                report.add("Credentials", Severity::Error, format!("{} is malformed, expected one 'a,b' line (fixing clears it, log in again afterwards)", file), Some(Repair::Truncate(path.clone())));
+                continue;
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if fs::metadata(&path).is_ok_and(|m| m.permissions().mode() & 0o077 != 0) {
+                report.add("Credentials", Severity::Warn, format!("{} is readable by other users", file), Some(Repair::Restrict(path)));
+            }
+        }
+    }
+    session
+}
+
+fn check_line(kind: ResourceKind, line: &str) -> Result<(), String> {
+    let (method, endpoint, data) = client::parse_line(line).ok_or("not a journal record")?;
+    if !JOURNAL_METHODS.contains(&method.as_str()) {
+        return Err(format!("unknown method '{}'", method));
+    }
+    if !endpoint.starts_with(&kind.endpoint()) {
+        return Err(format!("{} is not a {} endpoint", endpoint, kind.name()));
+    }
+    match (method.as_str(), data) {
+        ("POST" | "PUT", None) => Err(format!("{} without data", method)),
+        (_, Some(data)) => match serde_json::from_str::<Value>(&data) {
+            Ok(Value::Object(_)) => Ok(()),
+            Ok(_) => Err("data is not a JSON object".to_string()),
+            Err(e) => Err(format!("data is not valid JSON ({})", e)),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Splits a line holding several records written without a newline between them
+fn split_merged(kind: ResourceKind, line: &str) -> Option<Vec<String>> {
+    let heads: Vec<String> = JOURNAL_METHODS.iter().map(|m| format!("{} {}", m, kind.endpoint())).collect();
+    let mut bounds: Vec<usize> = (1..line.len())
+        .filter(|&i| line.is_char_boundary(i) && heads.iter().any(|h| line[i..].starts_with(h.as_str())))
+        .collect();
+    if bounds.is_empty() {
+        return None;
+    }
+    bounds.insert(0, 0);
+    bounds.push(line.len());
+    let parts: Vec<String> = bounds.windows(2).map(|w| line[w[0]..w[1]].trim().to_string()).collect();
+    parts.iter().all(|p| check_line(kind, p).is_ok()).then_some(parts)
+}
+
+/// Checks one journal line by line. Returns the data of the last pending create per local ID
+fn check_journal(report: &mut Report, kind: ResourceKind) -> Result<HashMap<String, Value>, anyhow::Error> {
+    let path = kind.journal_path()?;
+    let mut creates = HashMap::new();
+    if !path.is_file() {
+        return Ok(creates);
+    }
+    let contents = match String::from_utf8(fs::read(&path)?) {
+        Ok(contents) => contents,
+        Err(_) => {
+            let relative = kind.relative_journal().unwrap_or_default();
+            report.add("Journals", Severity::Error, format!("{} journal is not readable text", kind.name()), Some(Repair::Replace(relative)));
+            return Ok(creates);
+        }
+    };
+
+    let mut lines = Vec::new();
+    let mut rejected = Vec::new();
+    let mut problems = Vec::new();
+    let mut merged = 0;
+    let mut blank = false;
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        match check_line(kind, line) {
+            Ok(()) => lines.push(line.to_string()),
+            Err(reason) => match split_merged(kind, line) {
+                Some(parts) => {
+                    merged += 1;
+                    lines.extend(parts);
+                }
+                None => {
+                    problems.push(format!("line {} {}", number + 1, reason));
+                    rejected.push(line.to_string());
+                }
+            },
+        }
+    }
+    if merged > 0 {
+        problems.push(format!("{} line(s) hold several records", merged));
+    }
+    let severity = if problems.is_empty() { Severity::Warn } else { Severity::Error };
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        problems.push("no final newline, the next record would join the last line".to_string());
+    }
+    if blank {
+        problems.push("blank lines".to_string());
+    }
+    if !problems.is_empty() {
+        let repair = Repair::Journal { path, lines: lines.clone(), rejected };
+        report.add("Journals", severity, format!("{} journal: {}", kind.name(), problems.join("; ")), Some(repair));
+    }
+
+    for line in &lines {
+        if let Some((method, _, Some(data))) = client::parse_line(line) {
+            let data: Value = serde_json::from_str(&data).unwrap_or(Value::Null);
+            if let (true, Some(id)) = (method == "POST", data.get("local_id").and_then(|id| id.as_str())) {
+                creates.insert(id.to_string(), data.clone());
+            }
+        }
+    }
+    Ok(creates)
+}
+
+/// Checks the object files of one kind against its journal, and flags anything else in its directory
+fn check_objects(report: &mut Report, kind: ResourceKind, creates: &HashMap<String, Value>) -> Result<(), anyhow::Error> {
+    let dir = kind.dir()?;
+    let journal = kind.journal_path()?;
+    let mut entries: Vec<PathBuf> = fs::read_dir(&dir)?.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    entries.sort();
+
+    let mut local_ids = Vec::new();
+    for path in entries {
+        let file = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+        if kind.journaled() && path == journal {
+            continue;
+        }
+        if atomic::is_leftover(&file) {
+            report.add("Objects", Severity::Warn, format!("{}/{} was left behind by an interrupted write", kind.name(), file), Some(Repair::Quarantine(path)));
+            continue;
+        }
+        if path.is_dir() || path.extension().and_then(|e| e.to_str()) != Some("json") {
+            report.add("Objects", Severity::Warn, format!("Stray file {}/{}", kind.name(), file), Some(Repair::Quarantine(path)));
+            continue;
+        }
+        let data: Value = match fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str(&c).ok()) {
+            Some(data) => data,
+            None => {
+                report.add("Objects", Severity::Error, format!("{}/{} is not valid JSON and breaks every command that loads {}", kind.name(), file, kind.name()), Some(Repair::Quarantine(path)));
+                continue;
+            }
+        };
+        let id = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let read_only = data.get("read_only").and_then(|r| r.as_bool()).unwrap_or(false);
+        if id.starts_with('_') || read_only {
+            continue;
+        }
+        if id.parse::<u64>().is_err() {
+            report.add("Objects", Severity::Warn, format!("{}/{} is neither a synced, local nor reference object", kind.name(), file), Some(Repair::Quarantine(path)));
+            continue;
+        }
+        local_ids.push(id.clone());
+        if kind.journaled() && !creates.contains_key(&id) {
+            let name = data.get("name").and_then(|n| n.as_str()).unwrap_or("[unnamed]");
+            report.add("Objects", Severity::Warn, format!("{}/{} ({}) has no pending create and will never be pushed", kind.name(), file, name), Some(Repair::Rejournal { kind, path }));
+        }
+    }
+
+    let mut orphaned: Vec<(&String, &Value)> = creates.iter().filter(|(id, _)| !local_ids.contains(id)).collect();
+    orphaned.sort_by_key(|(id, _)| id.as_str());
+    for (id, data) in orphaned {
+        report.add(
+            "Objects",
+            Severity::Warn,
+            format!("{} journal creates local object {} but its file is missing", kind.name(), id),
+            Some(Repair::Restore { path: dir.join(format!("{}.json", id)), data: data.clone() }),
+        );
+    }
+    Ok(())
+}
+
+fn check_saved_objs(report: &mut Report, data_dir: &Path) -> Result<(), anyhow::Error> {
+    let objects_dir = data_dir.join("saved_objs");
+    if !objects_dir.is_dir() {
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(&objects_dir)?.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let known = if path.is_dir() {
+            ResourceKind::ALL.iter().any(|k| k.name() == name)
+        } else {
+            OBJECTS_ROOT_FILES.contains(&name)
+        };
+        if !known {
+            report.add("Objects", Severity::Warn, format!("Stray entry saved_objs/{}", name), Some(Repair::Quarantine(path)));
+        }
+    }
+
+    for kind in ResourceKind::ALL {
+        if !kind.dir()?.is_dir() {
+            continue;
+        }
+        let creates = if kind.journaled() { check_journal(report, kind)? } else { HashMap::new() };
+        check_objects(report, kind, &creates)?;
+    }
+    Ok(())
+}
+
+fn check_combat(report: &mut Report, data_dir: &Path) {
+    if Combat::exists() {
+        if let Err(e) = Combat::load() {
+            report.add("Combat", Severity::Error, e.to_string(), Some(Repair::Quarantine(data_dir.join("combat.json"))));
+        }
+    }
+}
+
+async fn check_server(report: &mut Report, session: Option<(String, String)>) -> Result<(), anyhow::Error> {
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(SERVER_TIMEOUT_SECS)).build()?;
+    if let Err(e) = client.get(SERVER).send().await {
+        report.add("Server", Severity::Error, format!("{} is unreachable: {}", SERVER, e), None);
+        return Ok(());
+    }
+    let Some((token, user_id)) = session else { return Ok(()) };
+    let response = client.get(format!("{}/auth/is-logged-in", SERVER))
+        .json(&serde_json::json!({ "token": token, "user_id": user_id }))
+        .send()
+        .await;
+    match response {
+        Ok(r) if r.status().is_success() => {}
+        Ok(r) => report.add("Server", Severity::Warn, format!("The saved login was rejected ({}), run `archerdndsys --login`", r.status()), None),
+        Err(e) => report.add("Server", Severity::Error, format!("Could not check the saved login: {}", e), None),
+    }
+    Ok(())
+}
+
+/// Moves a file under the quarantine directory, keeping its path relative to the data directory
+fn quarantine(path: &Path, data_dir: &Path, quarantine_dir: &Path) -> Result<PathBuf, anyhow::Error> {
+    let relative = path.strip_prefix(data_dir).unwrap_or(path);
+    let target = quarantine_dir.join(relative);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+fn create(data_dir: &Path, file: &str) -> Result<(), anyhow::Error> {
+    let path = data_dir.join(file);
+    if file.ends_with('/') {
+        fs::create_dir_all(&path)?;
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::File::create(&path)?;
+    }
+    Ok(())
+}
+
+fn apply(repair: &Repair, data_dir: &Path, quarantine_dir: &Path) -> Result<String, anyhow::Error> {
+    match repair {
+        Repair::Setup(files) => {
+            fs::create_dir_all(data_dir)?;
+            for file in files {
+                create(data_dir, file)?;
+            }
+            Ok(format!("created {} setup file(s)", files.len()))
+        }
+        Repair::Migrate => {
+            let backup = migrate::migrate(data_dir)?;
+            Ok(format!("upgraded to layout v{}, the previous state is in {}", migrate::CURRENT_VERSION, backup.display()))
+        }
+        Repair::Replace(file) => {
+            let moved = quarantine(&data_dir.join(file), data_dir, quarantine_dir)?;
+            create(data_dir, file)?;
+            Ok(format!("moved the old {} to {} and created it again", file, moved.display()))
+        }
+        Repair::Quarantine(path) => {
+            let moved = quarantine(path, data_dir, quarantine_dir)?;
+            Ok(format!("moved to {}", moved.display()))
+        }
+        Repair::Truncate(path) => {
+            atomic::write(path, "")?;
+            Ok(format!("cleared {}", path.display()))
+        }
+        #[cfg(unix)]
+        Repair::Restrict(path) => {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            Ok(format!("restricted {} to its owner", path.display()))
+        }
+        Repair::Journal { path, lines, rejected } => {
+            let mut contents = lines.join("\n");
+            if !contents.is_empty() {
+                contents.push('\n');
+            }
+            atomic::write(path, contents)?;
+            if rejected.is_empty() {
+                return Ok(format!("rewrote {} with {} record(s)", path.display(), lines.len()));
+            }
+            let target = quarantine_dir.join(path.strip_prefix(data_dir).unwrap_or(path));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut kept = fs::read_to_string(&target).unwrap_or_default();
+            kept.push_str(&rejected.join("\n"));
+            kept.push('\n');
+            atomic::write(&target, kept)?;
+            Ok(format!("rewrote {} with {} record(s), rejected lines saved to {}", path.display(), lines.len(), target.display()))
+        }
+        Repair::Restore { path, data } => {
+            atomic::write(path, serde_json::to_string_pretty(data)?)?;
+            Ok(format!("restored {}", path.display()))
+        }
+        Repair::Rejournal { kind, path } => {
+            let id = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            let mut data: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+            if let Some(map) = data.as_object_mut() {
+                map.insert("local_id".to_string(), Value::String(id.to_string()));
+            }
+            atomic::write(path, serde_json::to_string_pretty(&data)?)?;
+            store::append_journal(kind.name(), "POST", &kind.endpoint(), Some(&data))?;
+            Ok(format!("journaled the creation of {}/{}", kind.name(), id))
+        }
+    }
+}
+
+/// Checks everything, prints a report and with `fix` applies every available repair
+pub async fn run(fix: bool) -> Result<(), anyhow::Error> {
+    let data_dir = store::data_dir()?;
+    let mut report = Report::default();
+
+    info!("Checking {}", data_dir.display());
+    check_setup(&mut report, &data_dir);
+    let session = check_credentials(&mut report, &data_dir);
+    check_saved_objs(&mut report, &data_dir)?;
+    check_combat(&mut report, &data_dir);
+    info!("Contacting {}", SERVER);
+    check_server(&mut report, session).await?;
+
+    for section in ["Setup", "Credentials", "Journals", "Objects", "Combat", "Server"] {
+        let findings: Vec<&Finding> = report.findings.iter().filter(|f| f.section == section).collect();
+        if findings.is_empty() {
+            info!("{}: ok", section);
+        }
+        for finding in findings {
+            let fixable = if finding.repair.is_some() { " (fixable)" } else { "" };
+            match finding.severity {
+                Severity::Error => error!("{}: {}{}", section, finding.message, fixable),
+                Severity::Warn => warn!("{}: {}{}", section, finding.message, fixable),
+            }
+        }
+    }
+
+    let errors = report.count(Severity::Error);
+    let warnings = report.count(Severity::Warn);
+    let fixable = report.findings.iter().filter(|f| f.repair.is_some()).count();
+    if report.findings.is_empty() {
+        info!("No problems found.");
+        return Ok(());
+    }
+    info!("Found {} error(s), {} warning(s), {} fixable", errors, warnings, fixable);
+    if !fix {
+        if fixable > 0 {
+            info!("Run `archerdndsys doctor --fix` to repair them.");
+        }
+        return if errors > 0 { Err(Error::Storage(format!("{} error(s) found", errors)).into()) } else { Ok(()) };
+    }
+
+    let quarantine_dir = data_dir.join(QUARANTINE_DIR).join(Local::now().format("%Y%m%d-%H%M%S").to_string());
+    let mut failed = 0;
+    for finding in &report.findings {
+        let Some(repair) = &finding.repair else { continue };
+        match apply(repair, &data_dir, &quarantine_dir) {
+            Ok(done) => info!("Fixed {}: {}", finding.message, done),
+            Err(e) => {
+                failed += 1;
+                error!("Could not fix {}: {}", finding.message, e);
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Storage(format!("{} repair(s) failed", failed)).into());
+    }
+    // Errors without a repair are still there after --fix
+    let unfixable = report.findings.iter().filter(|f| f.severity == Severity::Error && f.repair.is_none()).count();
+    if unfixable > 0 {
+        return Err(Error::Storage(format!("{} error(s) cannot be repaired automatically", unfixable)).into());
+    }
+    info!("Repairs done. Run `archerdndsys doctor` again to confirm.");
+    Ok(())
+}
@@ -0,0 +1,295 @@
+// Encounter builder: a party, a list of Creatures, and the DMG XP budget and difficulty for them
+// Encounters are ordinary local objects in saved_objs/Encounters/ and can be loaded into the combat tracker
+
+use crate::combat::{self, Combat, MonsterOptions};
+use crate::error::Error;
+use crate::models::{Character, Creature, Encounter, EncounterCreature, PartyMember};
+use crate::store::{self, StoredObject};
+use crossterm::style::Stylize;
+use log::{info, warn};
+use crate::logging;
+
+const KIND: &str = "Encounters";
+
+/// Easy, medium, hard and deadly XP thresholds per character, indexed by level - 1
+const XP_THRESHOLDS: [[u32; 4]; 20] = [
+    [25, 50, 75, 100],
+    [50, 100, 150, 200],
+    [75, 150, 225, 400],
+    [125, 250, 375, 500],
+    [250, 500, 750, 1100],
+    [300, 600, 900, 1400],
+    [350, 750, 1100, 1700],
+    [450, 900, 1400, 2100],
+    [550, 1100, 1600, 2400],
+    [600, 1200, 1900, 2800],
+    [800, 1600, 2400, 3600],
+    [1000, 2000, 3000, 4500],
+    [1100, 2200, 3400, 5100],
+    [1250, 2500, 3800, 5700],
+    [1400, 2800, 4300, 6400],
+    [1600, 3200, 4800, 7200],
+    [2000, 3900, 5900, 8800],
+    [2100, 4200, 6300, 9500],
+    [2400, 4900, 7300, 10900],
+    [2800, 5700, 8500, 12700],
+];
+
+/// Encounter multipliers in order; small parties step one up, large parties one down
+const MULTIPLIERS: [f64; 8] = [0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 4.0, 5.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    /// No party to measure against
+    Unknown,
+    Trivial,
+    Easy,
+    Medium,
+    Hard,
+    Deadly,
+}
+
+impl Difficulty {
+    fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Unknown => "Unknown (no party)",
+            Difficulty::Trivial => "Trivial",
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Deadly => "Deadly",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Budget {
+    /// Party totals for easy, medium, hard and deadly
+    pub thresholds: [u32; 4],
+    pub base_xp: u32,
+    pub monster_count: u32,
+    pub multiplier: f64,
+    pub adjusted_xp: u32,
+    pub difficulty: Difficulty,
+}
+
+/// Multiplier for the number of monsters, shifted for parties of fewer than three or more than five
+pub fn multiplier(monsters: u32, party_size: usize) -> f64 {
+    let index: usize = match monsters {
+        0 | 1 => 1,
+        2 => 2,
+        3..=6 => 3,
+        7..=10 => 4,
+        11..=14 => 5,
+        _ => 6,
+    };
+    let index = match party_size {
+        0..=2 => index + 1,
+        3..=5 => index,
+        _ => index - 1,
+    };
+    MULTIPLIERS[index]
+}
+
+pub fn budget(party: &[PartyMember], creatures: &[(Creature, u32)]) -> Budget {
+    let mut thresholds = [0; 4];
+    for member in party {
+        let row = XP_THRESHOLDS[member.level.clamp(1, 20) as usize - 1];
+        for (total, xp) in thresholds.iter_mut().zip(row) {
+            *total += xp;
+        }
+    }
+    let base_xp = creatures.iter().map(|(c, count)| c.xp().unwrap_or(0) * count).sum();
+    // Creatures worth nothing (CR 0 without a listed XP) do not count towards the multiplier
+    let monster_count = creatures.iter().filter(|(c, _)| c.xp().unwrap_or(0) > 0).map(|(_, count)| count).sum();
+    let multiplier = multiplier(monster_count, party.len());
+    let adjusted_xp = (base_xp as f64 * multiplier).round() as u32;
+    let difficulty = match adjusted_xp {
+        _ if party.is_empty() => Difficulty::Unknown,
+        xp if xp >= thresholds[3] => Difficulty::Deadly,
+        xp if xp >= thresholds[2] => Difficulty::Hard,
+        xp if xp >= thresholds[1] => Difficulty::Medium,
+        xp if xp >= thresholds[0] => Difficulty::Easy,
+        _ => Difficulty::Trivial,
+    };
+    Budget { thresholds, base_xp, monster_count, multiplier, adjusted_xp, difficulty }
+}
+
+fn find(query: &str) -> Result<(StoredObject, Encounter), anyhow::Error> {
+    let obj = store::find(KIND, query)?;
+    let encounter = obj.parse()?;
+    Ok((obj, encounter))
+}
+
+/// Builds party members from cached Characters and bare levels
+fn party(characters: &[String], levels: &[u8]) -> Result<Vec<PartyMember>, anyhow::Error> {
+    let mut party = Vec::new();
+    for query in characters {
+        let obj = store::find("Characters", query)?;
+        let character: Character = obj.parse()?;
+        party.push(PartyMember { name: character.name.clone(), level: character.total_level().max(1), character: Some(obj.id.clone()) });
+    }
+    for (i, level) in levels.iter().enumerate() {
+        if !(1..=20).contains(level) {
+            return Err(Error::Usage(format!("Party levels are between 1 and 20, found {}", level)).into());
+        }
+        party.push(PartyMember { name: format!("Level {} adventurer {}", level, i + 1), level: *level, character: None });
+    }
+    Ok(party)
+}
+
+/// Loads the stat blocks of an encounter's creatures, by stored ID and then by name
+fn creatures(encounter: &Encounter) -> Result<Vec<(Creature, u32)>, anyhow::Error> {
+    encounter.creatures.iter().map(|entry| {
+        let obj = store::find("Creatures", &entry.creature).or_else(|_| store::find("Creatures", &entry.name))
+            .map_err(|_| Error::NotFound(format!("{} is no longer in the cache", entry.name)))?;
+        Ok((obj.parse::<Creature>()?, entry.count))
+    }).collect()
+}
+
+fn save(obj: &StoredObject, encounter: &Encounter) -> Result<(), anyhow::Error> {
+    store::update(obj, serde_json::to_value(encounter)?)?;
+    Ok(())
+}
+
+pub fn create(name: &str, characters: &[String], levels: &[u8], description: Option<&str>) -> Result<(), anyhow::Error> {
+    let encounter = Encounter {
+        name: name.to_string(),
+        description: description.unwrap_or_default().to_string(),
+        party: party(characters, levels)?,
+        ..Default::default()
+    };
+    let obj = store::create(KIND, serde_json::to_value(&encounter)?)?;
+    info!("Created encounter {} ({})", encounter.name, obj.id);
+    if encounter.party.is_empty() {
+        warn!("The party is empty, set it with `archerdndsys encounter party`.");
+    }
+    Ok(())
+}
+
+/// Replaces the party, or with `append` adds to it
+pub fn set_party(query: &str, characters: &[String], levels: &[u8], append: bool) -> Result<(), anyhow::Error> {
+    let (obj, mut encounter) = find(query)?;
+    let members = party(characters, levels)?;
+    if !append {
+        encounter.party.clear();
+    }
+    encounter.party.extend(members);
+    save(&obj, &encounter)?;
+    info!("Party of {} now has {} member(s)", encounter.name, encounter.party.len());
+    show(query)
+}
+
+pub fn add(query: &str, creature: &str, count: u32) -> Result<(), anyhow::Error> {
+    let (obj, mut encounter) = find(query)?;
+    let found = store::find("Creatures", creature)?;
+    let count = count.max(1);
+    match encounter.creatures.iter_mut().find(|c| c.creature == found.id) {
+        Some(entry) => entry.count += count,
+        None => encounter.creatures.push(EncounterCreature { creature: found.id.clone(), name: found.name().to_string(), count }),
+    }
+    save(&obj, &encounter)?;
+    info!("Added {} x{} to {}", found.name(), count, encounter.name);
+    show(query)
+}
+
+/// Removes `count` copies of a creature, or all of them when no count is given
+pub fn remove(query: &str, creature: &str, count: Option<u32>) -> Result<(), anyhow::Error> {
+    let (obj, mut encounter) = find(query)?;
+    let position = encounter.creatures.iter()
+        .position(|c| c.name.eq_ignore_ascii_case(creature) || c.creature.trim_start_matches('_') == creature.trim_start_matches('_'))
+        .ok_or_else(|| Error::NotFound(format!("{} has no creature matching '{}'", encounter.name, creature)))?;
+    let entry = &mut encounter.creatures[position];
+    match count {
+        Some(n) if n < entry.count => entry.count -= n,
+        _ => {
+            encounter.creatures.remove(position);
+        }
+    }
+    save(&obj, &encounter)?;
+    show(query)
+}
+
+pub fn show(query: &str) -> Result<(), anyhow::Error> {
+    let (obj, encounter) = find(query)?;
+    let creatures = creatures(&encounter)?;
+    let budget = budget(&encounter.party, &creatures);
+
+    println!("{} ({})", logging::bold(&encounter.name), obj.id);
+    if !encounter.description.is_empty() {
+        println!("  {}", encounter.description);
+    }
+    let party: Vec<String> = encounter.party.iter().map(|m| format!("{} ({})", m.name, m.level)).collect();
+    println!("  Party: {}", if party.is_empty() { "none".to_string() } else { party.join(", ") });
+    println!("  Creatures:");
+    for (creature, count) in &creatures {
+        let xp = creature.xp().unwrap_or(0);
+        println!("    {}x {:<24} CR {:<4} {:>6} XP", count, creature.name, creature.challenge_rating, xp * count);
+    }
+    println!(
+        "  Thresholds: easy {}, medium {}, hard {}, deadly {}",
+        budget.thresholds[0], budget.thresholds[1], budget.thresholds[2], budget.thresholds[3]
+    );
+    println!(
+        "  XP: {} base x{} for {} monster(s) = {} adjusted",
+        budget.base_xp, budget.multiplier, budget.monster_count, budget.adjusted_xp
+    );
+    let difficulty = budget.difficulty.label().to_string();
+    let difficulty = match budget.difficulty {
+        Difficulty::Unknown => difficulty.dark_grey(),
+        Difficulty::Trivial | Difficulty::Easy => difficulty.green(),
+        Difficulty::Medium => difficulty.yellow(),
+        Difficulty::Hard | Difficulty::Deadly => difficulty.red(),
+    };
+    println!("  Difficulty: {}", logging::styled(difficulty.bold()));
+    if !encounter.party.is_empty() {
+        // Awarded XP is the unadjusted total, shared evenly
+        println!("  XP per character: {}", budget.base_xp / encounter.party.len() as u32);
+    }
+    Ok(())
+}
+
+pub fn list() -> Result<(), anyhow::Error> {
+    let encounters = store::load_all(KIND)?;
+    if encounters.is_empty() {
+        info!("No encounters saved.");
+    }
+    for obj in encounters {
+        let encounter: Encounter = obj.parse()?;
+        let summary = match creatures(&encounter) {
+            Ok(creatures) => {
+                let budget = budget(&encounter.party, &creatures);
+                format!("{}, {} adjusted XP", budget.difficulty.label(), budget.adjusted_xp)
+            }
+            Err(e) => e.to_string(),
+        };
+        println!("{:<24} {:<32} {}", obj.id, encounter.name, summary);
+    }
+    Ok(())
+}
+
+/// Starts a combat with the encounter's creatures and the party members taken from the cache
+pub fn run(query: &str, force: bool) -> Result<(), anyhow::Error> {
+    let (_, encounter) = find(query)?;
+    if Combat::exists() && !force {
+        return Err(Error::Conflict("A combat is already in progress. End it first or pass --force to discard it.".to_string()).into());
+    }
+    let mut combat = Combat::default();
+    let mut messages = Vec::new();
+    for member in &encounter.party {
+        let Some(id) = &member.character else { continue };
+        messages.extend(combat.add(combat::character_combatant(id)?, None));
+    }
+    for (entry, (creature, count)) in encounter.creatures.iter().zip(creatures(&encounter)?) {
+        for _ in 0..count {
+            let query = if store::find("Creatures", &entry.creature).is_ok() { &entry.creature } else { &creature.name };
+            messages.extend(combat.add(combat::monster_combatant(query, &MonsterOptions::default())?, None));
+        }
+    }
+    combat.save()?;
+    for message in messages {
+        info!("{}", message);
+    }
+    info!("Combat started from {}. Run `archerdndsys combat next` for the first turn.", encounter.name);
+    Ok(())
+}
@@ -0,0 +1,199 @@
+// Error categories shared by the library and the binary
+// Functions keep returning anyhow::Error; the variants below travel inside it and `classify` recovers the category,
+// so callers can tell "not logged in" from "server down" from "invalid content" without matching on message text
+
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Bad arguments, e.g. an unknown content type
+    #[error("{0}")]
+    Usage(String),
+    /// ~/.archerdndsys is missing, incomplete or in an unknown layout
+    #[error("{0}")]
+    Setup(String),
+    /// Not logged in, or the server rejected the login
+    #[error("{0}")]
+    Auth(String),
+    /// The server could not be reached or answered with a server error
+    #[error("{0}")]
+    Network(String),
+    /// A session_calls.txt line could not be read or sent
+    #[error("{0}")]
+    Journal(String),
+    /// Changes to one object contradict each other or the server's copy, or another command is busy with the same data
+    #[error("{0}")]
+    Conflict(String),
+    /// A file in the data directory or a backup is unreadable, corrupt or could not be written
+    #[error("{0}")]
+    Storage(String),
+    /// Content failed validation
+    #[error("{0}")]
+    Validation(String),
+    /// An object, backup or revision that does not exist
+    #[error("{0}")]
+    NotFound(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ErrorKind {
+    General,
+    Usage,
+    Setup,
+    Auth,
+    Network,
+    Journal,
+    Conflict,
+    Storage,
+    Validation,
+    NotFound,
+}
+
+impl ErrorKind {
+    /// Stable identifier for scripts; never renamed once released
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::General => "E_GENERAL",
+            ErrorKind::Usage => "E_USAGE",
+            ErrorKind::Setup => "E_SETUP",
+            ErrorKind::Auth => "E_AUTH",
+            ErrorKind::Network => "E_NETWORK",
+            ErrorKind::Journal => "E_JOURNAL",
+            ErrorKind::Conflict => "E_CONFLICT",
+            ErrorKind::Storage => "E_STORAGE",
+            ErrorKind::Validation => "E_VALIDATION",
+            ErrorKind::NotFound => "E_NOT_FOUND",
+        }
+    }
+
+    /// Process exit status; 2 matches clap's own status for bad arguments
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::General => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Setup => 3,
+            ErrorKind::Auth => 4,
+            ErrorKind::Network => 5,
+            ErrorKind::Journal => 6,
+            ErrorKind::Conflict => 7,
+            ErrorKind::Storage => 8,
+            ErrorKind::Validation => 9,
+            ErrorKind::NotFound => 10,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Usage(_) => ErrorKind::Usage,
+            Error::Setup(_) => ErrorKind::Setup,
+            Error::Auth(_) => ErrorKind::Auth,
+            Error::Network(_) => ErrorKind::Network,
+            Error::Journal(_) => ErrorKind::Journal,
+            Error::Conflict(_) => ErrorKind::Conflict,
+            Error::Storage(_) => ErrorKind::Storage,
+            Error::Validation(_) => ErrorKind::Validation,
+            Error::NotFound(_) => ErrorKind::NotFound,
+        }
+    }
+
+    /// Categorizes a failed server response by its status
+    pub fn from_status(status: reqwest::StatusCode, message: String) -> Error {
+        match status.as_u16() {
+            401 | 403 => Error::Auth(message),
+            404 => Error::NotFound(message),
+            409 | 412 => Error::Conflict(message),
+            400 | 422 => Error::Validation(message),
+            _ => Error::Network(message),
+        }
+    }
+}
+
+/// Category of an error: the first typed error in its chain, then I/O and HTTP errors from std and reqwest
+pub fn classify(error: &anyhow::Error) -> ErrorKind {
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<Error>() {
+            return e.kind();
+        }
+        if cause.is::<std::io::Error>() {
+            return ErrorKind::Storage;
+        }
+        if cause.is::<reqwest::Error>() {
+            return ErrorKind::Network;
+        }
+    }
+    ErrorKind::General
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn codes_and_exit_statuses_are_stable() {
+        let expected = [
+            (ErrorKind::General, "E_GENERAL", 1),
+            (ErrorKind::Usage, "E_USAGE", 2),
+            (ErrorKind::Setup, "E_SETUP", 3),
+            (ErrorKind::Auth, "E_AUTH", 4),
+            (ErrorKind::Network, "E_NETWORK", 5),
+            (ErrorKind::Journal, "E_JOURNAL", 6),
+            (ErrorKind::Conflict, "E_CONFLICT", 7),
+            (ErrorKind::Storage, "E_STORAGE", 8),
+            (ErrorKind::Validation, "E_VALIDATION", 9),
+            (ErrorKind::NotFound, "E_NOT_FOUND", 10),
+        ];
+        for (kind, code, exit) in expected {
+            assert_eq!((kind.code(), kind.exit_code()), (code, exit), "{:?}", kind);
+            assert_eq!(kind.to_string(), code);
+        }
+    }
+
+    #[test]
+    fn classify_finds_typed_errors_under_context() {
+        let error = Err::<(), _>(anyhow::Error::from(Error::NotFound("missing".to_string())))
+            .context("Lookup failed")
+            .context("Command failed")
+            .unwrap_err();
+        assert_eq!(classify(&error), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn classify_maps_io_errors_to_storage() {
+        let error = Err::<(), _>(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied"))
+            .context("Could not save")
+            .unwrap_err();
+        assert_eq!(classify(&error), ErrorKind::Storage);
+    }
+
+    #[test]
+    fn classify_falls_back_to_general() {
+        assert_eq!(classify(&anyhow::anyhow!("something else")), ErrorKind::General);
+    }
+
+    #[test]
+    fn from_status_maps_server_responses() {
+        let expected = [
+            (401, ErrorKind::Auth),
+            (403, ErrorKind::Auth),
+            (404, ErrorKind::NotFound),
+            (409, ErrorKind::Conflict),
+            (422, ErrorKind::Validation),
+            (500, ErrorKind::Network),
+        ];
+        for (status, kind) in expected {
+            let error = Error::from_status(StatusCode::from_u16(status).unwrap(), "failed".to_string());
+            assert_eq!(error.kind(), kind, "status {}", status);
+        }
+    }
+}
@@ -0,0 +1,646 @@
+// Renders cached content into Markdown, standalone HTML or Homebrewery markup for handouts
+// Every object is first turned into a format-neutral Block, the renderers only deal with Blocks
+
+use crate::atomic;
+use crate::error::Error;
+use crate::models::{AbilityScores, Background, Class, Condition, Creature, CreatureAction, Feat, Feature, Item, Race, Spell, Subclass, SpellcastingProgression, ABILITIES};
+use crate::store::{self, StoredObject};
+use std::collections::HashSet;
+use std::path::Path;
+use log::info;
+
+/// Content types that can be exported, in the order they appear in a document
+pub const EXPORT_TYPES: [&str; 10] = [
+    "Classes", "Subclasses", "Features", "Races", "Backgrounds", "Feats", "Spells", "Items", "Conditions", "Creatures",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Homebrewery,
+}
+
+/// Format-neutral rendering of one object
+#[derive(Debug, Default)]
+pub struct Block {
+    pub title: String,
+    pub subtitle: String,
+    /// Bold label / value pairs shown under the subtitle
+    pub properties: Vec<(String, String)>,
+    pub paragraphs: Vec<String>,
+    pub table: Option<(Vec<String>, Vec<Vec<String>>)>,
+    /// Named paragraphs after the body, e.g. "At Higher Levels"
+    pub sections: Vec<(String, String)>,
+    /// Headed lists of named paragraphs, e.g. a stat block's "Actions"
+    pub groups: Vec<(String, Vec<(String, String)>)>,
+}
+
+fn ordinal(n: u8) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+fn capitalize(text: &str) -> String {
+    crate::import::capitalize(text)
+}
+
+fn paragraphs(text: &str) -> Vec<String> {
+    text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).map(|l| l.to_string()).collect()
+}
+
+fn spell_block(spell: &Spell) -> Block {
+    let mut subtitle = if spell.level == 0 {
+        format!("{} cantrip", capitalize(&spell.school))
+    } else {
+        format!("{}-level {}", ordinal(spell.level), spell.school.to_lowercase())
+    };
+    if spell.ritual {
+        subtitle.push_str(" (ritual)");
+    }
+    let mut components = spell.components.join(", ");
+    if let Some(material) = &spell.material {
+        components.push_str(&format!(" ({})", material));
+    }
+    let mut block = Block {
+        title: spell.name.clone(),
+        subtitle,
+        properties: vec![
+            ("Casting Time".to_string(), spell.casting_time.clone()),
+            ("Range".to_string(), spell.range.clone()),
+            ("Components".to_string(), components),
+            ("Duration".to_string(), spell.duration.clone()),
+        ],
+        paragraphs: paragraphs(&spell.description),
+        ..Default::default()
+    };
+    if let Some(higher) = spell.higher_levels.as_ref().filter(|h| !h.is_empty()) {
+        let higher = higher.strip_prefix("At Higher Levels. ").unwrap_or(higher);
+        block.sections.push(("At Higher Levels".to_string(), higher.to_string()));
+    }
+    if !spell.classes.is_empty() {
+        block.sections.push(("Classes".to_string(), spell.classes.join(", ")));
+    }
+    block
+}
+
+fn item_block(item: &Item) -> Block {
+    let mut subtitle = format!("{}, {}", capitalize(&item.item_type), item.rarity.to_lowercase());
+    if item.requires_attunement {
+        subtitle.push_str(" (requires attunement)");
+    }
+    let mut properties = Vec::new();
+    if let Some(bonus) = item.bonus {
+        properties.push(("Bonus".to_string(), format!("{:+}", bonus)));
+    }
+    if let Some(damage) = &item.damage {
+        properties.push(("Damage".to_string(), format!("{} {}", damage.dice, damage.damage_type).trim().to_string()));
+    }
+    if let Some(charges) = item.charges {
+        properties.push(("Charges".to_string(), charges.to_string()));
+    }
+    Block { title: item.name.clone(), subtitle, properties, paragraphs: paragraphs(&item.description), ..Default::default() }
+}
+
+fn class_block(class: &Class) -> Block {
+    let mut properties = vec![
+        ("Hit Dice".to_string(), format!("1d{} per {} level", class.hit_die, class.name.to_lowercase())),
+        ("Hit Points at 1st Level".to_string(), format!("{} + your Constitution modifier", class.hit_die)),
+    ];
+    if !class.saving_throws.is_empty() {
+        let saves: Vec<String> = class.saving_throws.iter().map(|s| capitalize(s)).collect();
+        properties.push(("Saving Throws".to_string(), saves.join(", ")));
+    }
+    if let Some(progression) = class.spellcasting {
+        let progression = match progression {
+            SpellcastingProgression::Full => "full caster",
+            SpellcastingProgression::Half => "half caster",
+            SpellcastingProgression::Third => "third caster",
+            SpellcastingProgression::Pact => "pact magic",
+        };
+        let ability = class.spellcasting_ability.as_deref().map(capitalize).unwrap_or_default();
+        properties.push(("Spellcasting".to_string(), format!("{} {}", progression, if ability.is_empty() { String::new() } else { format!("({})", ability) }).trim().to_string()));
+    }
+    if !class.multiclass_prerequisites.is_empty() {
+        let prereqs: Vec<String> = class.multiclass_prerequisites.iter().map(|(a, m)| format!("{} {}", capitalize(a), m)).collect();
+        properties.push(("Multiclassing".to_string(), prereqs.join(if class.multiclass_prerequisites_any { " or " } else { " and " })));
+    }
+    if class.subclass_level > 0 {
+        properties.push(("Subclass".to_string(), format!("chosen at {} level", ordinal(class.subclass_level))));
+    }
+
+    let slot_columns = class.levels.iter().map(|l| l.spell_slots.len()).max().unwrap_or(0);
+    let has_cantrips = class.levels.iter().any(|l| l.cantrips_known.is_some());
+    let has_known = class.levels.iter().any(|l| l.spells_known.is_some());
+    let mut header = vec!["Level".to_string(), "Proficiency Bonus".to_string(), "Features".to_string()];
+    if has_cantrips {
+        header.push("Cantrips Known".to_string());
+    }
+    if has_known {
+        header.push("Spells Known".to_string());
+    }
+    header.extend((1..=slot_columns as u8).map(ordinal));
+    let rows = class.levels.iter().map(|level| {
+        let mut features = level.features.clone();
+        if level.ability_score_improvement {
+            features.push("Ability Score Improvement".to_string());
+        }
+        let mut row = vec![
+            ordinal(level.level),
+            format!("+{}", 2 + (level.level.max(1) - 1) / 4),
+            if features.is_empty() { "-".to_string() } else { features.join(", ") },
+        ];
+        if has_cantrips {
+            row.push(level.cantrips_known.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string()));
+        }
+        if has_known {
+            row.push(level.spells_known.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string()));
+        }
+        row.extend((0..slot_columns).map(|i| match level.spell_slots.get(i) {
+            Some(n) if *n > 0 => n.to_string(),
+            _ => "-".to_string(),
+        }));
+        row
+    }).collect();
+
+    Block {
+        title: class.name.clone(),
+        subtitle: "Class".to_string(),
+        properties,
+        paragraphs: paragraphs(&class.description),
+        table: if class.levels.is_empty() { None } else { Some((header, rows)) },
+        ..Default::default()
+    }
+}
+
+fn subclass_block(subclass: &Subclass) -> Block {
+    let mut features = subclass.features.clone();
+    features.sort_by_key(|f| f.level);
+    Block {
+        title: subclass.name.clone(),
+        subtitle: format!("{} subclass", subclass.class),
+        paragraphs: paragraphs(&subclass.description),
+        table: if features.is_empty() {
+            None
+        } else {
+            Some((
+                vec![format!("{} Level", subclass.class), "Feature".to_string()],
+                features.iter().map(|f| vec![ordinal(f.level), f.feature.clone()]).collect(),
+            ))
+        },
+        ..Default::default()
+    }
+}
+
+fn race_block(race: &Race) -> Block {
+    let mut properties = Vec::new();
+    if !race.ability_bonuses.is_empty() {
+        let bonuses: Vec<String> = race.ability_bonuses.iter().map(|(a, b)| format!("{} {:+}", capitalize(a), b)).collect();
+        properties.push(("Ability Score Increase".to_string(), bonuses.join(", ")));
+    }
+    properties.push(("Size".to_string(), race.size.clone()));
+    properties.push(("Speed".to_string(), format!("{} feet", race.speed)));
+    if !race.languages.is_empty() {
+        properties.push(("Languages".to_string(), race.languages.join(", ")));
+    }
+    if !race.traits.is_empty() {
+        properties.push(("Traits".to_string(), race.traits.join(", ")));
+    }
+    Block { title: race.name.clone(), subtitle: "Race".to_string(), properties, paragraphs: paragraphs(&race.description), ..Default::default() }
+}
+
+fn feature_block(feature: &Feature) -> Block {
+    let owner = match (&feature.class, &feature.subclass) {
+        (Some(class), Some(subclass)) => format!("{} ({}) feature", class, subclass),
+        (Some(class), None) => format!("{} feature", class),
+        _ => "Feature".to_string(),
+    };
+    let subtitle = match feature.level {
+        Some(level) => format!("{}, {} level", owner, ordinal(level)),
+        None => owner,
+    };
+    Block { title: feature.name.clone(), subtitle, paragraphs: paragraphs(&feature.description), ..Default::default() }
+}
+
+fn background_block(background: &Background) -> Block {
+    let mut properties = Vec::new();
+    for (label, values) in [
+        ("Skill Proficiencies", &background.skill_proficiencies),
+        ("Tool Proficiencies", &background.tool_proficiencies),
+        ("Languages", &background.languages),
+        ("Equipment", &background.equipment),
+    ] {
+        if let Some(value) = join_nonempty(values) {
+            properties.push((label.to_string(), value));
+        }
+    }
+    let mut block = Block {
+        title: background.name.clone(),
+        subtitle: "Background".to_string(),
+        properties,
+        paragraphs: paragraphs(&background.description),
+        ..Default::default()
+    };
+    if !background.feature_name.is_empty() {
+        block.sections.push((format!("Feature: {}", background.feature_name), background.feature_description.clone()));
+    }
+    block
+}
+
+fn feat_block(feat: &Feat) -> Block {
+    let mut properties = Vec::new();
+    if let Some(prerequisite) = &feat.prerequisite {
+        properties.push(("Prerequisite".to_string(), prerequisite.clone()));
+    }
+    let mut increases: Vec<String> = feat.ability_increases.iter().map(|(a, n)| format!("{} +{}", capitalize(a), n)).collect();
+    if !feat.ability_choices.is_empty() {
+        let choices: Vec<String> = feat.ability_choices.iter().map(|a| capitalize(a)).collect();
+        increases.push(format!("{} +1", choices.join(" or ")));
+    }
+    if let Some(value) = join_nonempty(&increases) {
+        properties.push(("Ability Score Increase".to_string(), value));
+    }
+    if feat.repeatable {
+        properties.push(("Repeatable".to_string(), "Yes".to_string()));
+    }
+    let mut paragraphs = paragraphs(&feat.description);
+    paragraphs.extend(feat.benefits.iter().map(|b| format!("- {}", b)));
+    Block { title: feat.name.clone(), subtitle: "Feat".to_string(), properties, paragraphs, ..Default::default() }
+}
+
+fn condition_block(condition: &Condition) -> Block {
+    let mut paragraphs = paragraphs(&condition.description);
+    paragraphs.extend(condition.effects.iter().map(|e| format!("- {}", e)));
+    Block {
+        title: condition.name.clone(),
+        subtitle: "Condition".to_string(),
+        paragraphs,
+        table: if condition.levels.is_empty() {
+            None
+        } else {
+            Some((
+                vec!["Level".to_string(), "Effect".to_string()],
+                condition.levels.iter().enumerate().map(|(i, effect)| vec![(i + 1).to_string(), effect.clone()]).collect(),
+            ))
+        },
+        ..Default::default()
+    }
+}
+
+fn join_nonempty(values: &[String]) -> Option<String> {
+    if values.is_empty() { None } else { Some(values.join(", ")) }
+}
+
+fn action_entries(actions: &[CreatureAction]) -> Vec<(String, String)> {
+    actions.iter().map(|a| {
+        let name = match a.cost {
+            Some(cost) if cost > 1 => format!("{} (Costs {} Actions)", a.name, cost),
+            _ => a.name.clone(),
+        };
+        (name, a.description.clone())
+    }).collect()
+}
+
+fn creature_block(creature: &Creature) -> Block {
+    let mut properties = Vec::new();
+    let armor = match &creature.armor_type {
+        Some(armor) => format!("{} ({})", creature.armor_class, armor),
+        None => creature.armor_class.to_string(),
+    };
+    properties.push(("Armor Class".to_string(), armor));
+    let hp = if creature.hit_dice.is_empty() {
+        creature.hit_points.to_string()
+    } else {
+        format!("{} ({})", creature.hit_points, creature.hit_dice)
+    };
+    properties.push(("Hit Points".to_string(), hp));
+    let speed: Vec<String> = creature.speed.iter()
+        .map(|(mode, feet)| if mode == "walk" { format!("{} ft.", feet) } else { format!("{} {} ft.", mode, feet) })
+        .collect();
+    properties.push(("Speed".to_string(), join_nonempty(&speed).unwrap_or_else(|| "0 ft.".to_string())));
+    let saves: Vec<String> = creature.saving_throws.iter().map(|(a, m)| format!("{} {:+}", capitalize(&a.chars().take(3).collect::<String>()), m)).collect();
+    let skills: Vec<String> = creature.skills.iter().map(|(s, m)| format!("{} {:+}", capitalize(s), m)).collect();
+    for (label, values) in [
+        ("Saving Throws", &saves),
+        ("Skills", &skills),
+        ("Damage Vulnerabilities", &creature.damage_vulnerabilities),
+        ("Damage Resistances", &creature.damage_resistances),
+        ("Damage Immunities", &creature.damage_immunities),
+        ("Condition Immunities", &creature.condition_immunities),
+        ("Senses", &creature.senses),
+    ] {
+        if let Some(value) = join_nonempty(values) {
+            properties.push((label.to_string(), value));
+        }
+    }
+    properties.push(("Languages".to_string(), join_nonempty(&creature.languages).unwrap_or_else(|| "-".to_string())));
+    let challenge = match creature.xp() {
+        Some(xp) => format!("{} ({} XP)", creature.challenge_rating, xp),
+        None => creature.challenge_rating.clone(),
+    };
+    properties.push(("Challenge".to_string(), challenge));
+
+    let header: Vec<String> = ABILITIES.iter().map(|a| a[..3].to_uppercase()).collect();
+    let scores = ABILITIES.iter().map(|a| {
+        let score = creature.abilities.get(a).unwrap_or(10);
+        format!("{} ({:+})", score, AbilityScores::modifier(score))
+    }).collect();
+
+    let mut groups = Vec::new();
+    if !creature.actions.is_empty() {
+        groups.push(("Actions".to_string(), action_entries(&creature.actions)));
+    }
+    if !creature.bonus_actions.is_empty() {
+        groups.push(("Bonus Actions".to_string(), action_entries(&creature.bonus_actions)));
+    }
+    if !creature.reactions.is_empty() {
+        groups.push(("Reactions".to_string(), action_entries(&creature.reactions)));
+    }
+    if !creature.legendary_actions.is_empty() {
+        let mut entries = vec![(String::new(), format!(
+            "The {} can take {} legendary actions, choosing from the options below. Only one legendary action can be used at a time and only at the end of another creature's turn. The {} regains spent legendary actions at the start of its turn.",
+            creature.name.to_lowercase(), creature.legendary_actions_per_round, creature.name.to_lowercase()
+        ))];
+        entries.extend(action_entries(&creature.legendary_actions));
+        groups.push(("Legendary Actions".to_string(), entries));
+    }
+    if !creature.lair_actions.is_empty() {
+        groups.push(("Lair Actions".to_string(), action_entries(&creature.lair_actions)));
+    }
+
+    let mut subtitle = format!("{} {}", capitalize(&creature.size), creature.creature_type.to_lowercase());
+    if !creature.alignment.is_empty() {
+        subtitle.push_str(&format!(", {}", creature.alignment.to_lowercase()));
+    }
+    Block {
+        title: creature.name.clone(),
+        subtitle,
+        properties,
+        paragraphs: paragraphs(&creature.description),
+        table: Some((header, vec![scores])),
+        sections: action_entries(&creature.traits),
+        groups,
+    }
+}
+
+pub fn block(obj: &StoredObject) -> Result<Block, anyhow::Error> {
+    Ok(match obj.kind.as_str() {
+        "Spells" => spell_block(&obj.parse()?),
+        "Items" => item_block(&obj.parse()?),
+        "Classes" => class_block(&obj.parse()?),
+        "Subclasses" => subclass_block(&obj.parse()?),
+        "Races" => race_block(&obj.parse()?),
+        "Features" => feature_block(&obj.parse()?),
+        "Creatures" => creature_block(&obj.parse()?),
+        "Backgrounds" => background_block(&obj.parse()?),
+        "Feats" => feat_block(&obj.parse()?),
+        "Conditions" => condition_block(&obj.parse()?),
+        other => return Err(Error::Usage(format!("{} cannot be exported", other)).into()),
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn markdown_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let mut out = format!("| {} |\n", header.join(" | "));
+    out.push_str(&format!("|{}\n", header.iter().map(|_| ":---:|").collect::<String>()));
+    for row in rows {
+        out.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    out
+}
+
+/// "***Name.*** text" followed by `end`; entries without a name are plain paragraphs
+fn named_paragraph(name: &str, text: &str, end: &str) -> String {
+    if name.is_empty() {
+        format!("{}{}", text, end)
+    } else {
+        format!("***{}.*** {}{}", name, text, end)
+    }
+}
+
+pub fn render_markdown(title: &str, blocks: &[Block]) -> String {
+    let mut out = format!("# {}\n\n", title);
+    for block in blocks {
+        out.push_str(&format!("## {}\n\n*{}*\n\n", block.title, block.subtitle));
+        for (label, value) in &block.properties {
+            out.push_str(&format!("- **{}:** {}\n", label, value));
+        }
+        if !block.properties.is_empty() {
+            out.push('\n');
+        }
+        for paragraph in &block.paragraphs {
+            out.push_str(&format!("{}\n\n", paragraph));
+        }
+        if let Some((header, rows)) = &block.table {
+            out.push_str(&markdown_table(header, rows));
+            out.push('\n');
+        }
+        for (heading, text) in &block.sections {
+            out.push_str(&format!("***{}.*** {}\n\n", heading, text));
+        }
+        for (heading, entries) in &block.groups {
+            out.push_str(&format!("### {}\n\n", heading));
+            for (name, text) in entries {
+                out.push_str(&named_paragraph(name, text, "\n\n"));
+            }
+        }
+    }
+    out
+}
+
+/// Homebrewery (V3 renderer) markup: spells and items as stat-block style entries, one page per content type
+pub fn render_homebrewery(title: &str, blocks: &[(String, Block)]) -> String {
+    let mut out = format!("# {}\n\n", title);
+    let mut current_kind = "";
+    for (kind, block) in blocks {
+        if kind != current_kind {
+            if !current_kind.is_empty() {
+                out.push_str("\\page\n\n");
+            }
+            out.push_str(&format!("## {}\n\n", kind));
+            current_kind = kind;
+        }
+        // Creatures use Homebrewery's own monster frame, with the ability scores as a plain table inside it
+        let monster = kind == "Creatures";
+        if monster {
+            out.push_str("{{monster,frame\n");
+        }
+        out.push_str(&format!("#### {}\n*{}*\n___\n", block.title, block.subtitle));
+        for (label, value) in &block.properties {
+            out.push_str(&format!("- **{}:** {}\n", label, value));
+        }
+        out.push_str(":\n");
+        for paragraph in &block.paragraphs {
+            out.push_str(&format!("{}\n:\n", paragraph));
+        }
+        match &block.table {
+            Some((header, rows)) if monster => out.push_str(&format!("___\n{}___\n", markdown_table(header, rows))),
+            Some((header, rows)) => out.push_str(&format!("{{{{classTable,frame\n##### The {}\n{}}}}}\n", block.title, markdown_table(header, rows))),
+            None => {}
+        }
+        for (heading, text) in &block.sections {
+            out.push_str(&format!("***{}.*** {}\n:\n", heading, text));
+        }
+        for (heading, entries) in &block.groups {
+            out.push_str(&format!("### {}\n", heading));
+            for (name, text) in entries {
+                out.push_str(&named_paragraph(name, text, "\n:\n"));
+            }
+        }
+        if monster {
+            out.push_str("}}\n");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+const STAT_BLOCK_CSS: &str = r#"
+body { background: #fdf1dc; color: #1a1a1a; font-family: "Bookman Old Style", Georgia, serif; max-width: 900px; margin: 2em auto; padding: 0 1em; }
+h1 { color: #58180d; font-variant: small-caps; border-bottom: 2px solid #c9ad6a; }
+.stat-block { background: #fdf1dc; border-top: 4px solid #e69a28; border-bottom: 4px solid #e69a28; box-shadow: 0 0 6px #867453; padding: 0.6em 1em; margin: 1.5em 0; break-inside: avoid; }
+.stat-block h2 { color: #7a200d; font-variant: small-caps; margin: 0; font-size: 1.6em; }
+.stat-block .subtitle { font-style: italic; margin: 0 0 0.4em 0; }
+.stat-block .rule { height: 5px; background: linear-gradient(10deg, #a73335, #922610 50%, transparent); margin: 0.5em 0; border: none; }
+.stat-block .property { margin: 0.15em 0; color: #7a200d; }
+.stat-block .property b { color: #7a200d; }
+.stat-block h3 { color: #7a200d; font-variant: small-caps; font-weight: normal; border-bottom: 1px solid #7a200d; margin: 0.8em 0 0.3em 0; }
+.stat-block table { border-collapse: collapse; width: 100%; margin: 0.6em 0; font-size: 0.9em; }
+.stat-block th { text-align: center; border-bottom: 1px solid #7a200d; }
+.stat-block td { text-align: center; padding: 0.15em 0.4em; }
+.stat-block tr:nth-child(even) td { background: #e0e5c1; }
+@media print { body { background: white; } .stat-block { box-shadow: none; } }
+"#;
+
+pub fn render_html(title: &str, blocks: &[Block]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape_html(title), STAT_BLOCK_CSS, escape_html(title)
+    );
+    for block in blocks {
+        out.push_str("<div class=\"stat-block\">\n");
+        out.push_str(&format!("<h2>{}</h2>\n<p class=\"subtitle\">{}</p>\n<hr class=\"rule\">\n", escape_html(&block.title), escape_html(&block.subtitle)));
+        for (label, value) in &block.properties {
+            out.push_str(&format!("<p class=\"property\"><b>{}.</b> {}</p>\n", escape_html(label), escape_html(value)));
+        }
+        if !block.properties.is_empty() {
+            out.push_str("<hr class=\"rule\">\n");
+        }
+        for paragraph in &block.paragraphs {
+            out.push_str(&format!("<p>{}</p>\n", escape_html(paragraph)));
+        }
+        if let Some((header, rows)) = &block.table {
+            out.push_str("<table>\n<tr>");
+            for cell in header {
+                out.push_str(&format!("<th>{}</th>", escape_html(cell)));
+            }
+            out.push_str("</tr>\n");
+            for row in rows {
+                out.push_str("<tr>");
+                for cell in row {
+                    out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                }
+                out.push_str("</tr>\n");
+            }
+            out.push_str("</table>\n");
+        }
+        for (heading, text) in &block.sections {
+            out.push_str(&format!("<p><b><i>{}.</i></b> {}</p>\n", escape_html(heading), escape_html(text)));
+        }
+        for (heading, entries) in &block.groups {
+            out.push_str(&format!("<h3>{}</h3>\n", escape_html(heading)));
+            for (name, text) in entries {
+                if name.is_empty() {
+                    out.push_str(&format!("<p>{}</p>\n", escape_html(text)));
+                } else {
+                    out.push_str(&format!("<p><b><i>{}.</i></b> {}</p>\n", escape_html(name), escape_html(text)));
+                }
+            }
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Resolves the objects to export: explicit queries, a whole collection (objects sharing a `source`), or both
+pub fn select(queries: &[String], kind: Option<&str>, collection: Option<&str>) -> Result<Vec<StoredObject>, anyhow::Error> {
+    let mut selected: Vec<StoredObject> = Vec::new();
+    for query in queries {
+        let obj = match kind {
+            Some(kind) => store::find(kind, query)?,
+            None => {
+                let mut found = None;
+                for kind in EXPORT_TYPES {
+                    match store::find(kind, query) {
+                        Ok(obj) => {
+                            found = Some(obj);
+                            break;
+                        }
+                        // Ambiguous names are reported rather than silently falling through to another type
+                        Err(e) if store::load_all(kind)?.iter().filter(|o| o.name().eq_ignore_ascii_case(query)).count() > 1 => return Err(e),
+                        Err(_) => {}
+                    }
+                }
+                found.ok_or_else(|| Error::NotFound(format!("No exportable object matches '{}'", query)))?
+            }
+        };
+        selected.push(obj);
+    }
+    if let Some(collection) = collection {
+        let kinds: Vec<&str> = match kind {
+            Some(kind) => vec![kind],
+            None => EXPORT_TYPES.to_vec(),
+        };
+        let before = selected.len();
+        for kind in kinds {
+            selected.extend(store::load_all(kind)?.into_iter().filter(|obj| {
+                obj.data.get("source").and_then(|s| s.as_str()).is_some_and(|s| s.eq_ignore_ascii_case(collection))
+            }));
+        }
+        if selected.len() == before {
+            return Err(Error::NotFound(format!("No objects found in collection '{}'", collection)).into());
+        }
+    }
+    if selected.is_empty() {
+        return Err(Error::Usage("Nothing to export, name objects or pass --collection".to_string()).into());
+    }
+
+    selected.sort_by_key(|obj| EXPORT_TYPES.iter().position(|k| *k == obj.kind).unwrap_or(EXPORT_TYPES.len()));
+    // An object named directly and again through its collection is only exported once
+    let mut seen = HashSet::new();
+    selected.retain(|obj| seen.insert((obj.kind.clone(), obj.id.clone())));
+    Ok(selected)
+}
+
+/// Renders the selection and writes it to `output` (or stdout when none is given)
+pub fn export(objects: &[StoredObject], format: ExportFormat, title: &str, output: Option<&Path>) -> Result<(), anyhow::Error> {
+    let mut blocks = Vec::new();
+    for obj in objects {
+        blocks.push((obj.kind.clone(), block(obj)?));
+    }
+    let document = match format {
+        ExportFormat::Markdown => render_markdown(title, &blocks.into_iter().map(|(_, b)| b).collect::<Vec<_>>()),
+        ExportFormat::Html => render_html(title, &blocks.into_iter().map(|(_, b)| b).collect::<Vec<_>>()),
+        ExportFormat::Homebrewery => render_homebrewery(title, &blocks),
+    };
+
+    match output {
+        Some(path) => {
+            atomic::write(path, document)
+                .map_err(|e| Error::Storage(format!("Could not write {}: {}", path.display(), e)))?;
+            info!("Exported {} object(s) to {}", objects.len(), path.display());
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+// Version history for locally edited objects
+// Every create and update through store.rs keeps the version it wrote as ~/.archerdndsys/history/(item type)/(id)/(rev).json.
+// Revisions are numbered from 1 per object; the first edit of an object without history also keeps the version it replaced.
+// Local IDs are reused once the highest one is deleted, so creating an object starts its history afresh
+
+use crate::atomic;
+use crate::error::Error;
+use crate::resource::{self, ResourceKind};
+use crate::store::{self, StoredObject};
+use chrono::{DateTime, Local};
+use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use log::info;
+use crate::logging;
+
+pub const HISTORY_DIR: &str = "history";
+/// Revisions kept per object, oldest are dropped first
+pub const HISTORY_KEEP: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub rev: u32,
+    pub saved: DateTime<Local>,
+    /// What produced this version, e.g. "created", "edited", "reverted to r3"
+    pub note: String,
+    pub data: Value,
+}
+
+fn object_dir(kind: &str, id: &str) -> Result<PathBuf, anyhow::Error> {
+    Ok(store::data_dir()?.join(HISTORY_DIR).join(kind).join(id))
+}
+
+/// Every kept revision of an object, oldest first
+pub fn revisions(kind: &str, id: &str) -> Result<Vec<Revision>, anyhow::Error> {
+    let dir = object_dir(kind, id)?;
+    let Ok(entries) = fs::read_dir(&dir) else { return Ok(Vec::new()) };
+    let mut revisions = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let contents = fs::read_to_string(&path)?;
+        let revision: Revision = serde_json::from_str(&contents)
+            .map_err(|e| Error::Storage(format!("Could not parse {}: {}", path.display(), e)))?;
+        revisions.push(revision);
+    }
+    revisions.sort_by_key(|r| r.rev);
+    Ok(revisions)
+}
+
+fn write(kind: &str, id: &str, rev: u32, note: &str, data: &Value) -> Result<(), anyhow::Error> {
+    let dir = object_dir(kind, id)?;
+    fs::create_dir_all(&dir)?;
+    let revision = Revision { rev, saved: Local::now(), note: note.to_string(), data: data.clone() };
+    atomic::write(dir.join(format!("{}.json", rev)), serde_json::to_string_pretty(&revision)?)?;
+    Ok(())
+}
+
+/// Drops every revision kept under an ID, before a new object takes it over
+pub fn clear(kind: &str, id: &str) -> Result<(), anyhow::Error> {
+    let dir = object_dir(kind, id)?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+/// Keeps a version about to be written. `previous` is the version being replaced, kept as the first revision when the
+/// object has no history yet (e.g. a synced object edited for the first time)
+pub fn record(kind: &str, id: &str, previous: Option<&Value>, data: &Value, note: &str) -> Result<(), anyhow::Error> {
+    let existing = revisions(kind, id)?;
+    let mut next = existing.last().map(|r| r.rev + 1).unwrap_or(1);
+    if existing.is_empty() {
+        if let Some(previous) = previous.filter(|p| *p != data) {
+            write(kind, id, next, "original", previous)?;
+            next += 1;
+        }
+    } else if existing.last().is_some_and(|r| &r.data == data) {
+        return Ok(());
+    }
+    write(kind, id, next, note, data)?;
+
+    let kept = revisions(kind, id)?;
+    let dir = object_dir(kind, id)?;
+    for old in kept.iter().take(kept.len().saturating_sub(HISTORY_KEEP)) {
+        fs::remove_file(dir.join(format!("{}.json", old.rev)))?;
+    }
+    Ok(())
+}
+
+/// Finds an object for the history commands, in one type or across every journaled type
+pub fn find(query: &str, kind: Option<&str>) -> Result<StoredObject, anyhow::Error> {
+    if let Some(kind) = kind {
+        return store::find(resource::kind(kind)?.name(), query);
+    }
+    let mut found = Vec::new();
+    for kind in ResourceKind::ALL.into_iter().filter(|k| k.journaled()) {
+        if let Ok(obj) = store::find(kind.name(), query) {
+            found.push(obj);
+        }
+    }
+    match found.len() {
+        0 => Err(Error::NotFound(format!("No object found matching '{}'", query)).into()),
+        1 => Ok(found.remove(0)),
+        _ => Err(Error::Usage(format!(
+            "'{}' matches objects in {}, pick one with --type",
+            query,
+            found.iter().map(|o| o.kind.as_str()).collect::<Vec<_>>().join(", ")
+        )).into()),
+    }
+}
+
+fn revision(obj: &StoredObject, rev: u32) -> Result<Revision, anyhow::Error> {
+    let revisions = revisions(&obj.kind, &obj.id)?;
+    if revisions.is_empty() {
+        return Err(Error::NotFound(format!("{} ({}/{}) has no history. Only objects edited locally keep versions.", obj.name(), obj.kind, obj.id)).into());
+    }
+    revisions.into_iter().find(|r| r.rev == rev).ok_or_else(|| Error::NotFound(format!(
+        "{} ({}/{}) has no revision {}. See `archerdndsys history {}`.", obj.name(), obj.kind, obj.id, rev, obj.id
+    )).into())
+}
+
+pub fn print_history(query: &str, kind: Option<&str>) -> Result<(), anyhow::Error> {
+    let obj = find(query, kind)?;
+    let revisions = revisions(&obj.kind, &obj.id)?;
+    if revisions.is_empty() {
+        info!("{} ({}/{}) has no history yet.", obj.name(), obj.kind, obj.id);
+        return Ok(());
+    }
+    println!("{}", logging::bold(format!("{} ({}/{})", obj.name(), obj.kind, obj.id)));
+    println!("{}", logging::bold(format!("{:>5}  {:<17} {:<26} {}", "REV", "SAVED", "NOTE", "CHANGES")));
+    let current = revisions.iter().rev().find(|r| r.data == obj.data).map(|r| r.rev);
+    let mut previous: Option<&Value> = None;
+    for revision in &revisions {
+        let changes = match previous {
+            Some(previous) => {
+                let mut paths: Vec<String> = changes(previous, &revision.data, "").into_iter().map(|c| c.path).collect();
+                paths.dedup();
+                summarize(&paths)
+            }
+            None => String::new(),
+        };
+        println!(
+            "{:>5}  {:<17} {:<26} {}",
+            format!("r{}", revision.rev),
+            revision.saved.format("%Y-%m-%d %H:%M").to_string(),
+            format!("{}{}", revision.note, if current == Some(revision.rev) { " (current)" } else { "" }),
+            changes
+        );
+        previous = Some(&revision.data);
+    }
+    Ok(())
+}
+
+fn summarize(paths: &[String]) -> String {
+    const SHOWN: usize = 4;
+    let mut summary = paths.iter().take(SHOWN).cloned().collect::<Vec<_>>().join(", ");
+    if paths.len() > SHOWN {
+        summary.push_str(&format!(" and {} more", paths.len() - SHOWN));
+    }
+    summary
+}
+
+struct Change {
+    path: String,
+    old: Option<Value>,
+    new: Option<Value>,
+}
+
+/// Field-level differences between two versions; objects are compared by key and arrays by position
+fn changes(old: &Value, new: &Value, path: &str) -> Vec<Change> {
+    let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))).collect();
+            keys.sort();
+            keys.into_iter().flat_map(|key| match (a.get(key), b.get(key)) {
+                (Some(x), Some(y)) => changes(x, y, &join(key)),
+                (x, y) => vec![Change { path: join(key), old: x.cloned(), new: y.cloned() }],
+            }).collect()
+        }
+        (Value::Array(a), Value::Array(b)) => (0..a.len().max(b.len())).flat_map(|i| {
+            let key = format!("{}[{}]", path, i);
+            match (a.get(i), b.get(i)) {
+                (Some(x), Some(y)) => changes(x, y, &key),
+                (x, y) => vec![Change { path: key, old: x.cloned(), new: y.cloned() }],
+            }
+        }).collect(),
+        _ if old == new => Vec::new(),
+        _ => vec![Change { path: if path.is_empty() { "(whole object)".to_string() } else { path.to_string() }, old: Some(old.clone()), new: Some(new.clone()) }],
+    }
+}
+
+/// Shows what changed from revision `rev` to `against` (the current version when not given)
+pub fn diff(query: &str, kind: Option<&str>, rev: u32, against: Option<u32>) -> Result<(), anyhow::Error> {
+    let obj = find(query, kind)?;
+    let from = revision(&obj, rev)?;
+    let (to_label, to_data) = match against {
+        Some(against) => (format!("r{}", against), revision(&obj, against)?.data),
+        None => ("current".to_string(), obj.data.clone()),
+    };
+    println!("{}", logging::bold(format!("{} ({}/{}): r{} -> {}", obj.name(), obj.kind, obj.id, rev, to_label)));
+    let changes = changes(&from.data, &to_data, "");
+    if changes.is_empty() {
+        info!("No differences.");
+        return Ok(());
+    }
+    for change in changes {
+        if let Some(old) = change.old {
+            println!("{}", logging::styled(format!("- {}: {}", change.path, old).red()));
+        }
+        if let Some(new) = change.new {
+            println!("{}", logging::styled(format!("+ {}: {}", change.path, new).green()));
+        }
+    }
+    Ok(())
+}
+
+/// Writes revision `rev` back as the current version; the write is journaled like any other edit
+pub fn revert(query: &str, kind: Option<&str>, rev: u32) -> Result<(), anyhow::Error> {
+    let obj = find(query, kind)?;
+    let target = revision(&obj, rev)?;
+    if target.data == obj.data {
+        info!("{} already matches r{}.", obj.name(), rev);
+        return Ok(());
+    }
+    let reverted = store::update_noted(&obj, target.data, &format!("reverted to r{}", rev))?;
+    info!("Reverted {} ({}/{}) to r{}", reverted.name(), reverted.kind, reverted.id, rev);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::with_setup;
+    use serde_json::json;
+
+    fn spell(name: &str) -> Value {
+        json!({ "name": name, "level": 1, "school": "evocation", "description": "A test spell." })
+    }
+
+    #[test]
+    fn pruning_keeps_history_keep_revisions() {
+        with_setup(|_| {
+            let writes = HISTORY_KEEP as u32 + 5;
+            for n in 1..=writes {
+                record("Spells", "1", None, &json!({ "n": n }), "edited").unwrap();
+            }
+            let kept = revisions("Spells", "1").unwrap();
+            assert_eq!(kept.len(), HISTORY_KEEP);
+            assert_eq!(kept.first().unwrap().rev, 6);
+            assert_eq!(kept.last().unwrap().rev, writes);
+        });
+    }
+
+    #[test]
+    fn unchanged_write_records_nothing() {
+        with_setup(|_| {
+            record("Spells", "1", None, &json!({ "n": 1 }), "created").unwrap();
+            record("Spells", "1", Some(&json!({ "n": 1 })), &json!({ "n": 1 }), "edited").unwrap();
+            assert_eq!(revisions("Spells", "1").unwrap().len(), 1);
+        });
+    }
+
+    #[test]
+    fn first_edit_keeps_the_original() {
+        with_setup(|_| {
+            record("Spells", "_a1", Some(&json!({ "n": 1 })), &json!({ "n": 2 }), "edited").unwrap();
+            let notes: Vec<String> = revisions("Spells", "_a1").unwrap().into_iter().map(|r| r.note).collect();
+            assert_eq!(notes, ["original", "edited"]);
+        });
+    }
+
+    #[test]
+    fn revert_adds_a_revision_and_journals_it() {
+        with_setup(|_| {
+            let obj = store::create("Spells", spell("Bolt")).unwrap();
+            store::update(&obj, spell("Renamed Bolt")).unwrap();
+
+            revert(&obj.id, Some("Spells"), 1).unwrap();
+
+            let history = revisions("Spells", &obj.id).unwrap();
+            let last = history.last().unwrap();
+            assert_eq!((last.rev, last.note.as_str()), (3, "reverted to r1"));
+            assert_eq!(last.data, history[0].data);
+            assert_eq!(store::find("Spells", &obj.id).unwrap().name(), "Bolt");
+            let journal = fs::read_to_string(store::journal_path("Spells").unwrap()).unwrap();
+            assert_eq!(journal.lines().count(), 3);
+        });
+    }
+
+    #[test]
+    fn reused_local_id_starts_a_fresh_history() {
+        with_setup(|_| {
+            let first = store::create("Spells", spell("Bolt")).unwrap();
+            store::update(&first, spell("Renamed Bolt")).unwrap();
+            fs::remove_file(&first.path).unwrap();
+
+            let second = store::create("Spells", spell("Ward")).unwrap();
+
+            assert_eq!(second.id, first.id);
+            let history = revisions("Spells", &second.id).unwrap();
+            assert_eq!(history.len(), 1);
+            assert_eq!((history[0].rev, history[0].note.as_str()), (1, "created"));
+            assert_eq!(history[0].data["name"], "Ward");
+        });
+    }
+}
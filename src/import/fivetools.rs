@@ -0,0 +1,787 @@
+// Importer for 5etools homebrew JSON (the format used by the 5etools homebrew repository)
+
+use crate::error::Error;
+use crate::import::{ability_name, capitalize, lookup, ImportReport, Importer, SCHOOLS};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const DAMAGE_TYPES: [(&str, &str); 13] = [
+    ("A", "acid"), ("B", "bludgeoning"), ("C", "cold"), ("F", "fire"), ("O", "force"),
+    ("L", "lightning"), ("N", "necrotic"), ("P", "piercing"), ("I", "poison"),
+    ("Y", "psychic"), ("R", "radiant"), ("S", "slashing"), ("T", "thunder"),
+];
+
+const ITEM_TYPES: [(&str, &str); 16] = [
+    ("M", "melee weapon"), ("R", "ranged weapon"), ("A", "ammunition"), ("LA", "light armor"),
+    ("MA", "medium armor"), ("HA", "heavy armor"), ("S", "shield"), ("P", "potion"),
+    ("RG", "ring"), ("RD", "rod"), ("SC", "scroll"), ("ST", "staff"), ("WD", "wand"),
+    ("G", "adventuring gear"), ("INS", "instrument"), ("T", "tool"),
+];
+
+/// Attack kinds of `{@atk ...}` tags in stat blocks
+const ATTACK_TYPES: [(&str, &str); 6] = [
+    ("mw", "Melee Weapon Attack:"), ("rw", "Ranged Weapon Attack:"), ("mw,rw", "Melee or Ranged Weapon Attack:"),
+    ("ms", "Melee Spell Attack:"), ("rs", "Ranged Spell Attack:"), ("ms,rs", "Melee or Ranged Spell Attack:"),
+];
+
+const SIZES: [(&str, &str); 6] = [
+    ("T", "Tiny"), ("S", "Small"), ("M", "Medium"), ("L", "Large"), ("H", "Huge"), ("G", "Gargantuan"),
+];
+
+const ALIGNMENTS: [(&str, &str); 7] = [
+    ("L", "lawful"), ("N", "neutral"), ("C", "chaotic"), ("G", "good"), ("E", "evil"),
+    ("U", "unaligned"), ("A", "any alignment"),
+];
+
+/// Sections of a homebrew file this importer does not map
+const UNSUPPORTED_SECTIONS: [&str; 4] = ["optionalfeature", "deity", "object", "vehicle"];
+
+/// Replaces 5etools inline tags such as "{@damage 8d6}" or "{@spell fireball|phb}" with their display text
+pub fn strip_tags(text: &str) -> String {
+    let mut text = text.to_string();
+    while let Some(start) = text.rfind("{@") {
+        let Some(len) = text[start..].find('}') else { break };
+        let inner = &text[start + 2..start + len];
+        let (tag, content) = inner.split_once(' ').unwrap_or((inner, ""));
+        let parts: Vec<&str> = content.split('|').collect();
+        let first = parts.first().copied().unwrap_or("");
+        let display = match tag {
+            // {@scaledamage 8d6|3-9|1d6} displays the per-level increment
+            "scaledamage" | "scaledice" => parts.get(2).copied().unwrap_or(first).to_string(),
+            // Stat block notation: "{@atk mw} {@hit 4} to hit ... {@h}5 (1d6 + 2)", "{@dc 13}"
+            "atk" => lookup(&ATTACK_TYPES, first).unwrap_or("Attack:").to_string(),
+            "hit" => format!("+{}", first.trim_start_matches('+')),
+            "h" => "Hit: ".to_string(),
+            "dc" => format!("DC {}", first),
+            _ => first.to_string(),
+        };
+        text.replace_range(start..start + len + 1, &display);
+    }
+    text
+}
+
+/// Flattens an "entries" tree (strings, named sub-entries, lists) into plain text
+pub fn entries_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => strip_tags(s),
+        Value::Array(entries) => entries.iter().map(entries_text).filter(|t| !t.is_empty()).collect::<Vec<_>>().join("\n"),
+        Value::Object(obj) => {
+            if let Some(items) = obj.get("items").and_then(|i| i.as_array()) {
+                return items.iter().map(|i| format!("- {}", entries_text(i))).collect::<Vec<_>>().join("\n");
+            }
+            let body = obj.get("entries").or(obj.get("entry")).map(entries_text).unwrap_or_default();
+            match obj.get("name").and_then(|n| n.as_str()) {
+                Some(name) => format!("{}. {}", name, body),
+                None => body,
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+fn first_dice(text: &str) -> Option<String> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == '.' || c == '(' || c == ')')
+        .find(|w| crate::dice::DiceExpr::parse(w).is_some_and(|d| d.count > 0))
+        .map(|w| w.to_string())
+}
+
+fn plural(amount: u64, unit: &str) -> String {
+    if amount == 1 { format!("1 {}", unit) } else { format!("{} {}s", amount, unit) }
+}
+
+fn casting_time(time: &Value) -> String {
+    let Some(first) = time.as_array().and_then(|t| t.first()) else { return String::new() };
+    let number = first.get("number").and_then(|n| n.as_u64()).unwrap_or(1);
+    match first.get("unit").and_then(|u| u.as_str()).unwrap_or("action") {
+        "bonus" => format!("{} bonus action", number),
+        "reaction" => format!("{} reaction", number),
+        "action" => format!("{} action", number),
+        unit => plural(number, unit),
+    }
+}
+
+/// Returns (range, area)
+fn range_and_area(range: &Value) -> (String, Option<String>) {
+    let kind = range.get("type").and_then(|t| t.as_str()).unwrap_or("special");
+    let distance = range.get("distance");
+    let dist_type = distance.and_then(|d| d.get("type")).and_then(|t| t.as_str()).unwrap_or("");
+    let amount = distance.and_then(|d| d.get("amount")).and_then(|a| a.as_u64()).unwrap_or(0);
+    match kind {
+        "point" => (match dist_type {
+            "feet" => format!("{} feet", amount),
+            "miles" => plural(amount, "mile"),
+            "self" => "Self".to_string(),
+            "touch" => "Touch".to_string(),
+            other => capitalize(other),
+        }, None),
+        "radius" | "sphere" | "cone" | "line" | "cube" | "hemisphere" | "cylinder" => {
+            let shape = if kind == "sphere" || kind == "hemisphere" || kind == "cylinder" { "radius" } else { kind };
+            let area = format!("{}-foot {}", amount, shape);
+            (format!("Self ({})", area), Some(area))
+        }
+        other => (capitalize(other), None),
+    }
+}
+
+/// Returns (duration, concentration)
+fn duration(duration: &Value) -> (String, bool) {
+    let Some(first) = duration.as_array().and_then(|d| d.first()) else { return (String::new(), false) };
+    let concentration = first.get("concentration").and_then(|c| c.as_bool()).unwrap_or(false);
+    let text = match first.get("type").and_then(|t| t.as_str()).unwrap_or("special") {
+        "instant" => "Instantaneous".to_string(),
+        "permanent" => "Until dispelled".to_string(),
+        "timed" => {
+            let unit = first.pointer("/duration/type").and_then(|t| t.as_str()).unwrap_or("round");
+            let amount = first.pointer("/duration/amount").and_then(|a| a.as_u64()).unwrap_or(1);
+            if concentration {
+                format!("Concentration, up to {}", plural(amount, unit))
+            } else {
+                plural(amount, unit)
+            }
+        }
+        other => capitalize(other),
+    };
+    (text, concentration)
+}
+
+fn map_spell(spell: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("spell", spell, &[
+        "name", "level", "school", "time", "range", "components", "duration", "meta", "entries",
+        "entriesHigherLevel", "classes", "damageInflict", "source", "page",
+    ]);
+
+    let (range, area) = range_and_area(spell.get("range").unwrap_or(&Value::Null));
+    let (duration, concentration) = duration(spell.get("duration").unwrap_or(&Value::Null));
+    let description = entries_text(spell.get("entries").unwrap_or(&Value::Null));
+
+    let mut components = Vec::new();
+    let mut material = None;
+    if let Some(c) = spell.get("components") {
+        if c.get("v").and_then(|v| v.as_bool()).unwrap_or(false) { components.push("V"); }
+        if c.get("s").and_then(|v| v.as_bool()).unwrap_or(false) { components.push("S"); }
+        if let Some(m) = c.get("m") {
+            components.push("M");
+            material = m.as_str().map(|s| s.to_string())
+                .or_else(|| m.get("text").and_then(|t| t.as_str()).map(|s| s.to_string()));
+        }
+    }
+
+    let classes: Vec<String> = spell.pointer("/classes/fromClassList")
+        .and_then(|c| c.as_array())
+        .map(|list| list.iter().filter_map(|c| c.get("name").and_then(|n| n.as_str()).map(|n| n.to_string())).collect())
+        .unwrap_or_default();
+
+    let mut out = json!({
+        "name": spell.get("name").cloned().unwrap_or_default(),
+        "level": spell.get("level").and_then(|l| l.as_u64()).unwrap_or(0),
+        "school": spell.get("school").and_then(|s| s.as_str()).and_then(|s| lookup(&SCHOOLS, s)).unwrap_or(""),
+        "casting_time": casting_time(spell.get("time").unwrap_or(&Value::Null)),
+        "range": range,
+        "components": components,
+        "duration": duration,
+        "concentration": concentration,
+        "ritual": spell.pointer("/meta/ritual").and_then(|r| r.as_bool()).unwrap_or(false),
+        "description": description,
+        "classes": classes,
+    });
+    let map = out.as_object_mut().expect("json object");
+    if let Some(material) = material {
+        map.insert("material".to_string(), json!(material));
+    }
+    if let Some(area) = area {
+        map.insert("area".to_string(), json!(area));
+    }
+    if let Some(higher) = spell.get("entriesHigherLevel") {
+        map.insert("higher_levels".to_string(), json!(entries_text(higher)));
+    }
+    let damage_type = spell.get("damageInflict").and_then(|d| d.as_array()).and_then(|d| d.first()).and_then(|d| d.as_str());
+    if let (Some(damage_type), Some(dice)) = (damage_type, first_dice(&description)) {
+        map.insert("damage".to_string(), json!({ "dice": dice, "damage_type": damage_type }));
+    }
+    out
+}
+
+fn parse_bonus(value: Option<&Value>) -> Option<i64> {
+    value.and_then(|v| v.as_str().and_then(|s| s.trim_start_matches('+').parse().ok()).or(v.as_i64()))
+}
+
+fn map_item(item: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("item", item, &[
+        "name", "type", "rarity", "reqAttune", "entries", "wondrous", "bonusWeapon", "bonusAc",
+        "bonusSpellAttack", "dmg1", "dmgType", "charges", "source", "page",
+    ]);
+
+    let item_type = match item.get("type").and_then(|t| t.as_str()) {
+        Some(code) => lookup(&ITEM_TYPES, code.split('|').next().unwrap_or(code)).unwrap_or("gear").to_string(),
+        None if item.get("wondrous").and_then(|w| w.as_bool()).unwrap_or(false) => "wondrous item".to_string(),
+        None => "gear".to_string(),
+    };
+    let rarity = match item.get("rarity").and_then(|r| r.as_str()).unwrap_or("none") {
+        "none" => "common".to_string(),
+        r if r.starts_with("unknown") => "varies".to_string(),
+        r => r.to_string(),
+    };
+    let attunement = match item.get("reqAttune") {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(_)) => true,
+        _ => false,
+    };
+
+    let mut out = json!({
+        "name": item.get("name").cloned().unwrap_or_default(),
+        "item_type": item_type,
+        "rarity": rarity,
+        "requires_attunement": attunement,
+        "description": entries_text(item.get("entries").unwrap_or(&Value::Null)),
+    });
+    let map = out.as_object_mut().expect("json object");
+    if let Some(bonus) = parse_bonus(item.get("bonusWeapon")).or(parse_bonus(item.get("bonusAc"))).or(parse_bonus(item.get("bonusSpellAttack"))) {
+        map.insert("bonus".to_string(), json!(bonus));
+    }
+    if let Some(dice) = item.get("dmg1").and_then(|d| d.as_str()) {
+        let damage_type = item.get("dmgType").and_then(|t| t.as_str()).and_then(|t| lookup(&DAMAGE_TYPES, t)).unwrap_or("");
+        map.insert("damage".to_string(), json!({ "dice": dice, "damage_type": damage_type }));
+    }
+    if let Some(charges) = item.get("charges").and_then(|c| c.as_u64()) {
+        map.insert("charges".to_string(), json!(charges));
+    }
+    out
+}
+
+/// "Name|Class|ClassSource|Level" (class) or "Name|Class|ClassSource|Subclass|SubclassSource|Level" (subclass)
+fn feature_ref(reference: &Value) -> Option<(String, u8)> {
+    let text = reference.as_str().or_else(|| reference.get("classFeature").and_then(|c| c.as_str()))
+        .or_else(|| reference.get("subclassFeature").and_then(|c| c.as_str()))?;
+    let parts: Vec<&str> = text.split('|').collect();
+    let level = match parts.len() {
+        n if n >= 6 => parts[5].parse().ok()?,
+        n if n >= 4 => parts[3].parse().ok()?,
+        _ => return None,
+    };
+    Some((parts[0].to_string(), level))
+}
+
+fn map_class(class: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("class", class, &[
+        "name", "hd", "proficiency", "spellcastingAbility", "casterProgression", "multiclassing",
+        "classFeatures", "cantripProgression", "spellsKnownProgression", "classTableGroups", "source", "page",
+    ]);
+
+    let mut prerequisites = Map::new();
+    let mut any = false;
+    if let Some(requirements) = class.pointer("/multiclassing/requirements") {
+        let (reqs, is_any) = match requirements.get("or").and_then(|o| o.as_array()).and_then(|o| o.first()) {
+            Some(or) => (or, true),
+            None => (requirements, false),
+        };
+        any = is_any;
+        for (ability, min) in reqs.as_object().into_iter().flatten() {
+            if let Some(ability) = ability_name(ability) {
+                prerequisites.insert(ability.to_string(), min.clone());
+            }
+        }
+    }
+
+    let mut features: BTreeMap<u8, Vec<String>> = BTreeMap::new();
+    let mut asi_levels = Vec::new();
+    let mut subclass_level = 0;
+    for reference in class.get("classFeatures").and_then(|f| f.as_array()).into_iter().flatten() {
+        let Some((name, level)) = feature_ref(reference) else { continue };
+        if reference.get("gainSubclassFeature").and_then(|g| g.as_bool()).unwrap_or(false) && subclass_level == 0 {
+            subclass_level = level;
+        }
+        if name == "Ability Score Improvement" {
+            asi_levels.push(level);
+        } else {
+            features.entry(level).or_default().push(name);
+        }
+    }
+
+    let slot_rows: Vec<Value> = class.get("classTableGroups").and_then(|g| g.as_array()).into_iter().flatten()
+        .find_map(|g| g.get("rowsSpellProgression").and_then(|r| r.as_array()).cloned())
+        .unwrap_or_default();
+    let levels: Vec<Value> = (1..=20u8).map(|level| {
+        let i = level as usize - 1;
+        let mut row = json!({
+            "level": level,
+            "features": features.get(&level).cloned().unwrap_or_default(),
+            "ability_score_improvement": asi_levels.contains(&level),
+            "spell_slots": slot_rows.get(i).cloned().unwrap_or(json!([])),
+        });
+        if let Some(n) = class.get("cantripProgression").and_then(|p| p.get(i)) {
+            row["cantrips_known"] = n.clone();
+        }
+        if let Some(n) = class.get("spellsKnownProgression").and_then(|p| p.get(i)) {
+            row["spells_known"] = n.clone();
+        }
+        row
+    }).collect();
+
+    let mut out = json!({
+        "name": class.get("name").cloned().unwrap_or_default(),
+        "hit_die": class.pointer("/hd/faces").and_then(|f| f.as_u64()).unwrap_or(8),
+        "saving_throws": class.get("proficiency").and_then(|p| p.as_array()).into_iter().flatten()
+            .filter_map(|a| a.as_str().and_then(ability_name)).collect::<Vec<_>>(),
+        "subclass_level": subclass_level,
+        "multiclass_prerequisites": prerequisites,
+        "multiclass_prerequisites_any": any,
+        "levels": levels,
+    });
+    let progression = match class.get("casterProgression").and_then(|p| p.as_str()) {
+        Some("full") => Some("full"),
+        Some("1/2") | Some("artificer") => Some("half"),
+        Some("1/3") => Some("third"),
+        Some("pact") => Some("pact"),
+        _ => None,
+    };
+    if let Some(progression) = progression {
+        out["spellcasting"] = json!(progression);
+        if let Some(ability) = class.get("spellcastingAbility").and_then(|a| a.as_str()).and_then(ability_name) {
+            out["spellcasting_ability"] = json!(ability);
+        }
+    }
+    out
+}
+
+fn map_subclass(subclass: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("subclass", subclass, &["name", "shortName", "className", "subclassFeatures", "source", "classSource", "page"]);
+    let features: Vec<Value> = subclass.get("subclassFeatures").and_then(|f| f.as_array()).into_iter().flatten()
+        .filter_map(feature_ref)
+        .map(|(feature, level)| json!({ "level": level, "feature": feature }))
+        .collect();
+    json!({
+        "name": subclass.get("name").cloned().unwrap_or_default(),
+        "class": subclass.get("className").cloned().unwrap_or_default(),
+        "features": features,
+    })
+}
+
+fn map_race(race: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("race", race, &["name", "size", "speed", "ability", "entries", "languageProficiencies", "source", "page"]);
+    let size = match race.get("size").and_then(|s| s.as_array()).and_then(|s| s.first()).and_then(|s| s.as_str()) {
+        Some("T") => "Tiny",
+        Some("S") => "Small",
+        Some("L") => "Large",
+        Some("H") => "Huge",
+        _ => "Medium",
+    };
+    let speed = race.get("speed").and_then(|s| s.as_u64().or_else(|| s.get("walk").and_then(|w| w.as_u64()))).unwrap_or(30);
+    let mut bonuses = Map::new();
+    if let Some(ability) = race.get("ability").and_then(|a| a.as_array()).and_then(|a| a.first()).and_then(|a| a.as_object()) {
+        for (key, value) in ability {
+            match ability_name(key) {
+                Some(name) => { bonuses.insert(name.to_string(), value.clone()); }
+                None => report.unmapped_field("race", &format!("ability.{}", key)),
+            }
+        }
+    }
+    let entries = race.get("entries").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+    let traits: Vec<String> = entries.iter().filter_map(|e| e.get("name").and_then(|n| n.as_str()).map(|n| n.to_string())).collect();
+    let languages: Vec<String> = race.get("languageProficiencies").and_then(|l| l.as_array()).and_then(|l| l.first())
+        .and_then(|l| l.as_object())
+        .map(|l| l.iter().filter(|(_, v)| v.as_bool() == Some(true)).map(|(k, _)| capitalize(k)).collect())
+        .unwrap_or_default();
+    json!({
+        "name": race.get("name").cloned().unwrap_or_default(),
+        "description": entries_text(&Value::Array(entries)),
+        "size": size,
+        "speed": speed,
+        "ability_bonuses": bonuses,
+        "traits": traits,
+        "languages": languages,
+    })
+}
+
+/// Names of the proficiencies set to true in the first entry of a 5etools proficiency list
+fn proficiency_names(value: Option<&Value>) -> Vec<String> {
+    value.and_then(|l| l.as_array()).and_then(|l| l.first()).and_then(|l| l.as_object())
+        .map(|l| l.iter().filter(|(_, v)| v.as_bool() == Some(true)).map(|(k, _)| capitalize(k)).collect())
+        .unwrap_or_default()
+}
+
+/// Renders 5etools prerequisite objects as text, e.g. "Dexterity 13, 4th level"
+fn prerequisite_text(prerequisites: &Value) -> Option<String> {
+    let mut parts = Vec::new();
+    for prerequisite in prerequisites.as_array().into_iter().flatten().filter_map(|p| p.as_object()) {
+        for (key, value) in prerequisite {
+            match key.as_str() {
+                "level" => parts.push(format!("level {}", value.as_u64().or(value.get("level").and_then(|l| l.as_u64())).unwrap_or(1))),
+                "ability" => {
+                    for (ability, score) in value.as_array().into_iter().flatten().filter_map(|a| a.as_object()).flatten() {
+                        parts.push(format!("{} {}", capitalize(ability_name(ability).unwrap_or(ability)), score));
+                    }
+                }
+                "race" => {
+                    let races: Vec<String> = value.as_array().into_iter().flatten()
+                        .filter_map(|r| r.get("name").and_then(|n| n.as_str()).map(capitalize))
+                        .collect();
+                    parts.push(races.join(" or "));
+                }
+                "spellcasting" | "spellcasting2020" => parts.push("the ability to cast at least one spell".to_string()),
+                "proficiency" => {
+                    for (kind, what) in value.as_array().into_iter().flatten().filter_map(|p| p.as_object()).flatten() {
+                        parts.push(format!("proficiency with {} {}", what.as_str().unwrap_or(""), kind));
+                    }
+                }
+                "other" => parts.push(strip_tags(value.as_str().unwrap_or(""))),
+                _ => {}
+            }
+        }
+    }
+    let parts: Vec<String> = parts.into_iter().filter(|p| !p.trim().is_empty()).collect();
+    if parts.is_empty() { None } else { Some(parts.join(", ")) }
+}
+
+fn map_feat(feat: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("feat", feat, &["name", "prerequisite", "ability", "repeatable", "entries", "source", "page"]);
+    let mut increases = Map::new();
+    let mut choices = Vec::new();
+    for ability in feat.get("ability").and_then(|a| a.as_array()).into_iter().flatten().filter_map(|a| a.as_object()) {
+        for (key, value) in ability {
+            if key == "choose" {
+                choices.extend(value.get("from").and_then(|f| f.as_array()).into_iter().flatten()
+                    .filter_map(|a| a.as_str().and_then(ability_name)).map(|a| a.to_string()));
+                continue;
+            }
+            match ability_name(key) {
+                Some(name) => { increases.insert(name.to_string(), value.clone()); }
+                None => report.unmapped_field("feat", &format!("ability.{}", key)),
+            }
+        }
+    }
+    let mut out = json!({
+        "name": feat.get("name").cloned().unwrap_or_default(),
+        "description": entries_text(feat.get("entries").unwrap_or(&Value::Null)),
+        "ability_increases": increases,
+        "ability_choices": choices,
+        "repeatable": feat.get("repeatable").and_then(|r| r.as_bool()).unwrap_or(false),
+    });
+    if let Some(prerequisite) = feat.get("prerequisite").and_then(prerequisite_text) {
+        out["prerequisite"] = json!(prerequisite);
+    }
+    out
+}
+
+fn map_background(background: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("background", background, &[
+        "name", "skillProficiencies", "toolProficiencies", "languageProficiencies", "entries", "source", "page",
+    ]);
+    let entries = background.get("entries").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+    // The feature is the entry flagged as such, or the one named "Feature: ..."
+    let is_feature = |e: &Value| {
+        e.pointer("/data/isFeature").and_then(|f| f.as_bool()) == Some(true)
+            || e.get("name").and_then(|n| n.as_str()).is_some_and(|n| n.starts_with("Feature:"))
+    };
+    let feature = entries.iter().find(|e| is_feature(e));
+    let rest: Vec<Value> = entries.iter().filter(|e| !is_feature(e)).cloned().collect();
+    let mut languages = proficiency_names(background.get("languageProficiencies"));
+    if let Some(any) = background.pointer("/languageProficiencies/0/anyStandard").and_then(|n| n.as_u64()) {
+        languages.push(if any == 1 { "one of your choice".to_string() } else { format!("{} of your choice", any) });
+    }
+    json!({
+        "name": background.get("name").cloned().unwrap_or_default(),
+        "description": entries_text(&Value::Array(rest)),
+        "skill_proficiencies": proficiency_names(background.get("skillProficiencies")),
+        "tool_proficiencies": proficiency_names(background.get("toolProficiencies")),
+        "languages": languages,
+        "feature_name": feature.and_then(|f| f.get("name")).and_then(|n| n.as_str())
+            .map(|n| n.trim_start_matches("Feature:").trim().to_string()).unwrap_or_default(),
+        "feature_description": feature.map(|f| entries_text(f.get("entries").unwrap_or(&Value::Null))).unwrap_or_default(),
+    })
+}
+
+fn map_condition(condition: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("condition", condition, &["name", "entries", "source", "page"]);
+    let entries = condition.get("entries").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+    // Bulleted rules become effects, a table (exhaustion) becomes the levels
+    let mut effects = Vec::new();
+    let mut levels = Vec::new();
+    let mut text = Vec::new();
+    for entry in &entries {
+        match entry.get("type").and_then(|t| t.as_str()) {
+            Some("list") => effects.extend(entry.get("items").and_then(|i| i.as_array()).into_iter().flatten().map(entries_text)),
+            Some("table") => levels.extend(entry.get("rows").and_then(|r| r.as_array()).into_iter().flatten()
+                .filter_map(|row| row.as_array().and_then(|cells| cells.last()).map(entries_text))),
+            _ => text.push(entry.clone()),
+        }
+    }
+    json!({
+        "name": condition.get("name").cloned().unwrap_or_default(),
+        "description": entries_text(&Value::Array(text)),
+        "effects": effects,
+        "levels": levels,
+    })
+}
+
+fn map_feature(feature: &Value, report: &mut ImportReport, entry_type: &str) -> Value {
+    report.unmapped_fields(entry_type, feature, &[
+        "name", "className", "classSource", "subclassShortName", "subclassSource", "level", "entries", "source", "page",
+    ]);
+    let mut out = json!({
+        "name": feature.get("name").cloned().unwrap_or_default(),
+        "description": entries_text(feature.get("entries").unwrap_or(&Value::Null)),
+    });
+    if let Some(level) = feature.get("level").and_then(|l| l.as_u64()) {
+        out["level"] = json!(level);
+    }
+    if let Some(class) = feature.get("className").and_then(|c| c.as_str()) {
+        out["class"] = json!(class);
+    }
+    if let Some(subclass) = feature.get("subclassShortName").and_then(|c| c.as_str()) {
+        out["subclass"] = json!(subclass);
+    }
+    out
+}
+
+/// Reads "{@tag value}" from raw stat block text, e.g. the bonus of the first "{@hit 4}"
+fn first_tag<'a>(text: &'a str, tag: &str) -> Option<(&'a str, &'a str)> {
+    let start = text.find(&format!("{{@{} ", tag))?;
+    let rest = &text[start + tag.len() + 3..];
+    let end = rest.find('}')?;
+    Some((&rest[..end], &rest[end + 1..]))
+}
+
+/// A trait or action entry. The attack bonus and first damage roll are read from the raw tags before they are stripped
+fn creature_action(entry: &Value) -> Value {
+    let name = strip_tags(entry.get("name").and_then(|n| n.as_str()).unwrap_or(""));
+    let raw = entry.get("entries").map(|e| e.to_string()).unwrap_or_default();
+    let mut out = json!({
+        "name": name,
+        "description": entries_text(entry.get("entries").unwrap_or(&Value::Null)),
+    });
+    if let Some(bonus) = first_tag(&raw, "hit").and_then(|(b, _)| b.trim_start_matches('+').parse::<i64>().ok()) {
+        out["attack_bonus"] = json!(bonus);
+    }
+    if let Some((dice, after)) = first_tag(&raw, "damage") {
+        // "{@damage 1d6 + 2}) slashing damage"
+        let damage_type = after.split("damage").next().unwrap_or("").split_whitespace().last()
+            .filter(|t| DAMAGE_TYPES.iter().any(|(_, name)| name == t))
+            .unwrap_or("");
+        out["damage"] = json!({ "dice": dice.replace(' ', ""), "damage_type": damage_type });
+    }
+    // Legendary actions: "Wing Attack (Costs 2 Actions)"
+    if let Some(cost) = name.split("(Costs ").nth(1).and_then(|c| c.split_whitespace().next()).and_then(|c| c.parse::<u8>().ok()) {
+        out["cost"] = json!(cost);
+    }
+    out
+}
+
+fn creature_actions(monster: &Value, key: &str) -> Vec<Value> {
+    monster.get(key).and_then(|a| a.as_array()).into_iter().flatten().map(creature_action).collect()
+}
+
+/// Damage or condition lists: plain names, or groups like {"resist": [...], "note": "from nonmagical attacks"}
+fn damage_list(monster: &Value, key: &str) -> Vec<String> {
+    monster.get(key).and_then(|l| l.as_array()).into_iter().flatten().filter_map(|entry| match entry {
+        Value::String(s) => Some(s.clone()),
+        Value::Object(group) => {
+            let names: Vec<&str> = group.get(key).and_then(|n| n.as_array()).into_iter().flatten().filter_map(|n| n.as_str()).collect();
+            let note = group.get("note").and_then(|n| n.as_str()).unwrap_or("");
+            let text = format!("{} {}", names.join(", "), note);
+            Some(strip_tags(text.trim())).filter(|t| !t.is_empty())
+        }
+        _ => None,
+    }).collect()
+}
+
+/// Spellcasting blocks become traits: the header text, then each spell list
+fn spellcasting_traits(monster: &Value) -> Vec<Value> {
+    let names = |list: &Value| list.as_array().into_iter().flatten().filter_map(|s| s.as_str()).map(strip_tags).collect::<Vec<_>>().join(", ");
+    monster.get("spellcasting").and_then(|s| s.as_array()).into_iter().flatten().map(|block| {
+        let mut lines = vec![entries_text(block.get("headerEntries").unwrap_or(&Value::Null))];
+        if let Some(will) = block.get("will") {
+            lines.push(format!("At will: {}", names(will)));
+        }
+        for (uses, list) in block.get("daily").and_then(|d| d.as_object()).into_iter().flatten() {
+            lines.push(format!("{}/day{}: {}", uses.trim_end_matches('e'), if uses.ends_with('e') { " each" } else { "" }, names(list)));
+        }
+        for (level, spells) in block.get("spells").and_then(|s| s.as_object()).into_iter().flatten() {
+            let slots = spells.get("slots").and_then(|s| s.as_u64()).map(|s| format!(" ({} slots)", s)).unwrap_or_default();
+            let label = if level == "0" { "Cantrips (at will)".to_string() } else { format!("Level {}{}", level, slots) };
+            lines.push(format!("{}: {}", label, names(spells.get("spells").unwrap_or(&Value::Null))));
+        }
+        json!({
+            "name": block.get("name").and_then(|n| n.as_str()).unwrap_or("Spellcasting"),
+            "description": lines.into_iter().filter(|l| !l.is_empty()).collect::<Vec<_>>().join("\n"),
+        })
+    }).collect()
+}
+
+fn map_monster(monster: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("monster", monster, &[
+        "name", "size", "type", "alignment", "ac", "hp", "speed", "str", "dex", "con", "int", "wis", "cha", "save",
+        "skill", "vulnerable", "resist", "immune", "conditionImmune", "senses", "passive", "languages", "cr", "trait",
+        "action", "bonus", "reaction", "legendary", "legendaryActions", "spellcasting", "source", "page",
+        // Search tags 5etools derives from the stat block itself
+        "traitTags", "senseTags", "actionTags", "languageTags", "damageTags", "damageTagsSpell", "miscTags",
+        "conditionInflict", "conditionInflictSpell", "savingThrowForced", "spellcastingTags", "environment",
+        "hasToken", "hasFluff", "hasFluffImages",
+    ]);
+
+    let size = monster.get("size").and_then(|s| s.as_array()).and_then(|s| s.first()).and_then(|s| s.as_str())
+        .and_then(|s| lookup(&SIZES, s)).unwrap_or("Medium");
+    let creature_type = match monster.get("type") {
+        Some(Value::String(t)) => t.clone(),
+        Some(Value::Object(t)) => {
+            let base = t.get("type").and_then(|b| b.as_str()).unwrap_or("");
+            let tags: Vec<&str> = t.get("tags").and_then(|g| g.as_array()).into_iter().flatten()
+                .filter_map(|g| g.as_str().or_else(|| g.get("tag").and_then(|n| n.as_str())))
+                .collect();
+            if tags.is_empty() { base.to_string() } else { format!("{} ({})", base, tags.join(", ")) }
+        }
+        _ => String::new(),
+    };
+    let alignment: Vec<&str> = monster.get("alignment").and_then(|a| a.as_array()).into_iter().flatten()
+        .filter_map(|a| a.as_str().and_then(|a| lookup(&ALIGNMENTS, a)))
+        .collect();
+
+    let ac = monster.get("ac").and_then(|a| a.as_array()).and_then(|a| a.first());
+    let armor_class = ac.and_then(|a| a.as_i64().or_else(|| a.get("ac").and_then(|v| v.as_i64()))).unwrap_or(10);
+    let armor_type: Vec<String> = ac.and_then(|a| a.get("from")).and_then(|f| f.as_array()).into_iter().flatten()
+        .filter_map(|f| f.as_str()).map(strip_tags).collect();
+
+    let mut speed = Map::new();
+    for (mode, value) in monster.get("speed").and_then(|s| s.as_object()).into_iter().flatten() {
+        if let Some(feet) = value.as_u64().or_else(|| value.get("number").and_then(|n| n.as_u64())) {
+            speed.insert(mode.clone(), json!(feet));
+        }
+    }
+    let mut abilities = Map::new();
+    for short in ["str", "dex", "con", "int", "wis", "cha"] {
+        let name = ability_name(short).unwrap_or(short);
+        abilities.insert(name.to_string(), json!(monster.get(short).and_then(|v| v.as_u64()).unwrap_or(10)));
+    }
+    let mut saving_throws = Map::new();
+    for (ability, bonus) in monster.get("save").and_then(|s| s.as_object()).into_iter().flatten() {
+        if let (Some(ability), Some(bonus)) = (ability_name(ability), parse_bonus(Some(bonus))) {
+            saving_throws.insert(ability.to_string(), json!(bonus));
+        }
+    }
+    let mut skills = Map::new();
+    for (skill, bonus) in monster.get("skill").and_then(|s| s.as_object()).into_iter().flatten() {
+        if let Some(bonus) = parse_bonus(Some(bonus)) {
+            skills.insert(skill.clone(), json!(bonus));
+        }
+    }
+    let mut senses: Vec<String> = monster.get("senses").and_then(|s| s.as_array()).into_iter().flatten()
+        .filter_map(|s| s.as_str()).map(strip_tags).collect();
+    if let Some(passive) = monster.get("passive").and_then(|p| p.as_u64()) {
+        senses.push(format!("passive Perception {}", passive));
+    }
+    let challenge_rating = monster.get("cr")
+        .and_then(|cr| cr.as_str().or_else(|| cr.get("cr").and_then(|c| c.as_str())))
+        .unwrap_or("0");
+
+    let mut traits = creature_actions(monster, "trait");
+    traits.extend(spellcasting_traits(monster));
+    let legendary_actions = creature_actions(monster, "legendary");
+    let per_round = if legendary_actions.is_empty() { 0 } else { monster.get("legendaryActions").and_then(|l| l.as_u64()).unwrap_or(3) };
+
+    let mut out = json!({
+        "name": monster.get("name").cloned().unwrap_or_default(),
+        "size": size,
+        "creature_type": creature_type,
+        "alignment": alignment.join(" "),
+        "armor_class": armor_class,
+        "hit_points": monster.pointer("/hp/average").cloned().unwrap_or_default(),
+        "hit_dice": monster.pointer("/hp/formula").and_then(|f| f.as_str()).unwrap_or("").replace(' ', ""),
+        "speed": speed,
+        "abilities": abilities,
+        "saving_throws": saving_throws,
+        "skills": skills,
+        "damage_vulnerabilities": damage_list(monster, "vulnerable"),
+        "damage_resistances": damage_list(monster, "resist"),
+        "damage_immunities": damage_list(monster, "immune"),
+        "condition_immunities": damage_list(monster, "conditionImmune"),
+        "senses": senses,
+        "languages": monster.get("languages").and_then(|l| l.as_array()).into_iter().flatten()
+            .filter_map(|l| l.as_str()).map(strip_tags).collect::<Vec<_>>(),
+        "challenge_rating": challenge_rating,
+        "traits": traits,
+        "actions": creature_actions(monster, "action"),
+        "bonus_actions": creature_actions(monster, "bonus"),
+        "reactions": creature_actions(monster, "reaction"),
+        "legendary_actions": legendary_actions,
+        "legendary_actions_per_round": per_round,
+    });
+    if !armor_type.is_empty() {
+        out["armor_type"] = json!(armor_type.join(", "));
+    }
+    out
+}
+
+/// Imports a 5etools homebrew file. The source name defaults to the file's `_meta` source
+pub fn import_file(path: &Path, source: Option<&str>) -> Result<ImportReport, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::Storage(format!("Could not read {}: {}", path.display(), e)))?;
+    let brew: Value = serde_json::from_str(&contents)
+        .map_err(|e| Error::Validation(format!("{} is not valid JSON: {}", path.display(), e)))?;
+    if !brew.is_object() {
+        return Err(Error::Validation(format!("{} is not a 5etools homebrew file", path.display())).into());
+    }
+
+    let source = source.map(|s| s.to_string())
+        .or_else(|| brew.pointer("/_meta/sources/0/full").and_then(|s| s.as_str()).map(|s| s.to_string()))
+        .or_else(|| brew.pointer("/_meta/sources/0/json").and_then(|s| s.as_str()).map(|s| s.to_string()))
+        .unwrap_or_else(|| path.file_stem().and_then(|s| s.to_str()).unwrap_or("5etools").to_string());
+
+    let mut report = ImportReport::default();
+    let mut importer = Importer::new(&source)?;
+    let section = |name: &str| brew.get(name).and_then(|s| s.as_array()).cloned().unwrap_or_default();
+
+    // Classes first so subclasses and features referencing them validate
+    for class in section("class") {
+        let data = map_class(&class, &mut report);
+        importer.save(&mut report, "Classes", data)?;
+    }
+    for subclass in section("subclass") {
+        let data = map_subclass(&subclass, &mut report);
+        importer.save(&mut report, "Subclasses", data)?;
+    }
+    for feature in section("classFeature") {
+        let data = map_feature(&feature, &mut report, "classFeature");
+        importer.save(&mut report, "Features", data)?;
+    }
+    for feature in section("subclassFeature") {
+        let data = map_feature(&feature, &mut report, "subclassFeature");
+        importer.save(&mut report, "Features", data)?;
+    }
+    for spell in section("spell") {
+        let data = map_spell(&spell, &mut report);
+        importer.save(&mut report, "Spells", data)?;
+    }
+    for item in section("item").into_iter().chain(section("baseitem")) {
+        let data = map_item(&item, &mut report);
+        importer.save(&mut report, "Items", data)?;
+    }
+    for race in section("race") {
+        let data = map_race(&race, &mut report);
+        importer.save(&mut report, "Races", data)?;
+    }
+    for background in section("background") {
+        let data = map_background(&background, &mut report);
+        importer.save(&mut report, "Backgrounds", data)?;
+    }
+    for feat in section("feat") {
+        let data = map_feat(&feat, &mut report);
+        importer.save(&mut report, "Feats", data)?;
+    }
+    for condition in section("condition") {
+        let data = map_condition(&condition, &mut report);
+        importer.save(&mut report, "Conditions", data)?;
+    }
+    for monster in section("monster") {
+        let data = map_monster(&monster, &mut report);
+        importer.save(&mut report, "Creatures", data)?;
+    }
+    for name in UNSUPPORTED_SECTIONS {
+        let count = section(name).len();
+        if count > 0 {
+            report.skipped.push(format!("{} '{}' entries: not supported by this importer", count, name));
+        }
+    }
+
+    Ok(report)
+}
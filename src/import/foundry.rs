@@ -0,0 +1,369 @@
+// Importer for Foundry VTT (dnd5e system) compendium exports: a JSON array, a single document or a NeDB .db pack
+
+use crate::error::Error;
+use crate::import::{ability_name, capitalize, lookup, strip_html, ImportReport, Importer, SCHOOLS};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const ITEM_DOCUMENTS: [&str; 6] = ["weapon", "equipment", "consumable", "tool", "loot", "container"];
+
+/// Reads every document from the file, whatever the export layout
+fn read_documents(path: &Path) -> Result<Vec<Value>, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::Storage(format!("Could not read {}: {}", path.display(), e)))?;
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(Value::Array(documents)) => Ok(documents),
+        Ok(document @ Value::Object(_)) => Ok(vec![document]),
+        Ok(_) => Err(Error::Validation(format!("{} is not a Foundry document or pack", path.display())).into()),
+        // NeDB packs hold one JSON document per line
+        Err(_) => contents.lines()
+            .filter(|l| !l.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| serde_json::from_str(line)
+                .map_err(|e| Error::Validation(format!("{} line {} is not valid JSON: {}", path.display(), i + 1, e)).into()))
+            .collect(),
+    }
+}
+
+/// Foundry v10+ keeps fields under `system`, older exports under `data`
+fn system(document: &Value) -> &Value {
+    document.get("system").or(document.get("data")).unwrap_or(&Value::Null)
+}
+
+fn text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn description(system: &Value) -> String {
+    strip_html(&text(system.pointer("/description/value")))
+}
+
+fn plural(amount: &str, unit: &str) -> String {
+    if amount == "1" || amount.is_empty() { format!("1 {}", unit) } else { format!("{} {}s", amount, unit) }
+}
+
+fn map_spell(system: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("spell", system, &[
+        "description", "source", "activation", "duration", "target", "range", "level", "school",
+        "components", "properties", "materials", "damage", "scaling",
+    ]);
+
+    let activation_cost = text(system.pointer("/activation/cost"));
+    let casting_time = match system.pointer("/activation/type").and_then(|t| t.as_str()).unwrap_or("action") {
+        "bonus" => "1 bonus action".to_string(),
+        "reaction" => "1 reaction".to_string(),
+        "action" => "1 action".to_string(),
+        "minute" | "hour" | "day" => plural(&activation_cost, system.pointer("/activation/type").and_then(|t| t.as_str()).unwrap_or("")),
+        other => capitalize(other),
+    };
+
+    let range_value = text(system.pointer("/range/value"));
+    let range = match system.pointer("/range/units").and_then(|u| u.as_str()).unwrap_or("") {
+        "ft" => format!("{} feet", range_value),
+        "mi" => plural(&range_value, "mile"),
+        "self" => "Self".to_string(),
+        "touch" => "Touch".to_string(),
+        "spec" => "Special".to_string(),
+        "any" => "Unlimited".to_string(),
+        other => capitalize(other),
+    };
+
+    let area = system.get("target")
+        .filter(|t| ["sphere", "cone", "cube", "line", "cylinder", "radius", "square"].contains(&t.get("type").and_then(|t| t.as_str()).unwrap_or("")))
+        .map(|t| {
+            let shape = match t.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+                "sphere" | "cylinder" => "radius",
+                other => other,
+            };
+            format!("{}-foot {}", text(t.get("value")), shape)
+        });
+    let range = match (&area, range.as_str()) {
+        (Some(area), "Self") => format!("Self ({})", area),
+        _ => range,
+    };
+
+    // v3 stores components as a properties list, older versions as a components object
+    let properties: Vec<String> = system.get("properties").and_then(|p| p.as_array()).into_iter().flatten()
+        .filter_map(|p| p.as_str().map(|p| p.to_string())).collect();
+    let flag = |name: &str, long: &str| {
+        properties.iter().any(|p| p == name) || system.pointer(&format!("/components/{}", long)).and_then(|v| v.as_bool()).unwrap_or(false)
+    };
+    let mut components = Vec::new();
+    if flag("vocal", "vocal") { components.push("V"); }
+    if flag("somatic", "somatic") { components.push("S"); }
+    if flag("material", "material") { components.push("M"); }
+    let concentration = flag("concentration", "concentration");
+    let ritual = flag("ritual", "ritual");
+
+    let duration_value = text(system.pointer("/duration/value"));
+    let duration = match system.pointer("/duration/units").and_then(|u| u.as_str()).unwrap_or("inst") {
+        "inst" => "Instantaneous".to_string(),
+        "perm" => "Until dispelled".to_string(),
+        "spec" => "Special".to_string(),
+        unit @ ("round" | "minute" | "hour" | "day") if concentration => format!("Concentration, up to {}", plural(&duration_value, unit)),
+        unit @ ("round" | "minute" | "hour" | "day") => plural(&duration_value, unit),
+        other => capitalize(other),
+    };
+
+    let mut out = json!({
+        "level": system.get("level").and_then(|l| l.as_u64()).unwrap_or(0),
+        "school": system.get("school").and_then(|s| s.as_str()).and_then(|s| lookup(&SCHOOLS, s)).unwrap_or(""),
+        "casting_time": casting_time,
+        "range": range,
+        "components": components,
+        "duration": duration,
+        "concentration": concentration,
+        "ritual": ritual,
+        "description": description(system),
+    });
+    let material = text(system.pointer("/materials/value"));
+    if !material.is_empty() {
+        out["material"] = json!(material);
+    }
+    if let Some(area) = area {
+        out["area"] = json!(area);
+    }
+    if let Some(part) = system.pointer("/damage/parts/0").and_then(|p| p.as_array()) {
+        let dice = text(part.first());
+        // Foundry formulas may reference roll data ("1d8 + @mod"), keep only the dice
+        let dice = dice.split_whitespace().next().unwrap_or("").to_string();
+        if !dice.is_empty() {
+            out["damage"] = json!({ "dice": dice, "damage_type": text(part.get(1)) });
+        }
+    }
+    out
+}
+
+fn map_item(document_type: &str, system: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields(document_type, system, &[
+        "description", "source", "rarity", "attunement", "attuned", "magicalBonus", "armor", "damage",
+        "uses", "type", "weaponType", "consumableType", "toolType", "properties",
+    ]);
+
+    let subtype = system.pointer("/type/value").and_then(|t| t.as_str())
+        .or(system.get("weaponType").and_then(|t| t.as_str()))
+        .or(system.get("consumableType").and_then(|t| t.as_str()))
+        .or(system.pointer("/armor/type").and_then(|t| t.as_str()))
+        .unwrap_or("");
+    let item_type = match (document_type, subtype) {
+        ("weapon", s) if s.ends_with('R') || s.contains("ranged") => "ranged weapon".to_string(),
+        ("weapon", _) => "melee weapon".to_string(),
+        ("equipment", "light" | "medium" | "heavy") => format!("{} armor", subtype),
+        ("equipment", "shield") => "shield".to_string(),
+        ("equipment", "trinket" | "clothing" | "") => "wondrous item".to_string(),
+        ("equipment", s) => s.to_string(),
+        ("consumable", "") => "consumable".to_string(),
+        ("consumable", s) => s.to_string(),
+        ("loot", _) => "gear".to_string(),
+        (t, _) => t.to_string(),
+    };
+
+    let rarity = match system.get("rarity").and_then(|r| r.as_str()).unwrap_or("") {
+        "" | "mundane" => "common".to_string(),
+        "veryRare" => "very rare".to_string(),
+        r => r.to_lowercase(),
+    };
+    // Older versions use 0/1/2 for none/required/attuned
+    let attunement = match system.get("attunement") {
+        Some(Value::String(s)) => s == "required",
+        Some(Value::Number(n)) => n.as_u64().unwrap_or(0) > 0,
+        Some(Value::Bool(b)) => *b,
+        _ => false,
+    };
+
+    let mut out = json!({
+        "item_type": item_type,
+        "rarity": rarity,
+        "requires_attunement": attunement,
+        "description": description(system),
+    });
+    let bonus = system.get("magicalBonus").or(system.pointer("/armor/magicalBonus"));
+    if let Some(bonus) = bonus.and_then(|b| b.as_i64().or_else(|| b.as_str().and_then(|s| s.parse().ok()))).filter(|b| *b != 0) {
+        out["bonus"] = json!(bonus);
+    }
+    let damage = system.pointer("/damage/parts/0").and_then(|p| p.as_array())
+        .map(|p| (text(p.first()), text(p.get(1))))
+        .or_else(|| system.pointer("/damage/base").map(|b| {
+            let count = text(b.get("number"));
+            (format!("{}d{}", count, text(b.get("denomination"))), text(b.pointer("/types/0")))
+        }));
+    if let Some((dice, damage_type)) = damage {
+        let dice = dice.split_whitespace().next().unwrap_or("").to_string();
+        if crate::dice::DiceExpr::parse(&dice).is_some() {
+            out["damage"] = json!({ "dice": dice, "damage_type": damage_type });
+        }
+    }
+    if let Some(charges) = system.pointer("/uses/max").and_then(|m| m.as_u64().or_else(|| m.as_str().and_then(|s| s.parse().ok()))) {
+        out["charges"] = json!(charges);
+    }
+    out
+}
+
+fn map_class(system: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("class", system, &[
+        "description", "source", "identifier", "hitDice", "hd", "spellcasting", "saves", "advancement", "levels",
+    ]);
+
+    let hit_die = system.get("hitDice").and_then(|h| h.as_str())
+        .or(system.pointer("/hd/denomination").and_then(|h| h.as_str()))
+        .and_then(|h| h.trim_start_matches('d').parse::<u64>().ok())
+        .unwrap_or(8);
+    let mut saves: Vec<&str> = system.get("saves").and_then(|s| s.as_array()).into_iter().flatten()
+        .filter_map(|s| s.as_str().and_then(ability_name)).collect();
+
+    let mut features: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+    let mut asi_levels = Vec::new();
+    let mut subclass_level = 0;
+    for advancement in system.get("advancement").and_then(|a| a.as_array()).into_iter().flatten() {
+        let level = advancement.get("level").and_then(|l| l.as_u64()).unwrap_or(0);
+        match advancement.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+            "AbilityScoreImprovement" => asi_levels.push(level),
+            "Subclass" => subclass_level = level,
+            "ItemGrant" => {
+                if let Some(title) = advancement.get("title").and_then(|t| t.as_str()).filter(|t| !t.is_empty()) {
+                    features.entry(level).or_default().push(title.to_string());
+                }
+            }
+            "Trait" if saves.is_empty() && level == 1 => {
+                // Newer exports grant saving throws through a Trait advancement ("saves:str")
+                saves = advancement.pointer("/configuration/grants").and_then(|g| g.as_array()).into_iter().flatten()
+                    .filter_map(|g| g.as_str().and_then(|g| g.strip_prefix("saves:")).and_then(ability_name))
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    let levels: Vec<Value> = (1..=20u64).map(|level| json!({
+        "level": level,
+        "features": features.get(&level).cloned().unwrap_or_default(),
+        "ability_score_improvement": asi_levels.contains(&level),
+    })).collect();
+
+    let mut out = json!({
+        "hit_die": hit_die,
+        "saving_throws": saves,
+        "subclass_level": subclass_level,
+        "levels": levels,
+        "description": description(system),
+    });
+    match system.pointer("/spellcasting/progression").and_then(|p| p.as_str()).unwrap_or("none") {
+        progression @ ("full" | "half" | "third" | "pact") => {
+            out["spellcasting"] = json!(progression);
+            if let Some(ability) = system.pointer("/spellcasting/ability").and_then(|a| a.as_str()).and_then(ability_name) {
+                out["spellcasting_ability"] = json!(ability);
+            }
+        }
+        "artificer" => out["spellcasting"] = json!("half"),
+        _ => {}
+    }
+    out
+}
+
+fn map_subclass(system: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("subclass", system, &["description", "source", "identifier", "classIdentifier", "advancement", "spellcasting"]);
+    let features: Vec<Value> = system.get("advancement").and_then(|a| a.as_array()).into_iter().flatten()
+        .filter(|a| a.get("type").and_then(|t| t.as_str()) == Some("ItemGrant"))
+        .filter_map(|a| Some(json!({
+            "level": a.get("level").and_then(|l| l.as_u64())?,
+            "feature": a.get("title").and_then(|t| t.as_str()).filter(|t| !t.is_empty())?,
+        })))
+        .collect();
+    json!({
+        "class": capitalize(&text(system.get("classIdentifier"))),
+        "description": description(system),
+        "features": features,
+    })
+}
+
+fn map_feature(system: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("feat", system, &["description", "source", "requirements", "type", "activation", "uses"]);
+    let mut out = json!({ "description": description(system) });
+    // Class features carry "Wizard 2" style requirements
+    let requirements = text(system.get("requirements"));
+    let mut words = requirements.split_whitespace();
+    if let (Some(class), Some(level)) = (words.next(), words.next().and_then(|l| l.parse::<u64>().ok())) {
+        out["class"] = json!(class);
+        out["level"] = json!(level);
+    }
+    out
+}
+
+fn map_race(system: &Value, report: &mut ImportReport) -> Value {
+    report.unmapped_fields("race", system, &["description", "source", "identifier", "movement", "type", "advancement", "senses"]);
+    let mut bonuses = Map::new();
+    let mut size = "Medium".to_string();
+    for advancement in system.get("advancement").and_then(|a| a.as_array()).into_iter().flatten() {
+        match advancement.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+            "AbilityScoreImprovement" => {
+                for (ability, bonus) in advancement.pointer("/configuration/fixed").and_then(|f| f.as_object()).into_iter().flatten() {
+                    if let Some(name) = ability_name(ability).filter(|_| bonus.as_i64().unwrap_or(0) != 0) {
+                        bonuses.insert(name.to_string(), bonus.clone());
+                    }
+                }
+            }
+            "Size" => {
+                if let Some(code) = advancement.pointer("/configuration/sizes/0").and_then(|s| s.as_str()) {
+                    size = match code {
+                        "tiny" => "Tiny", "sm" => "Small", "lg" => "Large", "huge" => "Huge", _ => "Medium",
+                    }.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    let traits: Vec<String> = system.get("advancement").and_then(|a| a.as_array()).into_iter().flatten()
+        .filter(|a| a.get("type").and_then(|t| t.as_str()) == Some("ItemGrant"))
+        .filter_map(|a| a.get("title").and_then(|t| t.as_str()).filter(|t| !t.is_empty()).map(|t| t.to_string()))
+        .collect();
+    json!({
+        "description": description(system),
+        "size": size,
+        "speed": system.pointer("/movement/walk").and_then(|w| w.as_u64()).unwrap_or(30),
+        "ability_bonuses": bonuses,
+        "traits": traits,
+    })
+}
+
+/// Imports a Foundry VTT export. The source name defaults to the file name
+pub fn import_file(path: &Path, source: Option<&str>) -> Result<ImportReport, anyhow::Error> {
+    let mut documents = read_documents(path)?;
+    let source = source.map(|s| s.to_string())
+        .unwrap_or_else(|| path.file_stem().and_then(|s| s.to_str()).unwrap_or("Foundry").to_string());
+
+    // Classes first so subclasses and features referencing them validate
+    documents.sort_by_key(|d| if d.get("type").and_then(|t| t.as_str()) == Some("class") { 0 } else { 1 });
+
+    let mut report = ImportReport::default();
+    let mut importer = Importer::new(&source)?;
+    for document in &documents {
+        let name = text(document.get("name"));
+        let document_type = document.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let system = system(document);
+        let (kind, mut data) = match document_type {
+            "spell" => ("Spells", map_spell(system, &mut report)),
+            "class" => ("Classes", map_class(system, &mut report)),
+            "subclass" => ("Subclasses", map_subclass(system, &mut report)),
+            "feat" => ("Features", map_feature(system, &mut report)),
+            "race" => ("Races", map_race(system, &mut report)),
+            t if ITEM_DOCUMENTS.contains(&t) => ("Items", map_item(t, system, &mut report)),
+            "" => {
+                report.skipped.push(format!("{}: not an Item document", if name.is_empty() { "[unnamed]" } else { &name }));
+                continue;
+            }
+            other => {
+                report.skipped.push(format!("{}: '{}' documents have no matching content type", name, other));
+                continue;
+            }
+        };
+        data["name"] = json!(name);
+        importer.save(&mut report, kind, data)?;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,189 @@
+// Importers that bring external content into the local store
+
+pub mod fivetools;
+pub mod foundry;
+pub mod srd;
+
+use crate::store;
+use crate::validate::ValidationContext;
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Spell school codes used by 5etools ("V") and Foundry ("evo"), with the crate's school names
+pub const SCHOOLS: [(&str, &str); 16] = [
+    ("A", "abjuration"), ("C", "conjuration"), ("D", "divination"), ("E", "enchantment"),
+    ("V", "evocation"), ("I", "illusion"), ("N", "necromancy"), ("T", "transmutation"),
+    ("abj", "abjuration"), ("con", "conjuration"), ("div", "divination"), ("enc", "enchantment"),
+    ("evo", "evocation"), ("ill", "illusion"), ("nec", "necromancy"), ("trs", "transmutation"),
+];
+
+/// Finds the crate's value for a source format's code in a (code, value) table, ignoring case
+pub fn lookup(table: &[(&str, &'static str)], code: &str) -> Option<&'static str> {
+    table.iter().find(|(c, _)| c.eq_ignore_ascii_case(code)).map(|(_, v)| *v)
+}
+
+/// Turns a display name into a file-safe slug, e.g. "Hunter's Mark" -> "hunter_s_mark"
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.trim_matches('_').to_string()
+}
+
+/// Outcome of an import run
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: BTreeMap<&'static str, usize>,
+    /// Entries that were not imported, with the reason
+    pub skipped: Vec<String>,
+    /// Source fields with no equivalent in the crate's content types, with how often they were seen
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl ImportReport {
+    pub fn unmapped_field(&mut self, entry_type: &str, field: &str) {
+        *self.unmapped.entry(format!("{}.{}", entry_type, field)).or_default() += 1;
+    }
+
+    /// Records every key of `object` not listed in `mapped` as unmapped
+    pub fn unmapped_fields(&mut self, entry_type: &str, object: &Value, mapped: &[&str]) {
+        if let Some(map) = object.as_object() {
+            for key in map.keys().filter(|k| !mapped.contains(&k.as_str())) {
+                self.unmapped_field(entry_type, key);
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        for (kind, count) in &self.imported {
+            info!("Imported {} {}", count, kind);
+        }
+        for skipped in &self.skipped {
+            warn!("Skipped {}", skipped);
+        }
+        for (field, count) in &self.unmapped {
+            warn!("Not imported, no equivalent field: {} ({}x)", field, count);
+        }
+    }
+}
+
+/// Names already held from the import's source and the next free local ID, for one content type
+struct KindState {
+    names: HashSet<String>,
+    next_id: u64,
+}
+
+/// Saves the objects of one import run. Each type's existing objects, its next local ID and the validation
+/// context are looked up once and then kept current in memory, instead of re-read for every object
+pub struct Importer {
+    source: String,
+    ctx: ValidationContext,
+    kinds: HashMap<&'static str, KindState>,
+}
+
+impl Importer {
+    pub fn new(source: &str) -> Result<Self, anyhow::Error> {
+        Ok(Importer { source: source.to_string(), ctx: ValidationContext::load()?, kinds: HashMap::new() })
+    }
+
+    fn kind_state(&mut self, kind: &'static str) -> Result<&mut KindState, anyhow::Error> {
+        if !self.kinds.contains_key(kind) {
+            let names = store::load_all(kind)?
+                .iter()
+                .filter(|obj| obj.data.get("source").and_then(|s| s.as_str()) == Some(self.source.as_str()))
+                .map(|obj| obj.name().to_lowercase())
+                .collect();
+            self.kinds.insert(kind, KindState { names, next_id: store::next_local_id(kind)? });
+        }
+        Ok(self.kinds.get_mut(kind).expect("inserted above"))
+    }
+
+    /// Creates a local object (local ID + journaled POST) unless one with the same name and source exists
+    pub fn save(&mut self, report: &mut ImportReport, kind: &'static str, mut data: Value) -> Result<(), anyhow::Error> {
+        let name = data.get("name").and_then(|n| n.as_str()).unwrap_or("[unnamed]").to_string();
+        if let Some(map) = data.as_object_mut() {
+            map.insert("source".to_string(), Value::String(self.source.clone()));
+        }
+
+        let state = self.kind_state(kind)?;
+        if state.names.contains(&name.to_lowercase()) {
+            report.skipped.push(format!("{}/{}: already imported from {}", kind, name, self.source));
+            return Ok(());
+        }
+        let id = state.next_id;
+
+        match store::create_with(kind, data, &self.ctx, id) {
+            Ok(_) => {
+                let state = self.kind_state(kind)?;
+                state.names.insert(name.to_lowercase());
+                state.next_id += 1;
+                // Later subclasses and features of this run reference the new class
+                if kind == "Classes" {
+                    self.ctx.class_names.push(name);
+                }
+                *report.imported.entry(kind).or_default() += 1;
+            }
+            Err(e) => report.skipped.push(format!("{}/{}: {}", kind, name, e)),
+        }
+        Ok(())
+    }
+}
+
+/// Reduces HTML (Foundry descriptions) to plain text
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    let mut tag = String::new();
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let tag_name = tag.trim_start_matches('/').split_whitespace().next().unwrap_or("").to_lowercase();
+                if ["p", "br", "li", "h1", "h2", "h3", "h4", "tr", "div"].contains(&tag_name.as_str()) && !text.ends_with('\n') && !text.is_empty() {
+                    text.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => text.push(c),
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+/// Expands short ability names used by 5etools and Foundry ("str") to the crate's full names
+pub fn ability_name(short: &str) -> Option<&'static str> {
+    match short.to_lowercase().as_str() {
+        "str" | "strength" => Some("strength"),
+        "dex" | "dexterity" => Some("dexterity"),
+        "con" | "constitution" => Some("constitution"),
+        "int" | "intelligence" => Some("intelligence"),
+        "wis" | "wisdom" => Some("wisdom"),
+        "cha" | "charisma" => Some("charisma"),
+        _ => None,
+    }
+}
+
+pub fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,67 @@
+// SRD reference dataset, stored as read-only reference content that is never journaled or pushed
+// The embedded data/srd_sample.json is a small abridged and paraphrased sample, not the SRD 5.1 text;
+// the full SRD 5.1 can be loaded from a file in the same format
+
+use crate::error::Error;
+use crate::import::slugify;
+use crate::{content_type, store};
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Source recorded on imported objects when the dataset does not name one in `_meta.source`
+pub const SRD_SOURCE: &str = "SRD 5.1 sample";
+/// File stem prefix for SRD objects, keeps them apart from local IDs and synced `_` objects
+pub const SRD_PREFIX: &str = "srd_";
+
+static EMBEDDED_SRD: &str = include_str!("../../data/srd_sample.json");
+
+/// Loads the embedded dataset (or `file` when given) into the local store, returning counts per type
+pub fn import_srd(file: Option<&Path>) -> Result<BTreeMap<&'static str, usize>, anyhow::Error> {
+    let contents = match file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| Error::Storage(format!("Could not read {}: {}", path.display(), e)))?,
+        None => EMBEDDED_SRD.to_string(),
+    };
+    let dataset: Value = serde_json::from_str(&contents)
+        .map_err(|e| Error::Validation(format!("SRD dataset is not valid JSON: {}", e)))?;
+    let sections = dataset.as_object()
+        .ok_or_else(|| Error::Validation("SRD dataset must be an object keyed by content type".to_string()))?;
+
+    let source = dataset.pointer("/_meta/source").and_then(|s| s.as_str()).unwrap_or(SRD_SOURCE).to_string();
+    if let Some(license) = dataset.pointer("/_meta/license").and_then(|l| l.as_str()) {
+        info!("{}", license);
+    }
+
+    // Classes go first so subclass and feature references validate
+    let mut kinds: Vec<&'static str> = sections.keys().filter_map(|k| content_type(k)).collect();
+    kinds.sort_by_key(|k| if *k == "Classes" { 0 } else { 1 });
+
+    let mut counts = BTreeMap::new();
+    for kind in kinds {
+        let Some(objects) = sections.iter().find(|(k, _)| k.eq_ignore_ascii_case(kind)).and_then(|(_, v)| v.as_array()) else {
+            continue;
+        };
+        let mut imported = 0;
+        for object in objects {
+            let mut object = object.clone();
+            let Some(name) = object.get("name").and_then(|n| n.as_str()).map(|n| n.to_string()) else {
+                warn!("Skipping unnamed SRD entry in {}", kind);
+                continue;
+            };
+            if let Some(map) = object.as_object_mut() {
+                map.insert("source".to_string(), Value::String(source.clone()));
+            }
+            let id = format!("{}{}", SRD_PREFIX, slugify(&name));
+            match store::save_reference(kind, &id, object) {
+                Ok(_) => imported += 1,
+                Err(e) => warn!("Skipped {}/{}: {:#}", kind, name, e),
+            }
+        }
+        counts.insert(kind, imported);
+    }
+
+    Ok(counts)
+}
@@ -0,0 +1,303 @@
+// Level-up engine for Characters, driven by the cached Class and Subclass objects
+
+use crate::dice::roll_die;
+use crate::error::Error;
+use crate::models::{AbilityScores, Character, CharacterClass, Class, Spell, SpellcastingProgression, Subclass, ABILITIES};
+use crate::{store, ui};
+use log::{info, warn};
+
+/// Spell slots per slot level for a combined multiclass spellcaster level (PHB p.165)
+pub const MULTICLASS_SPELL_SLOTS: [[u8; 9]; 20] = [
+    [2, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 0, 0, 0, 0, 0, 0, 0, 0],
+    [4, 2, 0, 0, 0, 0, 0, 0, 0],
+    [4, 3, 0, 0, 0, 0, 0, 0, 0],
+    [4, 3, 2, 0, 0, 0, 0, 0, 0],
+    [4, 3, 3, 0, 0, 0, 0, 0, 0],
+    [4, 3, 3, 1, 0, 0, 0, 0, 0],
+    [4, 3, 3, 2, 0, 0, 0, 0, 0],
+    [4, 3, 3, 3, 1, 0, 0, 0, 0],
+    [4, 3, 3, 3, 2, 0, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 1],
+    [4, 3, 3, 3, 3, 1, 1, 1, 1],
+    [4, 3, 3, 3, 3, 2, 1, 1, 1],
+    [4, 3, 3, 3, 3, 2, 2, 1, 1],
+];
+
+pub struct LevelUpOptions {
+    /// Class to gain a level in; defaults to the character's only class
+    pub class: Option<String>,
+    /// Roll the hit die instead of taking the fixed average
+    pub roll_hp: bool,
+}
+
+/// Combined spellcaster level used for the multiclass slot table
+pub fn caster_level(casters: &[(SpellcastingProgression, u8)]) -> u8 {
+    casters.iter().map(|(progression, level)| match progression {
+        SpellcastingProgression::Full => *level,
+        SpellcastingProgression::Half => *level / 2,
+        SpellcastingProgression::Third => *level / 3,
+        SpellcastingProgression::Pact => 0,
+    }).sum()
+}
+
+fn slots_for_caster_level(level: u8) -> Vec<u8> {
+    if level == 0 {
+        return Vec::new();
+    }
+    MULTICLASS_SPELL_SLOTS[(level.min(20) - 1) as usize].to_vec()
+}
+
+fn trim_slots(mut slots: Vec<u8>) -> Vec<u8> {
+    while slots.last() == Some(&0) {
+        slots.pop();
+    }
+    slots
+}
+
+fn find_class<'a>(classes: &'a [Class], name: &str) -> Option<&'a Class> {
+    classes.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+}
+
+/// Recomputes regular and pact spell slots from every class the character has levels in
+pub fn recompute_spell_slots(character: &mut Character, classes: &[Class]) {
+    let mut casters: Vec<(&Class, SpellcastingProgression, u8)> = Vec::new();
+    let mut pact_slots = Vec::new();
+
+    for entry in &character.classes {
+        let Some(class) = find_class(classes, &entry.class) else { continue };
+        match class.spellcasting {
+            Some(SpellcastingProgression::Pact) => {
+                pact_slots = class.level(entry.level).map(|l| l.spell_slots.clone()).unwrap_or_default();
+            }
+            Some(progression) => casters.push((class, progression, entry.level)),
+            None => {}
+        }
+    }
+
+    let combined: Vec<(SpellcastingProgression, u8)> = casters.iter().map(|(_, p, l)| (*p, *l)).collect();
+    character.spell_slots = match casters.as_slice() {
+        [] => Vec::new(),
+        // A single spellcasting class uses its own table when one is provided
+        [(class, _, level)] => match class.level(*level) {
+            Some(row) if !row.spell_slots.is_empty() => row.spell_slots.clone(),
+            _ => slots_for_caster_level(caster_level(&combined)),
+        },
+        _ => slots_for_caster_level(caster_level(&combined)),
+    };
+    character.spell_slots = trim_slots(character.spell_slots.clone());
+    character.pact_slots = trim_slots(pact_slots);
+}
+
+fn apply_ability_score_improvement(abilities: &mut AbilityScores) -> Result<Vec<String>, anyhow::Error> {
+    let mode = ui::choose(
+        "Ability Score Improvement:",
+        &["+2 to one ability score".to_string(), "+1 to two ability scores".to_string()],
+    )?;
+    let increases: &[u8] = if mode == 0 { &[2] } else { &[1, 1] };
+
+    let mut changes = Vec::new();
+    let mut already_picked: Vec<usize> = Vec::new();
+    for amount in increases {
+        let options: Vec<String> = ABILITIES.iter()
+            .map(|a| format!("{} ({})", a, abilities.get(a).unwrap_or(0)))
+            .collect();
+        let pick = loop {
+            let pick = ui::choose(&format!("Increase which ability by {}?", amount), &options)?;
+            if already_picked.contains(&pick) {
+                warn!("Pick two different abilities.");
+            } else {
+                break pick;
+            }
+        };
+        already_picked.push(pick);
+
+        if let Some(score) = abilities.get_mut(ABILITIES[pick]) {
+            // Saturating, since hand-edited or imported scores can sit anywhere in a u8
+            let raised = score.saturating_add(*amount);
+            let new_score = raised.min(20);
+            if new_score < raised {
+                info!("Ability scores cannot exceed 20, capped: {}", ABILITIES[pick]);
+            }
+            changes.push(format!("{} {} -> {}", ABILITIES[pick], score, new_score));
+            *score = new_score;
+        }
+    }
+
+    Ok(changes)
+}
+
+fn choose_spells(character: &mut Character, class: &Class, count: u8, cantrips: bool) -> Result<Vec<String>, anyhow::Error> {
+    let max_level = character.spell_slots.len().max(character.pact_slots.len()) as u8;
+    let mut candidates: Vec<Spell> = store::load_all("Spells")?
+        .iter()
+        .filter_map(|obj| obj.parse::<Spell>().ok())
+        .filter(|s| s.classes.iter().any(|c| c.eq_ignore_ascii_case(&class.name)))
+        .filter(|s| if cantrips { s.level == 0 } else { s.level >= 1 && s.level <= max_level })
+        .filter(|s| !character.spells_known.iter().any(|k| k.eq_ignore_ascii_case(&s.name)))
+        .collect();
+    candidates.sort_by(|a, b| a.level.cmp(&b.level).then(a.name.cmp(&b.name)));
+
+    let mut learned = Vec::new();
+    for i in 0..count {
+        if candidates.is_empty() {
+            info!("No more cached spells to choose from for {}", class.name);
+            break;
+        }
+        let options: Vec<String> = candidates.iter().map(|s| format!("{} (level {})", s.name, s.level)).collect();
+        let kind = if cantrips { "cantrip" } else { "spell" };
+        let pick = ui::choose(&format!("Choose new {} {}/{}:", kind, i + 1, count), &options)?;
+        let spell = candidates.remove(pick);
+        character.spells_known.push(spell.name.clone());
+        learned.push(spell.name);
+    }
+
+    Ok(learned)
+}
+
+/// Adds one level in a class to a cached character, prompting for any choices, and journals the update
+pub fn level_up(character_query: &str, options: &LevelUpOptions) -> Result<(), anyhow::Error> {
+    let character_obj = store::find("Characters", character_query)?;
+    let mut character: Character = character_obj.parse()?;
+
+    let classes: Vec<Class> = store::load_all("Classes")?
+        .iter()
+        .map(|obj| obj.parse::<Class>())
+        .collect::<Result<_, _>>()?;
+
+    let class_name = match (&options.class, character.classes.as_slice()) {
+        (Some(name), _) => name.clone(),
+        (None, [only]) => only.class.clone(),
+        (None, []) => return Err(Error::Usage(format!("{} has no class yet, pass --class to choose one.", character.name)).into()),
+        (None, _) => return Err(Error::Usage(format!("{} has several classes, pass --class to choose one.", character.name)).into()),
+    };
+    let class = find_class(&classes, &class_name)
+        .ok_or_else(|| Error::NotFound(format!("Class '{}' not found in the local cache.", class_name)))?
+        .clone();
+
+    let current_level = character.class_entry(&class.name).map(|c| c.level).unwrap_or(0);
+    let new_level = current_level + 1;
+    if new_level > 20 || character.total_level() >= 20 {
+        return Err(Error::Validation(format!("{} is already at the maximum level.", character.name)).into());
+    }
+
+    // Multiclassing requires meeting the prerequisites of the new class and every current class
+    if current_level == 0 && !character.classes.is_empty() {
+        if !class.meets_prerequisites(&character.abilities) {
+            return Err(Error::Validation(format!("{} does not meet the multiclass prerequisites for {}: {:?}", character.name, class.name, class.multiclass_prerequisites)).into());
+        }
+        for entry in &character.classes {
+            if let Some(existing) = find_class(&classes, &entry.class) {
+                if !existing.meets_prerequisites(&character.abilities) {
+                    return Err(Error::Validation(format!("{} does not meet the multiclass prerequisites of current class {}: {:?}", character.name, existing.name, existing.multiclass_prerequisites)).into());
+                }
+            }
+        }
+    }
+
+    let mut summary: Vec<String> = Vec::new();
+
+    // Hit points: maximum hit die at character level 1, otherwise rolled or average
+    let con_mod = AbilityScores::modifier(character.abilities.constitution);
+    let die = class.hit_die.max(1) as i32;
+    let hp_gain = if character.total_level() == 0 {
+        die + con_mod
+    } else if options.roll_hp {
+        let rolled = roll_die(die as u32) as i32;
+        summary.push(format!("Rolled {} on a d{}", rolled, die));
+        rolled + con_mod
+    } else {
+        die / 2 + 1 + con_mod
+    }.max(1);
+    character.max_hp += hp_gain;
+    character.current_hp += hp_gain;
+    summary.push(format!("Hit points +{} (max {})", hp_gain, character.max_hp));
+
+    match character.classes.iter_mut().find(|c| c.class.eq_ignore_ascii_case(&class.name)) {
+        Some(entry) => entry.level = new_level,
+        None => character.classes.push(CharacterClass { class: class.name.clone(), subclass: None, level: new_level }),
+    }
+    summary.push(format!("{} level {}", class.name, new_level));
+
+    let level_row = class.level(new_level).cloned().unwrap_or_default();
+    let previous_row = class.level(current_level).cloned().unwrap_or_default();
+    for feature in &level_row.features {
+        if !character.features.contains(feature) {
+            character.features.push(feature.clone());
+            summary.push(format!("New feature: {}", feature));
+        }
+    }
+
+    // Subclass choice and subclass features
+    let mut subclass_name = character.class_entry(&class.name).and_then(|c| c.subclass.clone());
+    if subclass_name.is_none() && class.subclass_level != 0 && new_level >= class.subclass_level {
+        let subclasses: Vec<Subclass> = store::load_all("Subclasses")?
+            .iter()
+            .filter_map(|obj| obj.parse::<Subclass>().ok())
+            .filter(|s| s.class.eq_ignore_ascii_case(&class.name))
+            .collect();
+        if subclasses.is_empty() {
+            info!("No cached subclasses found for {}", class.name);
+        } else {
+            let names: Vec<String> = subclasses.iter().map(|s| s.name.clone()).collect();
+            let pick = ui::choose(&format!("Choose a subclass for {}:", class.name), &names)?;
+            subclass_name = Some(names[pick].clone());
+            if let Some(entry) = character.classes.iter_mut().find(|c| c.class.eq_ignore_ascii_case(&class.name)) {
+                entry.subclass = subclass_name.clone();
+            }
+            summary.push(format!("Subclass: {}", names[pick]));
+        }
+    }
+    if let Some(subclass_name) = &subclass_name {
+        let subclass = store::load_all("Subclasses")?
+            .iter()
+            .filter_map(|obj| obj.parse::<Subclass>().ok())
+            .find(|s| s.name.eq_ignore_ascii_case(subclass_name));
+        if let Some(subclass) = subclass {
+            for grant in subclass.features.iter().filter(|f| f.level <= new_level) {
+                if !character.features.contains(&grant.feature) {
+                    character.features.push(grant.feature.clone());
+                    summary.push(format!("New {} feature: {}", subclass.name, grant.feature));
+                }
+            }
+        }
+    }
+
+    if level_row.ability_score_improvement {
+        for change in apply_ability_score_improvement(&mut character.abilities)? {
+            summary.push(format!("Ability score improvement: {}", change));
+        }
+    }
+
+    recompute_spell_slots(&mut character, &classes);
+    if !character.spell_slots.is_empty() {
+        summary.push(format!("Spell slots: {:?}", character.spell_slots));
+    }
+    if !character.pact_slots.is_empty() {
+        summary.push(format!("Pact slots: {:?}", character.pact_slots));
+    }
+
+    let new_cantrips = level_row.cantrips_known.unwrap_or(0).saturating_sub(previous_row.cantrips_known.unwrap_or(0));
+    for spell in choose_spells(&mut character, &class, new_cantrips, true)? {
+        summary.push(format!("New cantrip: {}", spell));
+    }
+    let new_spells = level_row.spells_known.unwrap_or(0).saturating_sub(previous_row.spells_known.unwrap_or(0));
+    for spell in choose_spells(&mut character, &class, new_spells, false)? {
+        summary.push(format!("New spell known: {}", spell));
+    }
+
+    store::update(&character_obj, serde_json::to_value(&character)?)?;
+
+    info!("Leveled up {}", character.name);
+    for line in summary {
+        println!("  - {}", line);
+    }
+
+    Ok(())
+}
@@ -1,67 +1,88 @@
 use std::sync::Arc;
-use crossterm::style::Stylize;
 use reqwest::Client;
 use tokio::sync::Semaphore;
+use error::Error;
+use resource::ResourceKind;
+use log::{error, warn};
 
+pub mod atomic;
 pub mod auth;
+pub mod backup;
 pub mod ui;
 pub mod client;
-
-static LOCAL_ID: i32 = 0; // This is a placeholder for local ID management, can be used to track unsynced items
+pub mod dice;
+pub mod models;
+pub mod store;
+pub mod levelup;
+pub mod validate;
+pub mod balance;
+pub mod cache;
+pub mod campaign;
+pub mod combat;
+pub mod doctor;
+pub mod encounter;
+pub mod error;
+pub mod export;
+pub mod history;
+pub mod import;
+pub mod listing;
+pub mod lock;
+pub mod logging;
+pub mod migrate;
+pub mod notes;
+pub mod resource;
+pub mod search;
+pub mod tui;
+#[cfg(test)]
+mod testing;
 
 pub const SERVER: &str = "https://archerdnd.tech/api";
-pub const REQ_FILES: [&str; 18] = [
-    "saved_objs/",
-    ".auth_tokens.txt",
-    ".session_id.txt",
-    ".auto_login.txt",
-    "saved_objs/Characters/",
-    "saved_objs/Classes/",
-    "saved_objs/Features/",
-    "saved_objs/Items/",
-    "saved_objs/Races/",
-    "saved_objs/Spells/",
-    "saved_objs/Subclasses/",
-    "saved_objs/Characters/session_calls.txt",
-    "saved_objs/Classes/session_calls.txt",
-    "saved_objs/Features/session_calls.txt",
-    "saved_objs/Items/session_calls.txt",
-    "saved_objs/Races/session_calls.txt",
-    "saved_objs/Spells/session_calls.txt",
-    "saved_objs/Subclasses/session_calls.txt",
-];
-
-pub fn check_setup_cmpl() -> Result<(), clap::Error> {
-    let home_dir = dirs::home_dir().ok_or_else(|| clap::Error::raw(
-        clap::error::ErrorKind::Io,
-        "Could not find home directory",
-    ))?;
+/// Files under ~/.archerdndsys/ that are not tied to a content type
+const BASE_FILES: [&str; 4] = ["saved_objs/", client::AUTH_TOKENS_FILE, ".session_id.txt", client::AUTO_LOGIN_FILE];
+
+/// Every file and directory `--setup` creates, relative to ~/.archerdndsys/
+pub fn required_files() -> Vec<String> {
+    let mut files: Vec<String> = BASE_FILES.iter().map(|f| f.to_string()).collect();
+    files.extend(ResourceKind::ALL.iter().map(|k| k.relative_dir()));
+    files.extend(ResourceKind::ALL.iter().filter_map(|k| k.relative_journal()));
+    files
+}
+
+/// Content type directory names under saved_objs/
+pub fn content_types() -> Vec<&'static str> {
+    ResourceKind::ALL.iter().map(|k| k.name()).collect()
+}
+
+/// Case-insensitive lookup of a content type name, e.g. "spells" -> "Spells"
+pub fn content_type(name: &str) -> Option<&'static str> {
+    ResourceKind::from_name(name).map(|k| k.name())
+}
+
+pub fn check_setup_cmpl() -> Result<(), Error> {
+    let home_dir = dirs::home_dir().ok_or_else(|| Error::Setup("Could not find home directory".to_string()))?;
     let archerdndsys_dir = home_dir.join(".archerdndsys");
 
     if !archerdndsys_dir.exists() {
-        return Err(clap::Error::raw(
-            clap::error::ErrorKind::Io,
-            "Please run `archerdndsys --setup` to initialize the client.",
-        ));
+        return Err(Error::Setup("Please run `archerdndsys --setup` to initialize the client.".to_string()));
     }
 
-    for file in &REQ_FILES {
-        let file_path = archerdndsys_dir.join(file);
+    for file in required_files() {
+        let file_path = archerdndsys_dir.join(&file);
         if !file_path.exists() {
-            return Err(clap::Error::raw(
-                clap::error::ErrorKind::Io,
-                format!("Required file '{}' not found. Please run `archerdndsys --setup` to initialize the client.", file),
-            ));
+            return Err(Error::Setup(format!(
+                "Required file '{}' not found. Please run `archerdndsys --setup` to initialize the client, or `archerdndsys doctor` to check the data directory.",
+                file
+            )));
         }
     }
 
     Ok(())
 }
 
-/** Formatting guidelines for local get/set
+/* Formatting guidelines for local get/set
 * filenames: saved_objs/(item type)/(_id).json
-* if item has not been pushed to server it will be saved in "saved_objs/unsynced/(item type)_(x).json"
-*                                  where x is the number of unsynced items of that type saved locally
+* if item has not been pushed to server it will be saved in "saved_objs/(item type)/(x).json"
+*                                  where x is the next free local ID of that type (see store.rs)
 *
 **/
 
@@ -69,27 +90,30 @@ pub fn check_setup_cmpl() -> Result<(), clap::Error> {
  * session_calls.txt formatting line-by-line:
  * [OPERATION] (GET/POST/PUT/DELETE) [SERVER_ENDPOINT] [RESOURCES] (json data)
 **/
-pub async fn push_load() -> Result<(), anyhow::Error> {
+pub async fn push_load(force: bool) -> Result<(), anyhow::Error> {
     // First, clean all session calls
-    for i in 11..REQ_FILES.len() {
-        let file_path = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
-            .join(".archerdndsys")
-            .join(REQ_FILES[i]);
-        client::clean_session_calls(file_path)?
+    for kind in ResourceKind::ALL.into_iter().filter(|k| k.journaled()) {
+        client::clean_session_calls(kind.journal_path()?)?
     }
 
-    // Preload authorization tokens
-
-    let auth_tokens_path = dirs::home_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
-        .join(".archerdndsys")
-        .join(".auth_tokens.txt");
-    if !auth_tokens_path.exists() {
-        return Err(anyhow::anyhow!("Authorization tokens file not found. Please run `archerdndsys --setup` to initialize the client."));
+    // Refuse to upload content that fails validation unless forced
+    let ctx = validate::ValidationContext::load()?;
+    let mut invalid = 0;
+    for kind in ResourceKind::ALL.into_iter().filter(|k| k.journaled()) {
+        invalid += validate::validate_journal(kind.name(), &ctx)?;
+    }
+    if invalid > 0 {
+        if !force {
+            return Err(Error::Validation(format!("{} validation error(s) in pending changes. Fix them or rerun with --force.", invalid)).into());
+        }
+        warn!("Pushing {} validation error(s) because --force was given.", invalid);
     }
 
-    let auth_tokens = client::load_auth_tokens().await?;
+    // Keep a copy of the unsynced state in case the server rejects part of it
+    backup::automatic("pre-sync")?;
+
+    // Preload authorization tokens
+    let auth_tokens = client::load_session()?;
 
     // Initialize the HTTP client and semaphore
     // Open 4 threads to parse files and push to server
@@ -108,11 +132,8 @@ pub async fn push_load() -> Result<(), anyhow::Error> {
     let client = Arc::new(Client::new());
     let semaphore = Arc::new(Semaphore::new(6));
     let mut tasks = Vec::new();
-    for i in 0..REQ_FILES.len() {
-        let file_path = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
-            .join(".archerdndsys")
-            .join(REQ_FILES[i]);
+    for kind in ResourceKind::ALL.into_iter().filter(|k| k.journaled()) {
+        let file_path = kind.journal_path()?;
 
         if file_path.exists() {
             let client_clone = Arc::clone(&client);
@@ -123,19 +144,38 @@ pub async fn push_load() -> Result<(), anyhow::Error> {
             tasks.push(tokio::spawn(async move {
                 let _permit = semaphore_clone.acquire().await.unwrap();
                 let file_path_str = file_path_clone.display().to_string();
+                // Failures are reported as they happen and collected so the sync as a whole can fail
+                let mut failures = Vec::new();
                 match client::collect_session_calls(file_path_clone) {
                     Ok(calls) => {
                         for call in calls {
+                            if client::is_reference_call(&call) {
+                                continue;
+                            }
                             if let Err(e) = client::process_call(call, Arc::clone(&client_clone), auth_tokens_clone.clone()).await {
-                                eprintln!("Error processing call from {}: {}", file_path_str, e);
+                                error!("Error processing call from {}: {:#}", file_path_str, e);
+                                failures.push(e);
                             }
                         }
                     },
-                    Err(e) => eprintln!("Error reading session calls from {}: {}", file_path_str, e),
+                    Err(e) => {
+                        error!("Error reading session calls from {}: {:#}", file_path_str, e);
+                        failures.push(e);
+                    }
                 }
+                failures
             }))
         }
-    } // Test run
+    }
 
-    Ok(())
+    let mut failures = Vec::new();
+    for result in futures::future::join_all(tasks).await {
+        failures.extend(result?);
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let count = failures.len();
+    // The first failure decides the category, e.g. an expired login fails every call the same way
+    Err(failures.remove(0).context(format!("{} pending call(s) failed", count)))
 }
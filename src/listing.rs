@@ -0,0 +1,179 @@
+// Listing of everything in saved_objs/ with display names, sync state and pending journal operations
+
+use crate::store::{self, StoredObject};
+use crate::{client, content_types, logging};
+use chrono::{DateTime, Local};
+use crossterm::style::Stylize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use log::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    /// Exists on the server (`_<id>.json`)
+    Synced,
+    /// Created locally, waiting for its first push
+    Local,
+    /// Read-only reference content such as the SRD, never pushed
+    Reference,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    Name,
+    Type,
+    State,
+    Pending,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListEntry {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+    pub name: String,
+    pub state: SyncState,
+    /// Journaled operations not yet pushed
+    pub pending: usize,
+    pub modified: DateTime<Local>,
+}
+
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    pub kind: Option<&'static str>,
+    pub state: Option<SyncState>,
+    /// Case-insensitive substring of the display name
+    pub name: Option<String>,
+    pub pending_only: bool,
+    pub sort: Option<SortKey>,
+    pub reverse: bool,
+}
+
+fn sync_state(obj: &StoredObject) -> SyncState {
+    if obj.is_read_only() {
+        SyncState::Reference
+    } else if obj.is_synced() {
+        SyncState::Synced
+    } else {
+        SyncState::Local
+    }
+}
+
+/// Counts journal lines per object ID: local objects by their `local_id`, synced ones by the URL's last segment
+pub fn pending_ops(kind: &str) -> Result<BTreeMap<String, usize>, anyhow::Error> {
+    let mut pending = BTreeMap::new();
+    let path = store::journal_path(kind)?;
+    let Ok(contents) = fs::read_to_string(&path) else { return Ok(pending) };
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let Some((_, endpoint, data)) = client::parse_line(line) else { continue };
+        let local_id = data.as_deref()
+            .and_then(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+            .and_then(|v| v.get("local_id").and_then(|id| id.as_str()).map(|id| id.to_string()));
+        let id = match local_id {
+            Some(id) => id,
+            None => format!("_{}", endpoint.rsplit('/').next().unwrap_or("")),
+        };
+        *pending.entry(id).or_default() += 1;
+    }
+    Ok(pending)
+}
+
+pub fn collect(options: &ListOptions) -> Result<Vec<ListEntry>, anyhow::Error> {
+    let kinds: Vec<&str> = match options.kind {
+        Some(kind) => vec![kind],
+        None => content_types(),
+    };
+    let name_filter = options.name.as_ref().map(|n| n.to_lowercase());
+
+    let mut entries = Vec::new();
+    for kind in kinds {
+        let pending = pending_ops(kind)?;
+        for obj in store::load_all(kind)? {
+            let modified: DateTime<Local> = fs::metadata(&obj.path).and_then(|m| m.modified())
+                .map(DateTime::from)
+                .unwrap_or_else(|_| Local::now());
+            entries.push(ListEntry {
+                kind: obj.kind.clone(),
+                id: obj.id.clone(),
+                name: obj.name().to_string(),
+                state: sync_state(&obj),
+                pending: pending.get(&obj.id).copied().unwrap_or(0),
+                modified,
+            });
+        }
+    }
+
+    entries.retain(|e| {
+        options.state.is_none_or(|s| e.state == s)
+            && (!options.pending_only || e.pending > 0)
+            && name_filter.as_ref().is_none_or(|n| e.name.to_lowercase().contains(n.as_str()))
+    });
+    match options.sort.unwrap_or(SortKey::Type) {
+        SortKey::Name => entries.sort_by_key(|e| e.name.to_lowercase()),
+        SortKey::Type => entries.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))),
+        SortKey::State => entries.sort_by_key(|e| e.state),
+        SortKey::Pending => entries.sort_by_key(|e| std::cmp::Reverse(e.pending)),
+        SortKey::Modified => entries.sort_by_key(|e| std::cmp::Reverse(e.modified)),
+    }
+    if options.reverse {
+        entries.reverse();
+    }
+    Ok(entries)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn state_label(state: SyncState) -> &'static str {
+    match state {
+        SyncState::Synced => "synced",
+        SyncState::Local => "local",
+        SyncState::Reference => "reference",
+    }
+}
+
+pub fn print(entries: &[ListEntry], format: ListFormat) -> Result<(), anyhow::Error> {
+    match format {
+        ListFormat::Json => println!("{}", serde_json::to_string_pretty(entries)?),
+        ListFormat::Csv => {
+            println!("type,id,name,state,pending,modified");
+            for e in entries {
+                println!("{},{},{},{},{},{}", csv_field(&e.kind), csv_field(&e.id), csv_field(&e.name), state_label(e.state), e.pending, e.modified.to_rfc3339());
+            }
+        }
+        ListFormat::Table => {
+            if entries.is_empty() {
+                info!("No saved objects match.");
+                return Ok(());
+            }
+            println!("{}", logging::bold(format!("{:<12} {:<24} {:<32} {:<10} {:>7}  {}", "TYPE", "ID", "NAME", "STATE", "PENDING", "MODIFIED")));
+            for e in entries {
+                let state = format!("{:<10}", state_label(e.state));
+                let state = match e.state {
+                    SyncState::Synced => state.green(),
+                    SyncState::Local => state.yellow(),
+                    SyncState::Reference => state.dark_grey(),
+                };
+                println!("{:<12} {:<24} {:<32} {} {:>7}  {}", e.kind, e.id, e.name, logging::styled(state), e.pending, e.modified.format("%Y-%m-%d %H:%M"));
+            }
+            let pending: usize = entries.iter().map(|e| e.pending).sum();
+            info!("{} object(s), {} pending operation(s)", entries.len(), pending);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+// Advisory lock on ~/.archerdndsys/ held for the whole of one invocation
+// A second archerdndsys waits for the first to finish, and gives up with an error after LOCK_TIMEOUT_SECS
+
+use crate::error::Error;
+use crate::store;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use log::info;
+
+pub const LOCK_FILE: &str = ".lock";
+const LOCK_TIMEOUT_SECS: u64 = 30;
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// Released when dropped, or by the OS if the process dies
+#[derive(Debug)]
+pub struct DataLock {
+    _file: File,
+}
+
+/// Locks the data directory, waiting for another invocation to release it. None when there is no data directory yet
+pub fn acquire() -> Result<Option<DataLock>, anyhow::Error> {
+    let data_dir = store::data_dir()?;
+    if !data_dir.exists() {
+        return Ok(None);
+    }
+    let path = data_dir.join(LOCK_FILE);
+    let mut file = OpenOptions::new().create(true).read(true).write(true).truncate(false).open(&path)?;
+
+    let start = Instant::now();
+    let mut announced = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(fs::TryLockError::WouldBlock) => {
+                // The holder writes its PID into the file
+                let holder = fs::read_to_string(&path).ok()
+                    .map(|pid| pid.trim().to_string())
+                    .filter(|pid| !pid.is_empty())
+                    .map(|pid| format!(" (PID {})", pid))
+                    .unwrap_or_default();
+                if start.elapsed() >= Duration::from_secs(LOCK_TIMEOUT_SECS) {
+                    return Err(Error::Conflict(format!(
+                        "The data directory is still in use by another archerdndsys command{} after {}s. Try again once it has finished.",
+                        holder, LOCK_TIMEOUT_SECS
+                    )).into());
+                }
+                if !announced {
+                    info!("Waiting for another archerdndsys command{} to finish...", holder);
+                    announced = true;
+                }
+                std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+            }
+            Err(fs::TryLockError::Error(e)) => return Err(Error::Storage(format!("Could not lock {}: {}", path.display(), e)).into()),
+        }
+    }
+
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    Ok(Some(DataLock { _file: file }))
+}
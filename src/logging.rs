@@ -0,0 +1,128 @@
+// Leveled logging for every command
+// Messages go to stderr so stdout only carries data (listings, reports, exports, JSON). -v/-q pick the level shown,
+// --log-file also keeps everything down to request/response traces in ~/.archerdndsys/logs/, rotated by size
+
+use crossterm::style::{style, StyledContent, Stylize};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use chrono::Local;
+use crate::store;
+
+pub const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "archerdndsys.log";
+const LOG_MAX_BYTES: u64 = 1024 * 1024;
+/// Rotated files kept next to the current one, archerdndsys.log.1 being the newest
+const LOG_KEEP: u32 = 4;
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+struct Logger {
+    /// Most detailed level shown on stderr
+    level: LevelFilter,
+    /// Takes every level when --log-file is given
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies log too, only our own messages are of interest
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME")) && (metadata.level() <= self.level || self.file.is_some())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() <= self.level {
+            let tag = format!("[{}]", record.level());
+            let tag = match record.level() {
+                Level::Error => tag.red(),
+                Level::Warn => tag.yellow(),
+                Level::Info => tag.green(),
+                Level::Debug | Level::Trace => tag.dark_grey(),
+            };
+            eprintln!("{} {}", styled(tag), record.args());
+        }
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{} {:<5} {}", Local::now().format("%Y-%m-%d %H:%M:%S%.3f"), record.level(), record.args());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Level shown on stderr for the number of -v and -q flags: -q errors only, -qq nothing, -v debug, -vv traces
+pub fn level(verbose: u8, quiet: u8) -> LevelFilter {
+    match verbose as i16 - quiet as i16 {
+        ..=-2 => LevelFilter::Off,
+        -1 => LevelFilter::Error,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Installs the logger. Colour is used only when both stdout and stderr are terminals and NO_COLOR is unset,
+/// so neither piped data nor a redirected log picks up escape codes
+pub fn init(level: LevelFilter, log_file: bool) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let color = !no_color && std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    COLOR.store(color, Ordering::Relaxed);
+
+    // Without a data directory there is nowhere to log to yet, and creating one would look like a broken setup
+    let data_dir = store::data_dir().ok().filter(|dir| dir.exists());
+    let file = match (&data_dir, log_file) {
+        (Some(dir), true) => open_log(&dir.join(LOG_DIR)).ok(),
+        _ => None,
+    };
+    let opened = file.is_some();
+    let max_level = if opened { LevelFilter::Trace } else { level };
+    if log::set_boxed_logger(Box::new(Logger { level, file: file.map(Mutex::new) })).is_ok() {
+        log::set_max_level(max_level);
+    }
+    if log_file && !opened {
+        log::warn!("No log file written: run `archerdndsys --setup` first, or check that ~/.archerdndsys/{} is writable.", LOG_DIR);
+    }
+}
+
+/// Opens the log for appending, first rotating it once it has grown past LOG_MAX_BYTES
+fn open_log(dir: &Path) -> Result<File, anyhow::Error> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(LOG_FILE);
+    if fs::metadata(&path).is_ok_and(|m| m.len() > LOG_MAX_BYTES) {
+        let rotated = |n: u32| dir.join(format!("{}.{}", LOG_FILE, n));
+        let _ = fs::remove_file(rotated(LOG_KEEP));
+        for n in (1..LOG_KEEP).rev() {
+            let _ = fs::rename(rotated(n), rotated(n + 1));
+        }
+        fs::rename(&path, rotated(1))?;
+    }
+    Ok(OpenOptions::new().create(true).append(true).open(&path)?)
+}
+
+/// Styled text when colour is on, the bare content otherwise. crossterm's own NO_COLOR check still writes resets
+pub fn styled<D: Display>(content: StyledContent<D>) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        content.to_string()
+    } else {
+        content.content().to_string()
+    }
+}
+
+/// Bold text for headers and names on stdout
+pub fn bold<D: Display>(content: D) -> String {
+    styled(style(content).bold())
+}
@@ -1,15 +1,15 @@
-mod auth;
-mod ui;
-mod client;
-
+use anyhow::Context;
 use clap::{Parser, Subcommand};
-use anyhow;
-use crossterm::style::Stylize;
-use archerdndsys::{push_load, check_setup_cmpl, REQ_FILES, SERVER};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use archerdndsys::error::{self, Error};
+use archerdndsys::{auth, backup, balance, cache, campaign, combat, doctor, encounter, export, history, import, listing, lock, migrate, notes, search, tui, levelup, logging, validate, push_load, check_setup_cmpl, content_type, content_types, required_files};
+use log::{debug, error, info};
 
 #[derive(Parser)]
 #[command(name = "archerdndsys", about = "A client for the Archer RPG System")]
 #[command(version = "0.1.0",term_width = 80)]
+#[command(group(clap::ArgGroup::new("eviction").args(["clear_cache", "max_size"]).multiple(true)))]
 #[command(author = "Lockie", long_about = "A client for the Archer RPG System. \n\nThis client allows you to manage your characters, campaigns, and other data for the Archer RPG System. It provides a command line interface to interact with the server and manage your data.\n\nWhen invoked with no flags\
 , it will list all saved data the user has locally by filenames. \n\nTo get started, run `archerdndsys --setup` to initialize the client.")]
 struct Cli {
@@ -44,18 +44,493 @@ struct Cli {
     /// Push all server calls to the server and update the database
     #[arg(short, long)]
     push_load: bool,
+
+    /// Push even if pending changes fail validation
+    #[arg(long, requires = "push_load")]
+    force: bool,
     
-    /// Calculate the total size of the cached objects
+    /// Show the size of the cached objects by type and category (synced, local, reference, journal)
     #[arg(short='S', long)]
     cache_size: bool,
+
+    /// Print the --cache-size report as JSON, and errors as a JSON object on stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Show less on stderr: -q only errors, -qq nothing. The exit status still tells what went wrong
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// Show more on stderr: -v debug messages, -vv request/response traces. Also lists every file in the --cache-size report
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Also write every message, down to request/response traces, to ~/.archerdndsys/logs/archerdndsys.log
+    #[arg(long, global = true)]
+    log_file: bool,
     
-    /// Clear all saved data not accessed in the last [argument] days.
-    /// If argument is 0 all cache will be cleared
+    /// Evict synced cache entries not used in the last [argument] days.
+    /// If argument is 0 every synced entry is evicted. Journals, local objects, pending edits and reference content are always kept
     #[arg(short='X', value_name = "DAYS", long)]
     clear_cache: Option<u64>,
+
+    /// Evict the least recently used synced entries until saved objects fit in SIZE, e.g. 50MB
+    #[arg(long, value_name = "SIZE", value_parser = cache::parse_size)]
+    max_size: Option<u64>,
+
+    /// Show what --clear-cache or --max-size would evict without removing anything
+    #[arg(long, requires = "eviction")]
+    dry_run: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Add a level to a cached character using the cached Class and Subclass data
+    LevelUp {
+        /// Character ID or name
+        character: String,
+
+        /// Class to gain the level in (required for new or multiple classes)
+        #[arg(long)]
+        class: Option<String>,
+
+        /// Roll the hit die instead of taking the average
+        #[arg(long)]
+        roll: bool,
+    },
+
+    /// Validate every cached object against the schema and content rules
+    Validate {
+        /// Only validate one content type, e.g. Spells
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+    },
+
+    /// Compare a homebrew Spell or Item against cached content of the same level or rarity
+    Balance {
+        /// Spell or Item ID or name
+        target: String,
+
+        /// Restrict the lookup to Spells or Items
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+    },
+
+    /// Import content into the local store
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+
+    /// Export content to Markdown, HTML or Homebrewery markup
+    Export {
+        /// Objects to export, by name or ID
+        objects: Vec<String>,
+        /// Restrict lookups to one content type
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+        /// Export every object whose source matches, e.g. an imported homebrew file
+        #[arg(long)]
+        collection: Option<String>,
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: export::ExportFormat,
+        /// Document title (defaults to the collection name)
+        #[arg(long)]
+        title: Option<String>,
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Search cached content by text and filters, e.g. `search fire 'level<=3' school:evocation class:wizard`
+    Search {
+        /// Search words and field filters (field:value, field~text, field<=n, field!=value); quote filters using < or >
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Only search one content type
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+        /// Maximum number of results to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Discard the search index and rebuild it from scratch
+        #[arg(long)]
+        rebuild: bool,
+    },
+
+    /// List saved objects with their name, sync state and pending operations (the default with no flags)
+    List {
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+        #[arg(long, value_enum)]
+        state: Option<listing::SyncState>,
+        /// Only objects whose name contains this text
+        #[arg(long)]
+        name: Option<String>,
+        /// Only objects with journaled operations waiting for a push
+        #[arg(long)]
+        pending: bool,
+        #[arg(long, value_enum)]
+        sort: Option<listing::SortKey>,
+        #[arg(long)]
+        reverse: bool,
+        #[arg(long, value_enum, default_value = "table")]
+        format: listing::ListFormat,
+    },
+
+    /// Session notes and journal for a campaign
+    Notes {
+        #[command(subcommand)]
+        action: NotesAction,
+    },
+
+    /// Manage campaigns (sent straight to the server, at most 15 requests per minute)
+    Campaign {
+        #[command(subcommand)]
+        action: CampaignAction,
+    },
+
+    /// Build encounters and rate their difficulty against a party
+    Encounter {
+        #[command(subcommand)]
+        action: EncounterAction,
+    },
+
+    /// Initiative and combat tracker for characters and monsters
+    Combat {
+        #[command(subcommand)]
+        action: CombatAction,
+    },
+
+    /// Check the data directory, credentials, journals and server connection for problems
+    Doctor {
+        /// Repair what can be repaired; anything removed is kept in ~/.archerdndsys/doctor_quarantine/
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// List the kept versions of a locally edited object
+    History {
+        /// Object ID or name
+        object: String,
+        /// Restrict the lookup to one content type
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+    },
+
+    /// Show what changed between a kept version of an object and the current one
+    Diff {
+        /// Object ID or name
+        object: String,
+        /// Revision number from `history`
+        rev: u32,
+        /// Compare against this revision instead of the current version
+        #[arg(long)]
+        against: Option<u32>,
+        /// Restrict the lookup to one content type
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+    },
+
+    /// Restore a kept version of an object; the change is journaled for the next --push-load
+    Revert {
+        /// Object ID or name
+        object: String,
+        /// Revision number from `history`
+        rev: u32,
+        /// Restrict the lookup to one content type
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+    },
+
+    /// Back up and restore the whole local data set
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
 }
 
-async fn client_init_startup() -> Result<(), clap::Error> {
+#[derive(Subcommand)]
+enum BackupAction {
+    /// Archive ~/.archerdndsys/ into ~/.archerdndsys/backups/
+    Create {
+        /// Short note kept in the manifest and the archive name
+        #[arg(long)]
+        label: Option<String>,
+        /// Also archive the saved login and session; anyone with the archive can use them
+        #[arg(long)]
+        include_credentials: bool,
+    },
+    /// List backups, newest last
+    List,
+    /// Verify a backup and replace the current data with it
+    Restore {
+        /// Backup name from `backup list`, a path to an archive, or "latest"
+        backup: String,
+        /// Only show what would be restored, replaced and removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum EncounterAction {
+    /// Create an encounter for a party
+    Create {
+        name: String,
+        /// Party member from the cached Characters (repeatable)
+        #[arg(long)]
+        character: Vec<String>,
+        /// Party member given only by level (repeatable)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=20))]
+        level: Vec<u8>,
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Replace the party of an encounter
+    Party {
+        encounter: String,
+        #[arg(long)]
+        character: Vec<String>,
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=20))]
+        level: Vec<u8>,
+        /// Add to the party instead of replacing it
+        #[arg(long)]
+        append: bool,
+    },
+    /// Add cached Creatures to an encounter
+    Add {
+        encounter: String,
+        creature: String,
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+    },
+    /// Remove creatures from an encounter (all copies unless --count is given)
+    Remove {
+        encounter: String,
+        creature: String,
+        #[arg(long)]
+        count: Option<u32>,
+    },
+    /// Show the XP budget and difficulty of an encounter
+    Show { encounter: String },
+    /// List saved encounters with their difficulty
+    List,
+    /// Start a combat with the encounter's creatures and cached party members
+    Run {
+        encounter: String,
+        /// Discard the combat in progress
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CombatAction {
+    /// Start a new combat
+    Start {
+        /// Discard the combat in progress
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add a cached character, rolling initiative with its Dexterity modifier
+    AddCharacter {
+        /// Character ID or name
+        character: String,
+        /// Use this initiative instead of rolling
+        #[arg(long, allow_hyphen_values = true)]
+        initiative: Option<i32>,
+    },
+    /// Add one or more monsters, using the cached Creature of that name for any stat not given
+    AddMonster {
+        name: String,
+        /// Hit points as a number or dice expression, e.g. 2d6+2 (default: the stat block's, or 10)
+        #[arg(long)]
+        hp: Option<String>,
+        #[arg(long, allow_hyphen_values = true)]
+        init_bonus: Option<i32>,
+        #[arg(long)]
+        ac: Option<i32>,
+        /// Constitution saving throw modifier for concentration checks
+        #[arg(long, allow_hyphen_values = true)]
+        con_save: Option<i32>,
+        /// Add this many copies, each rolling its own HP and initiative
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+        #[arg(long, allow_hyphen_values = true)]
+        initiative: Option<i32>,
+    },
+    /// Remove a combatant (list number or name)
+    Remove { who: String },
+    /// End the current turn and move to the next combatant
+    Next,
+    /// Show the initiative order
+    Status,
+    /// Deal damage; temporary HP absorb it first and concentration is checked automatically
+    Damage {
+        who: String,
+        amount: i32,
+        /// Critical hit, counts as two failed death saves against a downed character
+        #[arg(long)]
+        crit: bool,
+        /// Only report the concentration DC instead of rolling the save
+        #[arg(long)]
+        manual: bool,
+    },
+    Heal { who: String, amount: i32 },
+    /// Grant temporary hit points (they do not stack)
+    Temp { who: String, amount: i32 },
+    /// Apply or remove a condition
+    Condition {
+        who: String,
+        name: String,
+        /// Expires after this many of the combatant's turns
+        #[arg(long)]
+        rounds: Option<u32>,
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Start concentrating on a spell, or stop with --end
+    Concentrate {
+        who: String,
+        spell: Option<String>,
+        #[arg(long)]
+        end: bool,
+    },
+    /// Roll a death saving throw for a dying character
+    DeathSave {
+        who: String,
+        /// Record a roll made at the table instead of rolling
+        #[arg(long)]
+        roll: Option<i32>,
+    },
+    /// End the combat
+    End {
+        /// Write current HP back to the cached characters
+        #[arg(long)]
+        save_hp: bool,
+    },
+    /// Open the interactive combat tracker
+    Tui,
+}
+
+#[derive(Subcommand)]
+enum CampaignAction {
+    /// Create a campaign with yourself as DM
+    Create {
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Join a campaign with an invite code
+    Join { invite_code: String },
+    /// Leave a campaign
+    Leave { campaign: String },
+    /// Refresh and list the campaigns you belong to
+    List,
+    /// Show a cached campaign with its notes
+    Show { campaign: String },
+    /// Change a member's role (DM only)
+    Role {
+        campaign: String,
+        /// Member user ID or username
+        user: String,
+        #[arg(value_enum)]
+        role: archerdndsys::models::CampaignRole,
+    },
+    /// Attach a character and/or a homebrew collection
+    Attach {
+        campaign: String,
+        #[arg(long)]
+        character: Option<String>,
+        /// Source name of imported content (DM only)
+        #[arg(long)]
+        collection: Option<String>,
+    },
+    /// Remove a character and/or a homebrew collection
+    Detach {
+        campaign: String,
+        #[arg(long)]
+        character: Option<String>,
+        #[arg(long)]
+        collection: Option<String>,
+    },
+    /// Add a note to a campaign
+    Note { campaign: String, text: String },
+}
+
+#[derive(Subcommand)]
+enum NotesAction {
+    /// Mark the start of a play session
+    Start {
+        campaign: String,
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Mark the end of the session in progress
+    End { campaign: String },
+    /// Add an entry to the session in progress; [[Name]] in the text links a cached Item or Character
+    Add {
+        campaign: String,
+        text: String,
+        #[arg(long, value_enum)]
+        tag: Option<archerdndsys::models::NoteTag>,
+        /// Link a cached Item (repeatable)
+        #[arg(long)]
+        item: Vec<String>,
+        /// Link a cached Character (repeatable)
+        #[arg(long)]
+        character: Vec<String>,
+    },
+    /// Show the session log
+    List {
+        campaign: String,
+        #[arg(long, value_enum)]
+        tag: Option<archerdndsys::models::NoteTag>,
+    },
+    /// Export the session log to Markdown
+    Export {
+        campaign: String,
+        /// Only export this session number
+        #[arg(long)]
+        session: Option<u32>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportSource {
+    /// Load SRD reference content, read-only. Without --file this is a small abridged sample, not the full SRD 5.1
+    Srd {
+        /// Load a full SRD dataset file (same format as data/srd_sample.json) instead of the embedded sample
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Import a 5etools homebrew JSON file
+    #[command(name = "5etools")]
+    FiveTools {
+        file: PathBuf,
+        /// Source name recorded on imported objects (defaults to the file's _meta source)
+        #[arg(long)]
+        source: Option<String>,
+    },
+    /// Import a Foundry VTT dnd5e compendium export (JSON or .db pack)
+    Foundry {
+        file: PathBuf,
+        /// Source name recorded on imported objects (defaults to the file name)
+        #[arg(long)]
+        source: Option<String>,
+    },
+}
+
+async fn client_init_startup() -> Result<(), Error> {
     // 1. Check if ~/.archerdndsys/ exists, if not create it
     // 2. If ~/.archerdndsys/ exists, create, or check if following files exist:
     //    a. /session_calls.txt
@@ -63,240 +538,372 @@ async fn client_init_startup() -> Result<(), clap::Error> {
     //    c. /.auth_tokens.txt
     //    d. /.session_id.txt
 
-    println!("{}", "[INFO] Initializing client...".yellow());
+    info!("Initializing client...");
 
-    let home_dir = dirs::home_dir().ok_or_else(|| clap::Error::raw(
-        clap::error::ErrorKind::Io,
-        "Could not find home directory",
-    ))?;
+    let home_dir = dirs::home_dir().ok_or_else(|| Error::Setup("Could not find home directory".to_string()))?;
 
     let archerdndsys_dir = home_dir.join(".archerdndsys");
-    println!("{}", "[INFO] Checking for archerdndsys management directory: ".yellow());
+    debug!("Checking for archerdndsys management directory");
 
     if !archerdndsys_dir.exists() {
-        println!("{}", "[INFO] Directory not found. Creating archerdndsys management directory...".yellow());
-        std::fs::create_dir_all(&archerdndsys_dir).map_err(|e| {
-            println!("{} {}", "[ERROR] Failed to create directory: ".red(), e);
-            clap::Error::raw(clap::error::ErrorKind::Io, format!("Failed to create directory: {}", e))
-        })?;
+        info!("Directory not found. Creating archerdndsys management directory...");
+        std::fs::create_dir_all(&archerdndsys_dir).map_err(|e| Error::Storage(format!("Failed to create directory: {}", e)))?;
     }
 
-    println!("{}", "[INFO] Directory found.".green());
-    println!("{}", "[INFO] Checking for required files...".yellow());
+    debug!("Directory found.");
+    debug!("Checking for required files...");
 
-    for file in &REQ_FILES {
+    for file in required_files() {
+        let file = file.as_str();
         let file_path = archerdndsys_dir.join(file);
-        println!("{} {}", "[INFO] Checking for file: ".yellow(), file.bold());
+        debug!("Checking for file: {}", file);
         if !file_path.exists() {
-            println!("{} {}", "[INFO] File not found. Creating: ".yellow(), file.bold());
+            debug!("File not found: {}", file);
             if file.ends_with('/') {
-                println!("{} {}", "[INFO] Creating directory: ".yellow(), file.bold());
-                std::fs::create_dir_all(&file_path).map_err(|e| {
-                    println!("{} {}", "[ERROR] Failed to create directory: ".red(), e);
-                    clap::Error::raw(clap::error::ErrorKind::Io, format!("Failed to create directory: {}", e))
-                })?;
+                info!("Creating directory: {}", file);
+                std::fs::create_dir_all(&file_path).map_err(|e| Error::Storage(format!("Failed to create directory: {}", e)))?;
             } else {
-                println!("{} {}", "[INFO] Creating file: ".yellow(), file.bold());
-                std::fs::File::create(&file_path).map_err(|e| {
-                    println!("{} {}", "[ERROR] Failed to create file: ".red(), e);
-                    clap::Error::raw(clap::error::ErrorKind::Io, format!("Failed to create file: {}", e))
-                })?;
+                info!("Creating file: {}", file);
+                std::fs::File::create(&file_path).map_err(|e| Error::Storage(format!("Failed to create file: {}", e)))?;
             }
         }
     }
 
-    println!("{}", "[INFO] Client initialization complete.".green());
+    migrate::stamp().map_err(|e| Error::Storage(format!("Failed to write VERSION: {}", e)))?;
+
+    info!("Client initialization complete.");
     Ok(())
 }
 
+/// Reports why the command failed: a JSON object on stderr with --json, otherwise an error message at the -q/-v level
+fn report_error(e: &anyhow::Error, json: bool) {
+    let kind = error::classify(e);
+    if json {
+        let report = serde_json::json!({
+            "error": { "code": kind.code(), "exit_code": kind.exit_code(), "message": format!("{:#}", e) }
+        });
+        eprintln!("{}", report);
+    } else {
+        // The outermost context names the command that failed, the rest of the chain says why
+        error!("{:#} ({})", e, kind.code());
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
+async fn main() -> ExitCode {
     let args = Cli::parse();
-    let no_flags = !(args.setup || args.login || args.register || args.auto_login || args.run || args.check_setup || args.logout || args.push_load || args.cache_size || args.clear_cache.is_some());
+    logging::init(logging::level(args.verbose, args.quiet), args.log_file);
+    let json = args.json;
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report_error(&e, json);
+            ExitCode::from(error::classify(&e).exit_code())
+        }
+    }
+}
+
+async fn run(args: Cli) -> Result<(), anyhow::Error> {
+    let no_flags = !(args.setup || args.login || args.register || args.auto_login || args.run || args.check_setup || args.logout || args.push_load || args.cache_size || args.clear_cache.is_some() || args.max_size.is_some() || args.command.is_some());
+
+    // Held until main returns so concurrent invocations queue instead of interleaving writes
+    let _lock = lock::acquire().context("Could not lock the data directory")?;
+
+    // The doctor reports an outdated layout itself, so it still runs when an upgrade fails
+    if !matches!(args.command, Some(Commands::Doctor { .. })) {
+        migrate::on_startup().context("Data directory upgrade failed")?;
+    }
+
     if args.setup {
-        if let Err(e) = client_init_startup().await {
-            println!("{}: {}", "[ERROR] Client setup failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Client setup complete.".green());
-            return Ok(());
+        client_init_startup().await.context("Client setup failed")?;
+        info!("Client setup complete.");
+        info!("Run `archerdndsys import srd` to load a sample of SRD 5.1 reference content.");
+        return Ok(());
+    }
+
+    if let Some(command) = args.command {
+        match command {
+            Commands::LevelUp { character, class, roll } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let options = levelup::LevelUpOptions { class, roll_hp: roll };
+                levelup::level_up(&character, &options).context("Level up failed")?;
+            }
+            Commands::Validate { kind } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let kinds: Vec<&str> = content_types().into_iter()
+                    .filter(|k| kind.as_ref().is_none_or(|wanted| k.eq_ignore_ascii_case(wanted)))
+                    .collect();
+                if kinds.is_empty() {
+                    return Err(Error::Usage(format!("Unknown content type: {}", kind.unwrap_or_default())).into());
+                }
+                match validate::validate_cache(&kinds) {
+                    Ok((checked, 0, warnings)) => {
+                        info!("Validation passed: {} objects, {} warning(s)", checked, warnings);
+                    }
+                    Ok((checked, errors, warnings)) => {
+                        return Err(Error::Validation(format!("{} objects, {} error(s), {} warning(s)", checked, errors, warnings)))
+                            .context("Validation failed");
+                    }
+                    Err(e) => return Err(e.context("Validation failed")),
+                }
+            }
+            Commands::Balance { target, kind } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let kind = match kind.as_deref().map(|k| content_type(k).ok_or(k)) {
+                    Some(Err(unknown)) => return Err(Error::Usage(format!("Unknown content type: {}", unknown)).into()),
+                    Some(Ok(k)) => Some(k),
+                    None => None,
+                };
+                balance::analyze(&target, kind).context("Balance analysis failed")?;
+            }
+            Commands::Import { source } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                match source {
+                    ImportSource::Srd { file } => match import::srd::import_srd(file.as_deref()) {
+                        Ok(counts) => {
+                            for (kind, count) in counts {
+                                info!("Imported {} {}", count, kind);
+                            }
+                            info!("SRD import complete. Reference content is read-only and never pushed.");
+                        }
+                        Err(e) => return Err(e.context("SRD import failed")),
+                    },
+                    ImportSource::FiveTools { file, source } => match import::fivetools::import_file(&file, source.as_deref()) {
+                        Ok(report) => report.print(),
+                        Err(e) => return Err(e.context("5etools import failed")),
+                    },
+                    ImportSource::Foundry { file, source } => match import::foundry::import_file(&file, source.as_deref()) {
+                        Ok(report) => report.print(),
+                        Err(e) => return Err(e.context("Foundry import failed")),
+                    },
+                }
+            }
+            Commands::Export { objects, kind, collection, format, title, output } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let kind = match kind {
+                    Some(kind) => match content_type(&kind) {
+                        Some(kind) => Some(kind),
+                        None => return Err(Error::Usage(format!("Unknown content type: {}", kind)).into()),
+                    },
+                    None => None,
+                };
+                let title = title.or(collection.clone()).unwrap_or_else(|| "Homebrew".to_string());
+                let result = export::select(&objects, kind, collection.as_deref())
+                    .and_then(|selected| export::export(&selected, format, &title, output.as_deref()));
+                result.context("Export failed")?;
+            }
+            Commands::Search { query, kind, limit, rebuild } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let kind = match kind {
+                    Some(kind) => match content_type(&kind) {
+                        Some(kind) => Some(kind),
+                        None => return Err(Error::Usage(format!("Unknown content type: {}", kind)).into()),
+                    },
+                    None => None,
+                };
+                search::run(&query, kind, limit, rebuild).context("Search failed")?;
+            }
+            Commands::List { kind, state, name, pending, sort, reverse, format } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let kind = match kind {
+                    Some(kind) => match content_type(&kind) {
+                        Some(kind) => Some(kind),
+                        None => return Err(Error::Usage(format!("Unknown content type: {}", kind)).into()),
+                    },
+                    None => None,
+                };
+                let options = listing::ListOptions { kind, state, name, pending_only: pending, sort, reverse };
+                listing::collect(&options).and_then(|entries| listing::print(&entries, format)).context("Listing failed")?;
+            }
+            Commands::Campaign { action } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let result = match action {
+                    CampaignAction::Create { name, description } => campaign::create(&name, description.as_deref()).await,
+                    CampaignAction::Join { invite_code } => campaign::join(&invite_code).await,
+                    CampaignAction::Leave { campaign } => campaign::leave(&campaign).await,
+                    CampaignAction::List => campaign::list().await,
+                    CampaignAction::Show { campaign } => campaign::show(&campaign),
+                    CampaignAction::Role { campaign, user, role } => campaign::set_role(&campaign, &user, role).await,
+                    CampaignAction::Attach { campaign, character, collection } => {
+                        campaign::attach(&campaign, character.as_deref(), collection.as_deref(), false).await
+                    }
+                    CampaignAction::Detach { campaign, character, collection } => {
+                        campaign::attach(&campaign, character.as_deref(), collection.as_deref(), true).await
+                    }
+                    CampaignAction::Note { campaign, text } => campaign::add_note(&campaign, &text).await,
+                };
+                result.context("Campaign command failed")?;
+            }
+            Commands::Notes { action } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let result = match action {
+                    NotesAction::Start { campaign, title } => notes::start(&campaign, title.as_deref()),
+                    NotesAction::End { campaign } => notes::end(&campaign),
+                    NotesAction::Add { campaign, text, tag, item, character } => notes::add(&campaign, &text, tag, &item, &character),
+                    NotesAction::List { campaign, tag } => notes::list(&campaign, tag),
+                    NotesAction::Export { campaign, session, output } => notes::export(&campaign, session, output.as_deref()),
+                };
+                result.context("Notes command failed")?;
+            }
+            Commands::Encounter { action } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let result = match action {
+                    EncounterAction::Create { name, character, level, description } => encounter::create(&name, &character, &level, description.as_deref()),
+                    EncounterAction::Party { encounter: query, character, level, append } => encounter::set_party(&query, &character, &level, append),
+                    EncounterAction::Add { encounter: query, creature, count } => encounter::add(&query, &creature, count),
+                    EncounterAction::Remove { encounter: query, creature, count } => encounter::remove(&query, &creature, count),
+                    EncounterAction::Show { encounter: query } => encounter::show(&query),
+                    EncounterAction::List => encounter::list(),
+                    EncounterAction::Run { encounter: query, force } => encounter::run(&query, force),
+                };
+                result.context("Encounter command failed")?;
+            }
+            Commands::Combat { action } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                let result = match action {
+                    CombatAction::Start { force } => combat::start(force),
+                    CombatAction::End { save_hp } => combat::end(save_hp),
+                    CombatAction::Tui => tui::combat_screen(),
+                    CombatAction::Status => combat::Combat::load().map(|c| combat::print_status(&c)),
+                    CombatAction::AddCharacter { character, initiative } => combat::with_combat(|c| {
+                        let combatant = combat::character_combatant(&character)?;
+                        Ok(c.add(combatant, initiative))
+                    }),
+                    CombatAction::AddMonster { name, hp, init_bonus, ac, con_save, count, initiative } => combat::with_combat(|c| {
+                        let options = combat::MonsterOptions { hp, init_bonus, armor_class: ac, con_save };
+                        let mut messages = Vec::new();
+                        for _ in 0..count.max(1) {
+                            let combatant = combat::monster_combatant(&name, &options)?;
+                            messages.extend(c.add(combatant, initiative));
+                        }
+                        Ok(messages)
+                    }),
+                    CombatAction::Remove { who } => combat::with_combat(|c| Ok(c.remove(c.find(&who)?))),
+                    CombatAction::Next => combat::with_combat(|c| Ok(c.next_turn())),
+                    CombatAction::Damage { who, amount, crit, manual } => combat::with_combat(|c| Ok(c.damage(c.find(&who)?, amount.max(0), crit, !manual))),
+                    CombatAction::Heal { who, amount } => combat::with_combat(|c| Ok(c.heal(c.find(&who)?, amount.max(0)))),
+                    CombatAction::Temp { who, amount } => combat::with_combat(|c| Ok(c.temp_hp(c.find(&who)?, amount.max(0)))),
+                    CombatAction::Condition { who, name, rounds, remove } => combat::with_combat(|c| {
+                        let i = c.find(&who)?;
+                        Ok(if remove { c.remove_condition(i, &name) } else { c.add_condition(i, &name, rounds) })
+                    }),
+                    CombatAction::Concentrate { who, spell, end } => combat::with_combat(|c| {
+                        let i = c.find(&who)?;
+                        match (spell.as_deref(), end) {
+                            (_, true) => Ok(c.concentrate(i, None)),
+                            (Some(spell), false) => Ok(c.concentrate(i, Some(spell))),
+                            (None, false) => Err(Error::Usage("Give a spell name or --end".to_string()).into()),
+                        }
+                    }),
+                    CombatAction::DeathSave { who, roll } => combat::with_combat(|c| {
+                        if roll.is_some_and(|r| !(1..=20).contains(&r)) {
+                            return Err(Error::Usage("A death save roll is between 1 and 20".to_string()).into());
+                        }
+                        Ok(c.death_save(c.find(&who)?, roll))
+                    }),
+                };
+                result.context("Combat command failed")?;
+            }
+            Commands::Doctor { fix } => {
+                // No setup check, the doctor is what repairs a broken setup
+                doctor::run(fix).await.context("Doctor failed")?;
+            }
+            Commands::History { object, kind } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                history::print_history(&object, kind.as_deref()).context("History failed")?;
+            }
+            Commands::Diff { object, rev, against, kind } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                history::diff(&object, kind.as_deref(), rev, against).context("Diff failed")?;
+            }
+            Commands::Revert { object, rev, kind } => {
+                check_setup_cmpl().context("Setup incomplete or absent")?;
+                history::revert(&object, kind.as_deref(), rev).context("Revert failed")?;
+            }
+            Commands::Backup { action } => {
+                // No setup check, restoring is a way back to a working setup
+                let result = match action {
+                    BackupAction::Create { label, include_credentials } => backup::create("manual", label.as_deref(), include_credentials)
+                        .map(|path| info!("Backup written to {}", path.display())),
+                    BackupAction::List => backup::list(),
+                    BackupAction::Restore { backup: query, dry_run, yes } => backup::restore(&query, dry_run, yes),
+                };
+                result.context("Backup command failed")?;
+            }
         }
+        return Ok(());
     }
 
     if args.check_setup {
-        if let Err(e) = check_setup_cmpl() {
-            println!("{}: {}", "[ERROR] Setup incomplete or absent".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Setup is complete.".green());
-            return Ok(());
-        }
+        check_setup_cmpl().context("Setup incomplete or absent")?;
+        info!("Setup is complete.");
+        return Ok(());
     }
 
     if args.login {
-        if let Err(e) = auth::manual_login().await {
-            println!("{}: {}", "[ERROR] Manual login failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Manual login successful.".green());
-            return Ok(());
-        }
+        auth::manual_login().await.context("Manual login failed")?;
+        info!("Manual login successful.");
+        return Ok(());
     }
 
     if args.register {
-        if let Err(e) = auth::register().await {
-            println!("{}: {}", "[ERROR] Registration failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Registration successful.".green());
-            return Ok(());
-        }
+        auth::register().await.context("Registration failed")?;
+        info!("Registration successful.");
+        return Ok(());
     }
 
     if args.auto_login {
-        if let Err(e) = auth::auto_login().await {
-            println!("{}: {}", "[ERROR] Auto login failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Auto login successful.".green());
-            return Ok(());
-        }
+        auth::auto_login().await.context("Auto login failed")?;
+        info!("Auto login successful.");
+        return Ok(());
     }
 
     if args.logout {
-        if let Err(e) = auth::logout().await {
-            println!("{}: {}", "[ERROR] Logout failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Logout successful.".green());
-            return Ok(());
-        }
+        auth::logout().await.context("Logout failed")?;
+        info!("Logout successful.");
+        return Ok(());
     }
 
     if args.push_load {
-        if let Err(e) = push_load().await {
-            println!("{}: {}", "[ERROR] Push and load failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Push and load successful.".green());
-            return Ok(());
-        }
+        push_load(args.force).await.context("Push and load failed")?;
+        info!("Push and load successful.");
+        return Ok(());
     }
 
     if args.run {
         // Check if signed in
         if !auth::is_signed_in().await {
-            println!("{}", "[ERROR] You must be signed in to run the client.".red());
-            return Ok(());
+            return Err(Error::Auth("You must be signed in to run the client.".to_string()).into());
         }
         
         // Delete saved_objs/synced.txt if it exists, then create it
-        let home_dir = dirs::home_dir().ok_or_else(|| clap::Error::raw(
-            clap::error::ErrorKind::Io,
-            "Could not find home directory",
-        ))?;
+        let home_dir = dirs::home_dir().ok_or_else(|| Error::Setup("Could not find home directory".to_string()))?;
         let synced_file = home_dir.join(".archerdndsys/saved_objs/synced.txt");
         if synced_file.exists() {
-            std::fs::remove_file(&synced_file).map_err(|e| {
-                println!("{}: {}", "[ERROR] Failed to remove synced.txt".red(), e);
-                clap::Error::raw(clap::error::ErrorKind::Io, format!("Failed to remove synced.txt: {}", e))
-            })?;
+            std::fs::remove_file(&synced_file).map_err(|e| Error::Storage(format!("Failed to remove synced.txt: {}", e)))?;
         }
-        println!("{}", "[INFO] Deleted synced.txt file.".green());
+        info!("Deleted synced.txt file.");
         
-        std::fs::File::create(&synced_file).map_err(|e| {
-            println!("{}: {}", "[ERROR] Failed to create synced.txt".red(), e);
-            clap::Error::raw(clap::error::ErrorKind::Io, format!("Failed to create synced.txt: {}", e))
-        })?;
-        println!("{}", "[INFO] Created synced.txt file.".green());
+        std::fs::File::create(&synced_file).map_err(|e| Error::Storage(format!("Failed to create synced.txt: {}", e)))?;
+        info!("Created synced.txt file.");
         
         // TODO: Implement the TUI client
     }
     
     if args.cache_size {
-        // Calculate the total size of the cached objects
-       let size = client::calculate_cache_size().await;
-        match size {
-            Ok(size) => {
-                println!("{}: {} bytes", "[INFO] Total cache size".green(), size.to_string().bold());
-            },
-            Err(e) => {
-                println!("{}: {}", "[ERROR] Failed to calculate cache size".red(), e);
-            }
-        }
+        cache::print_usage(args.json, args.verbose > 0).context("Failed to calculate cache size")?;
         return Ok(());
     }
-    
-    if args.clear_cache.is_some() {
-        if args.clear_cache.unwrap() == 0 {
-            if let Err(e) = client::clear_all_cache().await {
-                println!("{}: {}", "[ERROR] Cache clearing failed".red(), e);
-                return Ok(())
-            } else {
-                println!("{}", "[INFO] All cache cleared.".green());
-                return Ok(())
-            }
-        }
 
-        // Clear all saved data not accessed in the last [argument] days
-        if let Err(e) = client::clear_cache(args.clear_cache.unwrap()).await {
-            println!("{}: {}", "[ERROR] Cache clearing failed".red(), e);
-            return Ok(());
-        } else {
-            println!("{}", "[INFO] Cache clearing complete.".green());
-            return Ok(());
-        }
+    if args.clear_cache.is_some() || args.max_size.is_some() {
+        cache::evict(args.clear_cache, args.max_size, args.dry_run).context("Cache clearing failed")?;
+        return Ok(());
     }
 
     if no_flags {
-
-        // List all saved data the user has locally by filenames
-        check_setup_cmpl()?;
-        let home_dir = dirs::home_dir().ok_or_else(|| clap::Error::raw(
-            clap::error::ErrorKind::Io,
-            "Could not find home directory",
-        ))?;
-
-        let saved_objs_dir = home_dir.join(".archerdndsys/saved_objs");
-        if saved_objs_dir.exists() {
-            println!("{}", "[INFO] Saved objects directory found.".green());
-            // List all files in saved_objs subdirectories (there should be no direct files in this directory)
-            // Divide by subdirectories (characters, campaigns, etc.)
-            if let Ok(entries) = std::fs::read_dir(&saved_objs_dir) {
-                println!("{}", "[INFO] Directory found, listing saved objects:".green());
-                for entry_res in entries {
-                    if let Ok(entry) = entry_res {
-                        let path = entry.path();
-                        if path.is_dir() {
-                            // Print the directory name
-                            let dir_name = path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("[unnamed]");
-                            println!("{}", dir_name.bold().underlined());
-                            // List all files in this directory
-                            if let Ok(files) = std::fs::read_dir(&path) {
-                                for file_res in files {
-                                    if let Ok(file) = file_res {
-                                        println!("  - {}", file.file_name().to_string_lossy());
-                                    } else {
-                                        println!("{}", "[ERROR] Could not read file".red());
-                                    }
-                                }
-                            } else {
-                                println!("{}", "[ERROR] Could not read directory".red());
-                            }
-                        } else {
-                            println!("{}: {}", "[INFO] File".yellow(), path.display());
-                        }
-                    } else {
-                        println!("{}", "[ERROR] Could not read entry in saved objects directory".red());
-                    }
-                }
-            } else {
-                println!("{}", "[ERROR] Could not read saved objects directory".red());
-            }
-        }
-        
+        // List all saved data the user has locally
+        check_setup_cmpl().context("Setup incomplete or absent")?;
+        listing::collect(&listing::ListOptions::default()).and_then(|entries| listing::print(&entries, listing::ListFormat::Table)).context("Listing failed")?;
         return Ok(());
     }
 
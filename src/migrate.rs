@@ -0,0 +1,257 @@
+// Versioned layout of ~/.archerdndsys/
+// VERSION holds the layout number. Older directories are upgraded one step at a time on startup, after a full copy is
+// taken to migration_backups/, so a failed or faulty upgrade never costs unsynced homebrew
+
+use crate::error::Error;
+use crate::resource::ResourceKind;
+use crate::{atomic, backup, client, lock, logging, store};
+use chrono::Local;
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+
+pub const VERSION_FILE: &str = "VERSION";
+const BACKUP_DIR: &str = "migration_backups";
+/// Never copied into a migration backup
+const SKIP_IN_BACKUP: [&str; 5] = [BACKUP_DIR, backup::BACKUP_DIR, "doctor_quarantine", logging::LOG_DIR, lock::LOCK_FILE];
+
+struct Migration {
+    to: u32,
+    description: &'static str,
+    apply: fn(&Path) -> Result<(), anyhow::Error>,
+}
+
+/// Every upgrade step in order. A directory without VERSION is layout 0, the first release
+const MIGRATIONS: [Migration; 3] = [
+    Migration { to: 1, description: "add directories and journals for content types added since the first release", apply: add_content_dirs },
+    Migration { to: 2, description: "store the login as a single 'token,user_id' line", apply: single_line_tokens },
+    Migration { to: 3, description: "end every journal with a newline", apply: terminate_journals },
+];
+
+pub const CURRENT_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].to;
+
+/// Content types the first release did not create
+const ADDED_KINDS: [ResourceKind; 7] = [
+    ResourceKind::Backgrounds,
+    ResourceKind::Feats,
+    ResourceKind::Conditions,
+    ResourceKind::Creatures,
+    ResourceKind::Encounters,
+    ResourceKind::Sessions,
+    ResourceKind::Campaigns,
+];
+
+fn add_content_dirs(_: &Path) -> Result<(), anyhow::Error> {
+    for kind in ADDED_KINDS {
+        fs::create_dir_all(kind.dir()?)?;
+        let journal = kind.journal_path()?;
+        if kind.journaled() && !journal.exists() {
+            fs::File::create(&journal)?;
+        }
+    }
+    Ok(())
+}
+
+/// The first release read the token file as two lines (access and refresh token) while login wrote "token,user_id".
+/// A two-line file has no user ID to convert, so it is cleared and the user logs in again
+fn single_line_tokens(data_dir: &Path) -> Result<(), anyhow::Error> {
+    let path = data_dir.join(client::AUTH_TOKENS_FILE);
+    let contents = fs::read_to_string(&path).unwrap_or_default();
+    if contents.trim().is_empty() {
+        return Ok(());
+    }
+    match client::parse_pair(&contents) {
+        Some((token, user_id)) => client::save_session(&token, &user_id),
+        None => {
+            atomic::write(&path, "")?;
+            warn!("The saved login was in an old format and has been cleared. Run `archerdndsys --login` again.");
+            Ok(())
+        }
+    }
+}
+
+/// Journals cleaned by older versions lost their final newline, so the next record joined the last line
+fn terminate_journals(_: &Path) -> Result<(), anyhow::Error> {
+    for kind in ResourceKind::ALL.into_iter().filter(|k| k.journaled()) {
+        let path = kind.journal_path()?;
+        let Ok(contents) = fs::read_to_string(&path) else { continue };
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            atomic::write(&path, contents + "\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Layout number of a data directory, 0 when it predates VERSION
+pub fn read_version(data_dir: &Path) -> Result<u32, anyhow::Error> {
+    let path = data_dir.join(VERSION_FILE);
+    if !path.exists() {
+        return Ok(0);
+    }
+    let contents = fs::read_to_string(&path)?;
+    contents.trim().parse()
+        .map_err(|_| Error::Setup(format!("{} does not hold a layout number: '{}'", path.display(), contents.trim())).into())
+}
+
+fn write_version(data_dir: &Path, version: u32) -> Result<(), anyhow::Error> {
+    atomic::write(data_dir.join(VERSION_FILE), format!("{}\n", version))?;
+    Ok(())
+}
+
+/// Marks a freshly set up directory as the current layout
+pub fn stamp() -> Result<(), anyhow::Error> {
+    let data_dir = store::data_dir()?;
+    if !data_dir.join(VERSION_FILE).exists() {
+        write_version(&data_dir, CURRENT_VERSION)?;
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if SKIP_IN_BACKUP.iter().any(|skip| name == *skip) {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            copy_dir(&path, &to.join(&name))?;
+        } else {
+            fs::copy(&path, to.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Backs the data directory up and applies every step above its layout. Returns the backup location
+pub fn migrate(data_dir: &Path) -> Result<PathBuf, anyhow::Error> {
+    let from = read_version(data_dir)?;
+    let backup = data_dir.join(BACKUP_DIR).join(format!("v{}-{}", from, Local::now().format("%Y%m%d-%H%M%S")));
+    copy_dir(data_dir, &backup)
+        .map_err(|e| Error::Storage(format!("Could not back up {} before migrating, nothing was changed: {}", data_dir.display(), e)))?;
+    info!("Backed up the data directory to {}", backup.display());
+
+    for step in MIGRATIONS.iter().filter(|m| m.to > from) {
+        (step.apply)(data_dir).map_err(|e| Error::Setup(format!(
+            "Migration to layout v{} failed: {}. The data directory before migrating is in {}",
+            step.to, e, backup.display()
+        )))?;
+        // Recorded after every step so a later failure resumes from here
+        write_version(data_dir, step.to)?;
+        info!("Migrated data directory to layout v{}: {}", step.to, step.description);
+    }
+    Ok(backup)
+}
+
+/// Run before any command: upgrades an older data directory and refuses one written by a newer release
+pub fn on_startup() -> Result<(), anyhow::Error> {
+    let data_dir = store::data_dir()?;
+    if !data_dir.exists() {
+        return Ok(());
+    }
+    let version = read_version(&data_dir)?;
+    if version > CURRENT_VERSION {
+        return Err(Error::Setup(format!(
+            "{} uses layout v{} but this archerdndsys only knows up to v{}. Upgrade archerdndsys to use it.",
+            data_dir.display(), version, CURRENT_VERSION
+        )).into());
+    }
+    if version < CURRENT_VERSION {
+        info!("Upgrading data directory from layout v{} to v{}", version, CURRENT_VERSION);
+        migrate(&data_dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::with_home;
+
+    /// A first-release directory holding only the Spells type
+    fn with_data_dir(test: impl FnOnce(&Path)) {
+        with_home(|data_dir| {
+            fs::create_dir_all(data_dir.join("saved_objs/Spells")).unwrap();
+            test(data_dir);
+        });
+    }
+
+    #[test]
+    fn add_content_dirs_creates_directories_and_journals() {
+        with_data_dir(|data_dir| {
+            add_content_dirs(data_dir).unwrap();
+            for kind in ADDED_KINDS {
+                assert!(kind.dir().unwrap().is_dir(), "{} has no directory", kind.name());
+                assert_eq!(kind.journal_path().unwrap().exists(), kind.journaled(), "{} journal", kind.name());
+            }
+        });
+    }
+
+    #[test]
+    fn single_line_tokens_keeps_token_and_user_id() {
+        with_data_dir(|data_dir| {
+            let path = data_dir.join(client::AUTH_TOKENS_FILE);
+            fs::write(&path, "abc,user1\n").unwrap();
+            single_line_tokens(data_dir).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "abc,user1");
+        });
+    }
+
+    #[test]
+    fn single_line_tokens_clears_two_line_file() {
+        with_data_dir(|data_dir| {
+            let path = data_dir.join(client::AUTH_TOKENS_FILE);
+            fs::write(&path, "access\nrefresh\n").unwrap();
+            single_line_tokens(data_dir).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        });
+    }
+
+    #[test]
+    fn terminate_journals_appends_missing_newline() {
+        with_data_dir(|data_dir| {
+            let journal = ResourceKind::Spells.journal_path().unwrap();
+            fs::write(&journal, "POST /spells {}").unwrap();
+            terminate_journals(data_dir).unwrap();
+            assert_eq!(fs::read_to_string(&journal).unwrap(), "POST /spells {}\n");
+
+            terminate_journals(data_dir).unwrap();
+            assert_eq!(fs::read_to_string(&journal).unwrap(), "POST /spells {}\n");
+        });
+    }
+
+    #[test]
+    fn read_version_of_unversioned_and_corrupt_directories() {
+        with_data_dir(|data_dir| {
+            assert_eq!(read_version(data_dir).unwrap(), 0);
+            fs::write(data_dir.join(VERSION_FILE), "two\n").unwrap();
+            assert!(read_version(data_dir).is_err());
+        });
+    }
+
+    #[test]
+    fn migrate_v1_to_current_after_backing_up() {
+        with_data_dir(|data_dir| {
+            fs::write(data_dir.join(VERSION_FILE), "1\n").unwrap();
+            fs::write(data_dir.join(client::AUTH_TOKENS_FILE), "access\nrefresh").unwrap();
+            let journal = ResourceKind::Spells.journal_path().unwrap();
+            fs::write(&journal, "POST /spells {}").unwrap();
+            fs::create_dir_all(data_dir.join(logging::LOG_DIR)).unwrap();
+
+            let backup = migrate(data_dir).unwrap();
+
+            assert_eq!(read_version(data_dir).unwrap(), CURRENT_VERSION);
+            assert_eq!(fs::read_to_string(data_dir.join(client::AUTH_TOKENS_FILE)).unwrap(), "");
+            assert_eq!(fs::read_to_string(&journal).unwrap(), "POST /spells {}\n");
+            // Layout v1 already has the added directories, the step is skipped
+            assert!(!ResourceKind::Feats.dir().unwrap().exists());
+
+            assert_eq!(fs::read_to_string(backup.join(VERSION_FILE)).unwrap(), "1\n");
+            assert_eq!(fs::read_to_string(backup.join("saved_objs/Spells/session_calls.txt")).unwrap(), "POST /spells {}");
+            assert!(!backup.join(logging::LOG_DIR).exists());
+            assert!(!backup.join(BACKUP_DIR).exists());
+        });
+    }
+}
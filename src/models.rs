@@ -0,0 +1,536 @@
+// Typed views over the JSON objects kept in saved_objs/
+// Every struct keeps unknown fields in `extra` so server data round-trips untouched
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+pub const ABILITIES: [&str; 6] = ["strength", "dexterity", "constitution", "intelligence", "wisdom", "charisma"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AbilityScores {
+    pub strength: u8,
+    pub dexterity: u8,
+    pub constitution: u8,
+    pub intelligence: u8,
+    pub wisdom: u8,
+    pub charisma: u8,
+}
+
+impl Default for AbilityScores {
+    fn default() -> Self {
+        AbilityScores { strength: 10, dexterity: 10, constitution: 10, intelligence: 10, wisdom: 10, charisma: 10 }
+    }
+}
+
+impl AbilityScores {
+    /// Accepts full names ("strength") or the usual abbreviations ("str")
+    pub fn get(&self, ability: &str) -> Option<u8> {
+        match ability.to_lowercase().as_str() {
+            "strength" | "str" => Some(self.strength),
+            "dexterity" | "dex" => Some(self.dexterity),
+            "constitution" | "con" => Some(self.constitution),
+            "intelligence" | "int" => Some(self.intelligence),
+            "wisdom" | "wis" => Some(self.wisdom),
+            "charisma" | "cha" => Some(self.charisma),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, ability: &str) -> Option<&mut u8> {
+        match ability.to_lowercase().as_str() {
+            "strength" | "str" => Some(&mut self.strength),
+            "dexterity" | "dex" => Some(&mut self.dexterity),
+            "constitution" | "con" => Some(&mut self.constitution),
+            "intelligence" | "int" => Some(&mut self.intelligence),
+            "wisdom" | "wis" => Some(&mut self.wisdom),
+            "charisma" | "cha" => Some(&mut self.charisma),
+            _ => None,
+        }
+    }
+
+    pub fn modifier(score: u8) -> i32 {
+        (score as i32 - 10).div_euclid(2)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CharacterClass {
+    pub class: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subclass: Option<String>,
+    pub level: u8,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Character {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub race: Option<String>,
+    pub abilities: AbilityScores,
+    pub classes: Vec<CharacterClass>,
+    pub max_hp: i32,
+    pub current_hp: i32,
+    pub features: Vec<String>,
+    /// Index 0 holds 1st-level slots
+    pub spell_slots: Vec<u8>,
+    /// Warlock-style slots that do not combine with other spellcasting classes
+    pub pact_slots: Vec<u8>,
+    pub spells_known: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Character {
+    pub fn total_level(&self) -> u8 {
+        self.classes.iter().map(|c| c.level).sum()
+    }
+
+    pub fn class_entry(&self, class_name: &str) -> Option<&CharacterClass> {
+        self.classes.iter().find(|c| c.class.eq_ignore_ascii_case(class_name))
+    }
+
+    pub fn proficiency_bonus(&self) -> i32 {
+        2 + (self.total_level().max(1) as i32 - 1) / 4
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpellcastingProgression {
+    Full,
+    Half,
+    Third,
+    Pact,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassLevel {
+    pub level: u8,
+    pub features: Vec<String>,
+    pub ability_score_improvement: bool,
+    pub spell_slots: Vec<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cantrips_known: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spells_known: Option<u8>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Class {
+    pub name: String,
+    pub description: String,
+    pub hit_die: u8,
+    pub saving_throws: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spellcasting: Option<SpellcastingProgression>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spellcasting_ability: Option<String>,
+    pub subclass_level: u8,
+    /// Minimum ability scores needed to multiclass into or out of this class
+    pub multiclass_prerequisites: BTreeMap<String, u8>,
+    /// When set, meeting any single prerequisite is enough (e.g. Str 13 or Dex 13)
+    pub multiclass_prerequisites_any: bool,
+    pub levels: Vec<ClassLevel>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Class {
+    pub fn level(&self, level: u8) -> Option<&ClassLevel> {
+        self.levels.iter().find(|l| l.level == level)
+    }
+
+    pub fn meets_prerequisites(&self, abilities: &AbilityScores) -> bool {
+        if self.multiclass_prerequisites.is_empty() {
+            return true;
+        }
+        let mut checks = self.multiclass_prerequisites.iter()
+            .map(|(ability, min)| abilities.get(ability).unwrap_or(0) >= *min);
+        if self.multiclass_prerequisites_any {
+            checks.any(|ok| ok)
+        } else {
+            checks.all(|ok| ok)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubclassFeature {
+    pub level: u8,
+    pub feature: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subclass {
+    pub name: String,
+    pub class: String,
+    pub description: String,
+    pub features: Vec<SubclassFeature>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feature {
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subclass: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Damage {
+    pub dice: String,
+    pub damage_type: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Spell {
+    pub name: String,
+    pub level: u8,
+    pub school: String,
+    pub casting_time: String,
+    pub range: String,
+    /// Any of "V", "S" and "M"
+    pub components: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    pub duration: String,
+    pub concentration: bool,
+    pub ritual: bool,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub higher_levels: Option<String>,
+    pub classes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damage: Option<Damage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Item {
+    pub name: String,
+    pub item_type: String,
+    pub rarity: String,
+    pub requires_attunement: bool,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bonus: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damage: Option<Damage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charges: Option<u32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Race {
+    pub name: String,
+    pub description: String,
+    pub size: String,
+    pub speed: u32,
+    pub ability_bonuses: BTreeMap<String, i8>,
+    pub traits: Vec<String>,
+    pub languages: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Background {
+    pub name: String,
+    pub description: String,
+    pub skill_proficiencies: Vec<String>,
+    pub tool_proficiencies: Vec<String>,
+    /// Languages granted, including choices such as "one of your choice"
+    pub languages: Vec<String>,
+    pub equipment: Vec<String>,
+    pub feature_name: String,
+    pub feature_description: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feat {
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prerequisite: Option<String>,
+    /// Fixed ability score increases, e.g. {"dexterity": 1}
+    pub ability_increases: BTreeMap<String, u8>,
+    /// Abilities the player picks one increase from
+    pub ability_choices: Vec<String>,
+    /// Can be taken more than once
+    pub repeatable: bool,
+    pub benefits: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Condition {
+    pub name: String,
+    pub description: String,
+    /// One rule per entry, e.g. "Attack rolls against the creature have advantage."
+    pub effects: Vec<String>,
+    /// Cumulative levels for conditions such as exhaustion, index 0 is level 1
+    pub levels: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Challenge ratings and the XP a creature of that rating is worth
+pub const CHALLENGE_XP: [(&str, u32); 34] = [
+    ("0", 10), ("1/8", 25), ("1/4", 50), ("1/2", 100),
+    ("1", 200), ("2", 450), ("3", 700), ("4", 1100), ("5", 1800),
+    ("6", 2300), ("7", 2900), ("8", 3900), ("9", 5000), ("10", 5900),
+    ("11", 7200), ("12", 8400), ("13", 10000), ("14", 11500), ("15", 13000),
+    ("16", 15000), ("17", 18000), ("18", 20000), ("19", 22000), ("20", 25000),
+    ("21", 33000), ("22", 41000), ("23", 50000), ("24", 62000), ("25", 75000),
+    ("26", 90000), ("27", 105000), ("28", 120000), ("29", 135000), ("30", 155000),
+];
+
+pub const CREATURE_SIZES: [&str; 6] = ["tiny", "small", "medium", "large", "huge", "gargantuan"];
+
+/// A named trait or action entry of a stat block
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CreatureAction {
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attack_bonus: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damage: Option<Damage>,
+    /// Legendary actions that take more than one action, e.g. "Wing Attack (Costs 2 Actions)"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<u8>,
+}
+
+/// Monster or NPC stat block
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Creature {
+    pub name: String,
+    pub size: String,
+    /// e.g. "humanoid (goblinoid)"
+    pub creature_type: String,
+    pub alignment: String,
+    pub armor_class: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub armor_type: Option<String>,
+    pub hit_points: i32,
+    /// e.g. "2d6" or "18d10+36"
+    pub hit_dice: String,
+    /// Movement mode ("walk", "fly", "swim", ...) to feet
+    pub speed: BTreeMap<String, u32>,
+    pub abilities: AbilityScores,
+    /// Only the proficient saves, as total modifiers
+    pub saving_throws: BTreeMap<String, i32>,
+    pub skills: BTreeMap<String, i32>,
+    pub damage_vulnerabilities: Vec<String>,
+    pub damage_resistances: Vec<String>,
+    pub damage_immunities: Vec<String>,
+    pub condition_immunities: Vec<String>,
+    pub senses: Vec<String>,
+    pub languages: Vec<String>,
+    /// "0", "1/8", "1/4", "1/2" or "1" to "30"
+    pub challenge_rating: String,
+    pub description: String,
+    pub traits: Vec<CreatureAction>,
+    pub actions: Vec<CreatureAction>,
+    pub bonus_actions: Vec<CreatureAction>,
+    pub reactions: Vec<CreatureAction>,
+    pub legendary_actions: Vec<CreatureAction>,
+    pub legendary_actions_per_round: u8,
+    pub lair_actions: Vec<CreatureAction>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Creature {
+    /// Challenge rating as a number, 1/4 -> 0.25
+    pub fn challenge(&self) -> Option<f64> {
+        challenge_value(&self.challenge_rating)
+    }
+
+    pub fn xp(&self) -> Option<u32> {
+        CHALLENGE_XP.iter().find(|(cr, _)| *cr == self.challenge_rating.trim()).map(|(_, xp)| *xp)
+    }
+
+    pub fn proficiency_bonus(&self) -> i32 {
+        2 + (self.challenge().unwrap_or(0.0).max(1.0) as i32 - 1) / 4
+    }
+
+    /// Saving throw modifier, falling back to the ability modifier for non-proficient saves
+    pub fn save(&self, ability: &str) -> i32 {
+        let key = ability.to_lowercase();
+        let short: String = key.chars().take(3).collect();
+        self.saving_throws.iter()
+            .find(|(k, _)| k.to_lowercase() == key || k.to_lowercase() == short)
+            .map(|(_, v)| *v)
+            .unwrap_or_else(|| AbilityScores::modifier(self.abilities.get(ability).unwrap_or(10)))
+    }
+}
+
+/// Parses "1/4", "0.25" or "3" into a number
+pub fn challenge_value(rating: &str) -> Option<f64> {
+    let rating = rating.trim();
+    match rating.split_once('/') {
+        Some((n, d)) => Some(n.trim().parse::<f64>().ok()? / d.trim().parse::<f64>().ok()?),
+        None => rating.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CampaignRole {
+    Dm,
+    #[default]
+    Player,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CampaignMember {
+    /// Server user ID
+    pub user: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub role: CampaignRole,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CampaignNote {
+    pub author: String,
+    pub created: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Campaign {
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invite_code: Option<String>,
+    pub members: Vec<CampaignMember>,
+    /// Server IDs of attached Characters
+    pub characters: Vec<String>,
+    /// Homebrew collections (the `source` of imported content) enabled for the campaign
+    pub collections: Vec<String>,
+    pub notes: Vec<CampaignNote>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Campaign {
+    pub fn role_of(&self, user: &str) -> Option<CampaignRole> {
+        self.members.iter().find(|m| m.user == user).map(|m| m.role)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteTag {
+    Npc,
+    Location,
+    Loot,
+    Quest,
+}
+
+/// Reference from a note to a cached object
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteLink {
+    pub kind: String,
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteEntry {
+    pub time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<NoteTag>,
+    pub text: String,
+    pub links: Vec<NoteLink>,
+}
+
+/// One play session of a campaign, from its start marker to its end marker
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub name: String,
+    /// Server ID of the campaign
+    pub campaign: String,
+    pub number: u32,
+    pub started: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended: Option<String>,
+    pub entries: Vec<NoteEntry>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A party member counted for XP thresholds, either a cached Character or just a level
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartyMember {
+    pub name: String,
+    pub level: u8,
+    /// Stored ID of the Character, when the member was taken from the cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub character: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncounterCreature {
+    /// Stored ID of the Creature
+    pub creature: String,
+    pub name: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Encounter {
+    pub name: String,
+    pub description: String,
+    pub party: Vec<PartyMember>,
+    pub creatures: Vec<EncounterCreature>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
@@ -0,0 +1,197 @@
+// Per-campaign session log: start/end markers, tagged entries and links to cached Items and Characters
+// Sessions are ordinary local objects in saved_objs/Sessions/, journaled and pushed like any other content
+
+use crate::atomic;
+use crate::error::Error;
+use crate::models::{Campaign, NoteEntry, NoteLink, NoteTag, Session};
+use crate::store::{self, StoredObject};
+use chrono::Local;
+use crossterm::style::Stylize;
+use std::collections::HashSet;
+use std::path::Path;
+use log::info;
+use crate::logging;
+
+const KIND: &str = "Sessions";
+/// Types that notes can link to, searched in this order for inline [[Name]] links
+const LINK_TYPES: [&str; 2] = ["Items", "Characters"];
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn tag_label(tag: NoteTag) -> &'static str {
+    match tag {
+        NoteTag::Npc => "NPC",
+        NoteTag::Location => "Location",
+        NoteTag::Loot => "Loot",
+        NoteTag::Quest => "Quest",
+    }
+}
+
+/// Returns the cached campaign's server ID and name
+fn campaign(query: &str) -> Result<(String, String), anyhow::Error> {
+    let obj = store::find("Campaigns", query)
+        .map_err(|e| Error::NotFound(format!("{} (run `archerdndsys campaign list` to refresh the cache)", e)))?;
+    let campaign: Campaign = obj.parse()?;
+    Ok((obj.id.trim_start_matches('_').to_string(), campaign.name))
+}
+
+/// Every session of a campaign, oldest first
+fn sessions(campaign_id: &str) -> Result<Vec<(StoredObject, Session)>, anyhow::Error> {
+    let mut sessions: Vec<(StoredObject, Session)> = store::load_all(KIND)?
+        .into_iter()
+        .filter_map(|obj| obj.parse::<Session>().ok().map(|s| (obj, s)))
+        .filter(|(_, s)| s.campaign == campaign_id)
+        .collect();
+    sessions.sort_by_key(|(_, s)| s.number);
+    Ok(sessions)
+}
+
+fn open_session(campaign_id: &str, campaign_name: &str) -> Result<(StoredObject, Session), anyhow::Error> {
+    sessions(campaign_id)?.into_iter().rev()
+        .find(|(_, s)| s.ended.is_none())
+        .ok_or_else(|| Error::NotFound(format!("No session in progress for {}. Run `archerdndsys notes start` first.", campaign_name)).into())
+}
+
+fn link(kind: &str, query: &str) -> Result<NoteLink, anyhow::Error> {
+    let obj = store::find(kind, query)?;
+    Ok(NoteLink { kind: obj.kind.clone(), id: obj.id.clone(), name: obj.name().to_string() })
+}
+
+/// Resolves inline "[[Name]]" references against cached Items, then Characters
+fn inline_links(text: &str) -> Result<Vec<NoteLink>, anyhow::Error> {
+    let mut links = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start..].find("]]") else { break };
+        let name = rest[start + 2..start + len].trim();
+        let found = LINK_TYPES.iter().find_map(|kind| link(kind, name).ok())
+            .ok_or_else(|| Error::NotFound(format!("[[{}]] does not match a cached Item or Character", name)))?;
+        links.push(found);
+        rest = &rest[start + len + 2..];
+    }
+    Ok(links)
+}
+
+pub fn start(campaign_query: &str, title: Option<&str>) -> Result<(), anyhow::Error> {
+    let (campaign_id, campaign_name) = campaign(campaign_query)?;
+    let existing = sessions(&campaign_id)?;
+    if let Some((_, open)) = existing.iter().find(|(_, s)| s.ended.is_none()) {
+        return Err(Error::Conflict(format!("{} is still in progress. Run `archerdndsys notes end` first.", open.name)).into());
+    }
+    let number = existing.iter().map(|(_, s)| s.number).max().unwrap_or(0) + 1;
+    let session = Session {
+        name: title.map(|t| t.to_string()).unwrap_or_else(|| format!("{} - Session {}", campaign_name, number)),
+        campaign: campaign_id,
+        number,
+        started: now(),
+        ..Default::default()
+    };
+    let obj = store::create(KIND, serde_json::to_value(&session)?)?;
+    info!("Started {} ({})", session.name, obj.id);
+    Ok(())
+}
+
+pub fn end(campaign_query: &str) -> Result<(), anyhow::Error> {
+    let (campaign_id, campaign_name) = campaign(campaign_query)?;
+    let (obj, mut session) = open_session(&campaign_id, &campaign_name)?;
+    session.ended = Some(now());
+    store::update(&obj, serde_json::to_value(&session)?)?;
+    info!("Ended {} ({} entries)", session.name, session.entries.len());
+    Ok(())
+}
+
+pub fn add(campaign_query: &str, text: &str, tag: Option<NoteTag>, items: &[String], characters: &[String]) -> Result<(), anyhow::Error> {
+    let (campaign_id, campaign_name) = campaign(campaign_query)?;
+    let (obj, mut session) = open_session(&campaign_id, &campaign_name)?;
+
+    let mut links = inline_links(text)?;
+    for item in items {
+        links.push(link("Items", item)?);
+    }
+    for character in characters {
+        links.push(link("Characters", character)?);
+    }
+    let mut seen = HashSet::new();
+    links.retain(|l| seen.insert((l.kind.clone(), l.id.clone())));
+
+    session.entries.push(NoteEntry { time: now(), tag, text: text.to_string(), links });
+    store::update(&obj, serde_json::to_value(&session)?)?;
+    info!("Note added to {}", session.name);
+    Ok(())
+}
+
+fn print_entry(entry: &NoteEntry) {
+    let tag = entry.tag.map(|t| format!("[{}] ", tag_label(t))).unwrap_or_default();
+    println!("  {} {}{}", logging::styled(entry.time.as_str().dark_grey()), logging::styled(tag.yellow()), entry.text);
+    if !entry.links.is_empty() {
+        let links: Vec<String> = entry.links.iter().map(|l| format!("{}/{}", l.kind, l.name)).collect();
+        println!("      -> {}", links.join(", "));
+    }
+}
+
+pub fn list(campaign_query: &str, tag: Option<NoteTag>) -> Result<(), anyhow::Error> {
+    let (campaign_id, campaign_name) = campaign(campaign_query)?;
+    let sessions = sessions(&campaign_id)?;
+    if sessions.is_empty() {
+        info!("No sessions recorded for {}", campaign_name);
+    }
+    for (_, session) in sessions {
+        let status = match &session.ended {
+            Some(ended) => format!("{} - {}", session.started, ended),
+            None => format!("{} - in progress", session.started),
+        };
+        println!("{} ({})", logging::bold(&session.name), status);
+        for entry in session.entries.iter().filter(|e| tag.is_none() || e.tag == tag) {
+            print_entry(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Markdown session log; links point at the objects' names so the export stays readable outside the tool
+pub fn render_markdown(campaign_name: &str, sessions: &[Session]) -> String {
+    let mut out = format!("# {} - Session Log\n\n", campaign_name);
+    for session in sessions {
+        out.push_str(&format!("## {}\n\n", session.name));
+        out.push_str(&format!("*Started {}", session.started));
+        match &session.ended {
+            Some(ended) => out.push_str(&format!(", ended {}*\n\n", ended)),
+            None => out.push_str(", in progress*\n\n"),
+        }
+        for entry in &session.entries {
+            let tag = entry.tag.map(|t| format!("**[{}]** ", tag_label(t))).unwrap_or_default();
+            let text = entry.text.replace("[[", "**").replace("]]", "**");
+            out.push_str(&format!("- `{}` {}{}", entry.time, tag, text));
+            if !entry.links.is_empty() {
+                let links: Vec<String> = entry.links.iter().map(|l| format!("*{}* ({})", l.name, l.kind.trim_end_matches('s'))).collect();
+                out.push_str(&format!(" - see {}", links.join(", ")));
+            }
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn export(campaign_query: &str, number: Option<u32>, output: Option<&Path>) -> Result<(), anyhow::Error> {
+    let (campaign_id, campaign_name) = campaign(campaign_query)?;
+    let sessions: Vec<Session> = sessions(&campaign_id)?.into_iter()
+        .map(|(_, s)| s)
+        .filter(|s| number.is_none_or(|n| s.number == n))
+        .collect();
+    if sessions.is_empty() {
+        return Err(Error::NotFound(format!("No matching sessions for {}", campaign_name)).into());
+    }
+    let document = render_markdown(&campaign_name, &sessions);
+    match output {
+        Some(path) => {
+            atomic::write(path, document)
+                .map_err(|e| Error::Storage(format!("Could not write {}: {}", path.display(), e)))?;
+            info!("Exported {} session(s) to {}", sessions.len(), path.display());
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+// Registry of the content types kept in saved_objs/
+// Each kind knows its directory, journal, server endpoint and schema; nothing else builds these by hand
+
+use crate::error::Error;
+use crate::models;
+use crate::store::data_dir;
+use crate::SERVER;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::PathBuf;
+
+const OBJECTS_DIR: &str = "saved_objs";
+const JOURNAL_FILE: &str = "session_calls.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceKind {
+    Characters,
+    Classes,
+    Features,
+    Items,
+    Races,
+    Spells,
+    Subclasses,
+    Backgrounds,
+    Feats,
+    Conditions,
+    Creatures,
+    Encounters,
+    Sessions,
+    Campaigns,
+}
+
+impl ResourceKind {
+    /// Every kind, in the order they are set up, listed and pushed
+    pub const ALL: [ResourceKind; 14] = [
+        ResourceKind::Characters,
+        ResourceKind::Classes,
+        ResourceKind::Features,
+        ResourceKind::Items,
+        ResourceKind::Races,
+        ResourceKind::Spells,
+        ResourceKind::Subclasses,
+        ResourceKind::Backgrounds,
+        ResourceKind::Feats,
+        ResourceKind::Conditions,
+        ResourceKind::Creatures,
+        ResourceKind::Encounters,
+        ResourceKind::Sessions,
+        ResourceKind::Campaigns,
+    ];
+
+    /// Directory name under saved_objs/, also the `kind` of stored objects
+    pub fn name(self) -> &'static str {
+        match self {
+            ResourceKind::Characters => "Characters",
+            ResourceKind::Classes => "Classes",
+            ResourceKind::Features => "Features",
+            ResourceKind::Items => "Items",
+            ResourceKind::Races => "Races",
+            ResourceKind::Spells => "Spells",
+            ResourceKind::Subclasses => "Subclasses",
+            ResourceKind::Backgrounds => "Backgrounds",
+            ResourceKind::Feats => "Feats",
+            ResourceKind::Conditions => "Conditions",
+            ResourceKind::Creatures => "Creatures",
+            ResourceKind::Encounters => "Encounters",
+            ResourceKind::Sessions => "Sessions",
+            ResourceKind::Campaigns => "Campaigns",
+        }
+    }
+
+    /// Case-insensitive lookup, e.g. "spells" -> Spells
+    pub fn from_name(name: &str) -> Option<ResourceKind> {
+        ResourceKind::ALL.into_iter().find(|k| k.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Local changes are recorded in a journal and pushed by --push-load.
+    /// Campaign calls go straight to the server, their directory only caches the latest server state
+    pub fn journaled(self) -> bool {
+        self != ResourceKind::Campaigns
+    }
+
+    /// Path relative to ~/.archerdndsys/, with the trailing slash `--setup` uses to tell directories from files
+    pub fn relative_dir(self) -> String {
+        format!("{}/{}/", OBJECTS_DIR, self.name())
+    }
+
+    pub fn relative_journal(self) -> Option<String> {
+        self.journaled().then(|| format!("{}/{}/{}", OBJECTS_DIR, self.name(), JOURNAL_FILE))
+    }
+
+    pub fn dir(self) -> Result<PathBuf, anyhow::Error> {
+        Ok(data_dir()?.join(OBJECTS_DIR).join(self.name()))
+    }
+
+    /// The journal file; kinds without one still resolve to the path it would have
+    pub fn journal_path(self) -> Result<PathBuf, anyhow::Error> {
+        Ok(self.dir()?.join(JOURNAL_FILE))
+    }
+
+    pub fn endpoint(self) -> String {
+        let path = match self {
+            ResourceKind::Characters => "characters",
+            ResourceKind::Classes => "classes",
+            ResourceKind::Features => "features",
+            ResourceKind::Items => "items",
+            ResourceKind::Races => "races",
+            ResourceKind::Spells => "spells",
+            ResourceKind::Subclasses => "subclasses",
+            ResourceKind::Backgrounds => "backgrounds",
+            ResourceKind::Feats => "feats",
+            ResourceKind::Conditions => "conditions",
+            ResourceKind::Creatures => "creatures",
+            ResourceKind::Encounters => "encounters",
+            ResourceKind::Sessions => "sessions",
+            ResourceKind::Campaigns => "campaigns",
+        };
+        format!("{}/{}", SERVER, path)
+    }
+
+    /// Checks that the data deserializes into this kind's model
+    pub fn check_schema(self, data: &Value) -> Result<(), serde_json::Error> {
+        fn check<T: DeserializeOwned>(data: &Value) -> Result<(), serde_json::Error> {
+            serde_json::from_value::<T>(data.clone()).map(|_| ())
+        }
+        match self {
+            ResourceKind::Characters => check::<models::Character>(data),
+            ResourceKind::Classes => check::<models::Class>(data),
+            ResourceKind::Features => check::<models::Feature>(data),
+            ResourceKind::Items => check::<models::Item>(data),
+            ResourceKind::Races => check::<models::Race>(data),
+            ResourceKind::Spells => check::<models::Spell>(data),
+            ResourceKind::Subclasses => check::<models::Subclass>(data),
+            ResourceKind::Backgrounds => check::<models::Background>(data),
+            ResourceKind::Feats => check::<models::Feat>(data),
+            ResourceKind::Conditions => check::<models::Condition>(data),
+            ResourceKind::Creatures => check::<models::Creature>(data),
+            ResourceKind::Encounters => check::<models::Encounter>(data),
+            ResourceKind::Sessions => check::<models::Session>(data),
+            ResourceKind::Campaigns => check::<models::Campaign>(data),
+        }
+    }
+}
+
+/// Looks a kind up by its directory name, failing with the usual message for unknown types
+pub fn kind(name: &str) -> Result<ResourceKind, anyhow::Error> {
+    ResourceKind::from_name(name).ok_or_else(|| Error::Usage(format!("Unknown content type: {}", name)).into())
+}
@@ -0,0 +1,364 @@
+// Full-text and structured search over saved_objs/
+// Backed by ~/.archerdndsys/search_index.json, refreshed incrementally from file modification times
+
+use crate::atomic;
+use crate::content_types;
+use crate::error::Error;
+use crate::models::challenge_value;
+use crate::store;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use log::info;
+use crate::logging;
+
+const INDEX_FILE: &str = "search_index.json";
+/// Bumped whenever IndexEntry changes shape, forces a rebuild
+const INDEX_VERSION: u32 = 1;
+
+/// Short filter names accepted in queries, mapped to object fields
+const FIELD_ALIASES: [(&str, &str); 6] = [
+    ("class", "classes"),
+    ("attunement", "requires_attunement"),
+    ("type", "item_type"),
+    ("hd", "hit_die"),
+    ("speed", "speed"),
+    ("cr", "challenge_rating"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub kind: String,
+    pub id: String,
+    pub name: String,
+    /// Milliseconds since the epoch and byte length of the file when it was indexed
+    pub modified: u64,
+    pub size: u64,
+    /// Lowercased field values, arrays and nested objects flattened ("damage.damage_type")
+    pub fields: BTreeMap<String, Vec<String>>,
+    pub name_terms: BTreeSet<String>,
+    /// Word -> occurrences across every text field
+    pub terms: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    pub version: u32,
+    /// Keyed by "Kind/id"
+    pub entries: BTreeMap<String, IndexEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Contains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub field: String,
+    pub op: Op,
+    pub value: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Query {
+    pub terms: Vec<String>,
+    pub filters: Vec<Filter>,
+    /// `kind:spells` restricts the search to one content type
+    pub kind: Option<&'static str>,
+}
+
+#[derive(Debug)]
+pub struct SearchResult<'a> {
+    pub entry: &'a IndexEntry,
+    pub score: u32,
+}
+
+fn index_path() -> Result<PathBuf, anyhow::Error> {
+    Ok(store::data_dir()?.join(INDEX_FILE))
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect()
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.to_lowercase()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, fields: &mut BTreeMap<String, Vec<String>>, terms: &mut BTreeMap<String, u32>) {
+    match value {
+        Value::Array(values) => {
+            for v in values {
+                flatten(prefix, v, fields, terms);
+            }
+        }
+        Value::Object(map) => {
+            for (key, v) in map {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&path, v, fields, terms);
+            }
+        }
+        _ => {
+            if let Some(s) = scalar(value) {
+                if value.is_string() {
+                    for word in tokenize(&s) {
+                        *terms.entry(word).or_default() += 1;
+                    }
+                }
+                fields.entry(prefix.to_string()).or_default().push(s);
+            }
+        }
+    }
+}
+
+fn index_entry(obj: &store::StoredObject, modified: u64, size: u64) -> IndexEntry {
+    let mut fields = BTreeMap::new();
+    let mut terms = BTreeMap::new();
+    flatten("", &obj.data, &mut fields, &mut terms);
+    IndexEntry {
+        kind: obj.kind.clone(),
+        id: obj.id.clone(),
+        name: obj.name().to_string(),
+        modified,
+        size,
+        fields,
+        name_terms: tokenize(obj.name()).into_iter().collect(),
+        terms,
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+    Some((modified, meta.len()))
+}
+
+impl SearchIndex {
+    pub fn load() -> Result<SearchIndex, anyhow::Error> {
+        let path = index_path()?;
+        let index = fs::read_to_string(&path).ok()
+            .and_then(|contents| serde_json::from_str::<SearchIndex>(&contents).ok())
+            .filter(|index| index.version == INDEX_VERSION);
+        Ok(index.unwrap_or(SearchIndex { version: INDEX_VERSION, entries: BTreeMap::new() }))
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = index_path()?;
+        atomic::write(&path, serde_json::to_string(self)?)
+            .map_err(|e| Error::Storage(format!("Could not write search index {}: {}", path.display(), e)).into())
+    }
+
+    /// Re-indexes only files that were added, changed or removed since the last run.
+    /// Returns how many entries changed
+    pub fn refresh(&mut self) -> Result<usize, anyhow::Error> {
+        let mut seen = BTreeSet::new();
+        let mut changed = 0;
+        for kind in content_types() {
+            let dir = store::kind_dir(kind)?;
+            let Ok(files) = fs::read_dir(&dir) else { continue };
+            for file in files.flatten() {
+                let path = file.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let Some(id) = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string()) else { continue };
+                let Some((modified, size)) = file_stamp(&path) else { continue };
+                let key = format!("{}/{}", kind, id);
+                seen.insert(key.clone());
+                if self.entries.get(&key).is_some_and(|e| e.modified == modified && e.size == size) {
+                    continue;
+                }
+                // Unparseable files are left out of the index, `validate` reports them
+                let Some(data) = fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str::<Value>(&c).ok()) else {
+                    self.entries.remove(&key);
+                    continue;
+                };
+                let obj = store::StoredObject { kind: kind.to_string(), id, path, data };
+                self.entries.insert(key, index_entry(&obj, modified, size));
+                changed += 1;
+            }
+        }
+        let before = self.entries.len();
+        self.entries.retain(|key, _| seen.contains(key));
+        Ok(changed + before - self.entries.len())
+    }
+
+    pub fn search(&self, query: &Query) -> Vec<SearchResult<'_>> {
+        let mut results: Vec<SearchResult> = self.entries.values()
+            .filter(|entry| query.kind.is_none_or(|kind| entry.kind == kind))
+            .filter(|entry| query.filters.iter().all(|filter| filter.matches(entry)))
+            .filter_map(|entry| score(entry, &query.terms).map(|score| SearchResult { entry, score }))
+            .collect();
+        results.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.entry.name.to_lowercase().cmp(&b.entry.name.to_lowercase())));
+        results
+    }
+}
+
+/// Every term must appear (as a word prefix) in the object, name hits rank above description hits
+fn score(entry: &IndexEntry, terms: &[String]) -> Option<u32> {
+    let mut total = 0;
+    for term in terms {
+        let mut term_score = 0;
+        if entry.name_terms.contains(term) {
+            term_score += 20;
+        } else if entry.name_terms.iter().any(|w| w.starts_with(term.as_str())) {
+            term_score += 10;
+        }
+        term_score += entry.terms.range(term.clone()..)
+            .take_while(|(word, _)| word.starts_with(term.as_str()))
+            .map(|(_, count)| *count)
+            .sum::<u32>();
+        if term_score == 0 {
+            return None;
+        }
+        total += term_score;
+    }
+    Some(total)
+}
+
+fn normalize_value(value: &str) -> String {
+    match value.to_lowercase().as_str() {
+        "yes" | "y" | "true" => "true".to_string(),
+        "no" | "n" | "false" => "false".to_string(),
+        other => other.trim_matches('"').to_string(),
+    }
+}
+
+impl Filter {
+    /// Parses "field<op>value", e.g. "level<=3", "school:evocation", "name~fire"
+    pub fn parse(token: &str) -> Option<Filter> {
+        let start = token.find(['<', '>', '!', ':', '=', '~'])?;
+        let field = token[..start].to_lowercase();
+        if field.is_empty() || !field.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+            return None;
+        }
+        let rest = &token[start..];
+        let (op, len) = if rest.starts_with("<=") {
+            (Op::Le, 2)
+        } else if rest.starts_with(">=") {
+            (Op::Ge, 2)
+        } else if rest.starts_with("!=") {
+            (Op::NotEq, 2)
+        } else {
+            match rest.chars().next()? {
+                '<' => (Op::Lt, 1),
+                '>' => (Op::Gt, 1),
+                ':' | '=' => (Op::Eq, 1),
+                '~' => (Op::Contains, 1),
+                _ => return None,
+            }
+        };
+        let value = normalize_value(&rest[len..]);
+        if value.is_empty() {
+            return None;
+        }
+        let field = FIELD_ALIASES.iter().find(|(alias, _)| *alias == field).map(|(_, f)| f.to_string()).unwrap_or(field);
+        Some(Filter { field, op, value })
+    }
+
+    pub fn matches(&self, entry: &IndexEntry) -> bool {
+        let values = entry.fields.get(&self.field).map(|v| v.as_slice()).unwrap_or(&[]);
+        // Missing booleans read as false so "attunement:no" also finds objects without the field
+        if values.is_empty() && self.value == "false" {
+            return self.op == Op::Eq;
+        }
+        let hit = |value: &String| match self.op {
+            Op::Eq | Op::NotEq => *value == self.value,
+            Op::Contains => value.contains(&self.value),
+            // Fractions are compared by value so "cr<=1/2" works
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => match (challenge_value(value), challenge_value(&self.value)) {
+                (Some(v), Some(wanted)) => match self.op {
+                    Op::Lt => v < wanted,
+                    Op::Le => v <= wanted,
+                    Op::Gt => v > wanted,
+                    _ => v >= wanted,
+                },
+                _ => false,
+            },
+        };
+        if self.op == Op::NotEq {
+            !values.iter().any(hit)
+        } else {
+            values.iter().any(hit)
+        }
+    }
+}
+
+impl Query {
+    /// Each argument is either a filter or free text; filter values may contain spaces when quoted by the shell
+    pub fn parse(args: &[String]) -> Result<Query, anyhow::Error> {
+        let mut query = Query::default();
+        for arg in args {
+            match Filter::parse(arg) {
+                Some(filter) if filter.field == "kind" => {
+                    query.kind = Some(crate::content_type(&filter.value)
+                        .ok_or_else(|| Error::Usage(format!("Unknown content type: {}", filter.value)))?);
+                }
+                Some(filter) => query.filters.push(filter),
+                None => query.terms.extend(tokenize(arg)),
+            }
+        }
+        Ok(query)
+    }
+}
+
+fn summary(entry: &IndexEntry) -> String {
+    let field = |name: &str| entry.fields.get(name).and_then(|v| v.first()).cloned().unwrap_or_default();
+    match entry.kind.as_str() {
+        "Spells" => match field("level").as_str() {
+            "0" => format!("{} cantrip", field("school")),
+            level => format!("level {} {}", level, field("school")),
+        },
+        "Items" => format!("{} {}", field("rarity"), field("item_type")),
+        "Subclasses" | "Features" => field("class"),
+        "Classes" => format!("d{}", field("hit_die")),
+        "Creatures" => format!("CR {} {}", field("challenge_rating"), field("creature_type")),
+        _ => String::new(),
+    }
+}
+
+/// Refreshes the index, runs the query and prints the best `limit` matches
+pub fn run(args: &[String], kind: Option<&'static str>, limit: usize, rebuild: bool) -> Result<(), anyhow::Error> {
+    let mut query = Query::parse(args)?;
+    if kind.is_some() {
+        query.kind = kind;
+    }
+    if query.terms.is_empty() && query.filters.is_empty() && query.kind.is_none() {
+        return Err(Error::Usage("Empty query, give search words and/or filters such as level<=3".to_string()).into());
+    }
+
+    let mut index = if rebuild { SearchIndex { version: INDEX_VERSION, entries: BTreeMap::new() } } else { SearchIndex::load()? };
+    if index.refresh()? > 0 {
+        index.save()?;
+    }
+
+    let results = index.search(&query);
+    info!("{} result(s)", results.len());
+    for result in results.iter().take(limit) {
+        let entry = result.entry;
+        println!("  {:<28} {} {}", format!("{}/{}", entry.kind, entry.id), logging::bold(format!("{:<32}", entry.name)), summary(entry));
+    }
+    if results.len() > limit {
+        info!("{} more, use --limit to show them", results.len() - limit);
+    }
+    Ok(())
+}
@@ -0,0 +1,221 @@
+// Local object store for saved_objs/
+// Synced objects live in saved_objs/(item type)/_(MONGOOSE_ID).json
+// Local-only objects live in saved_objs/(item type)/(LOCAL_ID).json and carry a "local_id" field
+// Every create/update is journaled to saved_objs/(item type)/session_calls.txt for push_load
+
+use crate::error::Error;
+use crate::{atomic, cache, history, resource, validate};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    /// Directory name under saved_objs/, e.g. "Spells"
+    pub kind: String,
+    /// File stem, `_` prefixed when the object exists on the server
+    pub id: String,
+    pub path: PathBuf,
+    pub data: Value,
+}
+
+impl StoredObject {
+    pub fn is_synced(&self) -> bool {
+        self.id.starts_with('_')
+    }
+
+    /// Reference content such as the SRD baseline, never edited or pushed
+    pub fn is_read_only(&self) -> bool {
+        self.data.get("read_only").and_then(|r| r.as_bool()).unwrap_or(false)
+    }
+
+    pub fn name(&self) -> &str {
+        self.data.get("name").and_then(|n| n.as_str()).unwrap_or("[unnamed]")
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, anyhow::Error> {
+        serde_json::from_value(self.data.clone())
+            .map_err(|e| Error::Storage(format!("{} {} is malformed: {}", self.kind, self.id, e)).into())
+    }
+}
+
+pub fn data_dir() -> Result<PathBuf, anyhow::Error> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
+        .join(".archerdndsys"))
+}
+
+pub fn kind_dir(kind: &str) -> Result<PathBuf, anyhow::Error> {
+    resource::kind(kind)?.dir()
+}
+
+pub fn journal_path(kind: &str) -> Result<PathBuf, anyhow::Error> {
+    resource::kind(kind)?.journal_path()
+}
+
+
+/// Loads every JSON object of one type, skipping journals and other stray files
+pub fn load_all(kind: &str) -> Result<Vec<StoredObject>, anyhow::Error> {
+    let dir = kind_dir(kind)?;
+    if !dir.exists() {
+        return Err(Error::Setup(format!("Directory for {} does not exist. Please run `archerdndsys --setup`.", kind)).into());
+    }
+
+    let mut objects = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let id = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(stem) => stem.to_string(),
+            None => continue,
+        };
+        let contents = fs::read_to_string(&path)?;
+        let data: Value = serde_json::from_str(&contents)
+            .map_err(|e| Error::Storage(format!("Could not parse {}: {}", path.display(), e)))?;
+        objects.push(StoredObject { kind: kind.to_string(), id, path, data });
+    }
+    objects.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(objects)
+}
+
+/// Finds an object by file stem (with or without the leading `_`) or by case-insensitive name
+pub fn find(kind: &str, query: &str) -> Result<StoredObject, anyhow::Error> {
+    let objects = load_all(kind)?;
+    let trimmed = query.trim_start_matches('_');
+    if let Some(obj) = objects.iter().find(|o| o.id == query || o.id.trim_start_matches('_') == trimmed) {
+        cache::touch(obj);
+        return Ok(obj.clone());
+    }
+
+    let mut matches: Vec<&StoredObject> = objects.iter().filter(|o| o.name().eq_ignore_ascii_case(query)).collect();
+    match matches.len() {
+        0 => Err(Error::NotFound(format!("No {} found matching '{}'", kind, query)).into()),
+        1 => {
+            let obj = matches.remove(0);
+            cache::touch(obj);
+            Ok(obj.clone())
+        }
+        _ => Err(Error::Usage(format!(
+            "'{}' matches {} {} objects, use an ID instead: {}",
+            query,
+            matches.len(),
+            kind,
+            matches.iter().map(|o| o.id.as_str()).collect::<Vec<_>>().join(", ")
+        )).into()),
+    }
+}
+
+pub fn next_local_id(kind: &str) -> Result<u64, anyhow::Error> {
+    let dir = kind_dir(kind)?;
+    let mut max = 0;
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if let Some(n) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok()) {
+            max = max.max(n);
+        }
+    }
+    Ok(max + 1)
+}
+
+/// Appends one line in the session_calls.txt format
+pub fn append_journal(kind: &str, method: &str, endpoint: &str, data: Option<&Value>) -> Result<(), anyhow::Error> {
+    let mut line = format!("{} {}", method, endpoint);
+    if let Some(data) = data {
+        line.push(' ');
+        line.push_str(&serde_json::to_string(data)?);
+    }
+
+    atomic::append_line(journal_path(kind)?, &line)?;
+
+    Ok(())
+}
+
+fn journal_write(obj: &StoredObject) -> Result<(), anyhow::Error> {
+    if obj.is_read_only() {
+        return Err(Error::Usage(format!("{}/{} is read-only reference content and cannot be journaled.", obj.kind, obj.id)).into());
+    }
+    let kind = resource::kind(&obj.kind)?;
+    if !kind.journaled() {
+        return Err(Error::Usage(format!("{} are not synced through the journal and cannot be edited locally.", obj.kind)).into());
+    }
+    if obj.is_synced() {
+        let url = format!("{}/{}", kind.endpoint(), obj.id.trim_start_matches('_'));
+        append_journal(&obj.kind, "PUT", &url, Some(&obj.data))
+    } else {
+        // Unsynced objects are re-created with their latest data; clean_session_calls keeps the last create
+        append_journal(&obj.kind, "POST", &kind.endpoint(), Some(&obj.data))
+    }
+}
+
+/// Saves a new local-only object and journals its creation
+pub fn create(kind: &str, data: Value) -> Result<StoredObject, anyhow::Error> {
+    create_with(kind, data, &validate::ValidationContext::load()?, next_local_id(kind)?)
+}
+
+/// `create` with the validation context and local ID supplied, so bulk imports look them up once per run
+pub fn create_with(kind: &str, mut data: Value, ctx: &validate::ValidationContext, id: u64) -> Result<StoredObject, anyhow::Error> {
+    validate::check_with(kind, &data, ctx)?;
+    let id = id.to_string();
+    if let Some(map) = data.as_object_mut() {
+        map.insert("local_id".to_string(), Value::String(id.clone()));
+    }
+    let path = kind_dir(kind)?.join(format!("{}.json", id));
+    atomic::write(&path, serde_json::to_string_pretty(&data)?)?;
+
+    let obj = StoredObject { kind: kind.to_string(), id, path, data };
+    journal_write(&obj)?;
+    // A reused local ID must not inherit the revisions of the object that held it before
+    history::clear(&obj.kind, &obj.id)?;
+    history::record(&obj.kind, &obj.id, None, &obj.data, "created")?;
+    Ok(obj)
+}
+
+/// Overwrites an existing object and journals the change
+pub fn update(obj: &StoredObject, data: Value) -> Result<StoredObject, anyhow::Error> {
+    update_noted(obj, data, "edited")
+}
+
+/// `update` with the note kept in the object's history
+pub fn update_noted(obj: &StoredObject, data: Value, note: &str) -> Result<StoredObject, anyhow::Error> {
+    if obj.is_read_only() {
+        return Err(Error::Usage(format!("{} ({}/{}) is read-only reference content. Create a homebrew copy instead.", obj.name(), obj.kind, obj.id)).into());
+    }
+    validate::check_before_write(&obj.kind, &data)?;
+    let updated = StoredObject { data, ..obj.clone() };
+    atomic::write(&updated.path, serde_json::to_string_pretty(&updated.data)?)?;
+    journal_write(&updated)?;
+    history::record(&updated.kind, &updated.id, Some(&obj.data), &updated.data, note)?;
+    cache::touch(&updated);
+    Ok(updated)
+}
+
+/// Writes read-only reference content under a fixed ID without journaling it
+pub fn save_reference(kind: &str, id: &str, mut data: Value) -> Result<StoredObject, anyhow::Error> {
+    validate::check_before_write(kind, &data)?;
+    if let Some(map) = data.as_object_mut() {
+        map.insert("read_only".to_string(), Value::Bool(true));
+    }
+    let path = kind_dir(kind)?.join(format!("{}.json", id));
+    atomic::write(&path, serde_json::to_string_pretty(&data)?)?;
+    Ok(StoredObject { kind: kind.to_string(), id: id.to_string(), path, data })
+}
+
+/// Caches an object exactly as the server returned it, as `_<server id>.json`, without journaling it
+pub fn save_synced(kind: &str, server_id: &str, data: Value) -> Result<StoredObject, anyhow::Error> {
+    let id = format!("_{}", server_id.trim_start_matches('_'));
+    let path = kind_dir(kind)?.join(format!("{}.json", id));
+    atomic::write(&path, serde_json::to_string_pretty(&data)?)?;
+    let obj = StoredObject { kind: kind.to_string(), id, path, data };
+    cache::touch(&obj);
+    Ok(obj)
+}
+
+/// Drops the local copy of an object without journaling a delete
+pub fn forget(obj: &StoredObject) -> Result<(), anyhow::Error> {
+    fs::remove_file(&obj.path)
+        .map_err(|e| Error::Storage(format!("Could not remove {}: {}", obj.path.display(), e)).into())
+}
@@ -0,0 +1,42 @@
+// Fixtures shared by unit tests that need a data directory
+// Every path resolves through $HOME, so a test points it at its own temp directory and holds one lock while it runs
+
+use crate::{migrate, required_files, store};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+static HOME: Mutex<()> = Mutex::new(());
+
+/// Runs `test` with $HOME set to an empty temp directory, passing the (not yet created) data directory
+pub fn with_home(test: impl FnOnce(&Path)) {
+    let _guard = HOME.lock().unwrap_or_else(|e| e.into_inner());
+    let home = TempDir::new().unwrap();
+    std::env::set_var("HOME", home.path());
+    test(&store::data_dir().unwrap());
+}
+
+/// Runs `test` against a data directory laid out as `--setup` leaves it
+pub fn with_setup(test: impl FnOnce(&Path)) {
+    with_home(|data_dir| {
+        for file in required_files() {
+            let path = data_dir.join(&file);
+            if file.ends_with('/') {
+                fs::create_dir_all(&path).unwrap();
+            } else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, "").unwrap();
+            }
+        }
+        migrate::stamp().unwrap();
+        test(data_dir);
+    });
+}
+
+/// Writes a JSON object file into saved_objs/<kind>/
+pub fn write_object(kind: &str, id: &str, data: serde_json::Value) -> std::path::PathBuf {
+    let path = store::kind_dir(kind).unwrap().join(format!("{}.json", id));
+    fs::write(&path, data.to_string()).unwrap();
+    path
+}
@@ -0,0 +1,216 @@
+// Terminal screens built on ratatui
+// The combat screen drives the same Combat operations as the `combat` subcommands and saves after each one
+
+use crate::combat::Combat;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+/// What the typed input line will be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Damage,
+    Heal,
+    TempHp,
+    /// "name [rounds]", a leading '-' removes the condition
+    Condition,
+    /// Spell name, empty ends concentration
+    Concentration,
+}
+
+impl Pending {
+    fn prompt(&self) -> &'static str {
+        match self {
+            Pending::Damage => "Damage",
+            Pending::Heal => "Heal",
+            Pending::TempHp => "Temporary HP",
+            Pending::Condition => "Condition [rounds] (-name removes)",
+            Pending::Concentration => "Concentrating on (empty to end)",
+        }
+    }
+}
+
+struct CombatScreen {
+    combat: Combat,
+    table: TableState,
+    input: Option<(Pending, String)>,
+}
+
+const HELP: &str = "n next turn  ↑/↓ select  d damage  h heal  t temp HP  c condition  o concentration  s death save  x remove  q quit";
+
+impl CombatScreen {
+    fn selected(&self) -> Option<usize> {
+        self.table.selected().filter(|i| *i < self.combat.combatants.len())
+    }
+
+    fn apply(&mut self, pending: Pending, text: &str) {
+        let Some(i) = self.selected() else { return };
+        let text = text.trim();
+        let number = || text.parse::<i32>().ok().filter(|n| *n >= 0);
+        let messages = match pending {
+            Pending::Damage => number().map(|n| self.combat.damage(i, n, false, true)),
+            Pending::Heal => number().map(|n| self.combat.heal(i, n)),
+            Pending::TempHp => number().map(|n| self.combat.temp_hp(i, n)),
+            Pending::Condition => {
+                let mut parts = text.split_whitespace();
+                match parts.next() {
+                    Some(name) if name.starts_with('-') => Some(self.combat.remove_condition(i, name.trim_start_matches('-'))),
+                    Some(name) => Some(self.combat.add_condition(i, name, parts.next().and_then(|r| r.parse().ok()))),
+                    None => None,
+                }
+            }
+            Pending::Concentration => Some(self.combat.concentrate(i, Some(text).filter(|t| !t.is_empty()))),
+        };
+        if messages.is_none() {
+            self.combat.log.push(format!("'{}' is not a valid {}", text, pending.prompt().to_lowercase()));
+        }
+    }
+
+    /// Returns false when the screen should close
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if let Some((pending, mut text)) = self.input.take() {
+            match code {
+                KeyCode::Enter => self.apply(pending, &text),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    text.pop();
+                    self.input = Some((pending, text));
+                }
+                KeyCode::Char(c) => {
+                    text.push(c);
+                    self.input = Some((pending, text));
+                }
+                _ => self.input = Some((pending, text)),
+            }
+            return true;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('n') => {
+                self.combat.next_turn();
+                self.table.select(Some(self.combat.turn));
+            }
+            KeyCode::Up => self.table.select_previous(),
+            KeyCode::Down => self.table.select_next(),
+            KeyCode::Char('d') => self.input = Some((Pending::Damage, String::new())),
+            KeyCode::Char('h') => self.input = Some((Pending::Heal, String::new())),
+            KeyCode::Char('t') => self.input = Some((Pending::TempHp, String::new())),
+            KeyCode::Char('c') => self.input = Some((Pending::Condition, String::new())),
+            KeyCode::Char('o') => self.input = Some((Pending::Concentration, String::new())),
+            KeyCode::Char('s') => {
+                if let Some(i) = self.selected() {
+                    self.combat.death_save(i, None);
+                }
+            }
+            KeyCode::Char('x') => {
+                if let Some(i) = self.selected() {
+                    self.combat.remove(i);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, log, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(5),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let title = if self.combat.round == 0 {
+            "Combat - press n to start the first round".to_string()
+        } else {
+            format!("Combat - round {}", self.combat.round)
+        };
+        frame.render_widget(Paragraph::new(title).style(Style::new().add_modifier(Modifier::BOLD)), header);
+
+        let rows = self.combat.combatants.iter().enumerate().map(|(i, c)| {
+            let turn = if self.combat.round > 0 && i == self.combat.turn { ">" } else { "" };
+            let hp = if c.temp_hp > 0 { format!("{}/{} +{}", c.hp, c.max_hp, c.temp_hp) } else { format!("{}/{}", c.hp, c.max_hp) };
+            let conditions: Vec<String> = c.conditions.iter()
+                .map(|cond| match cond.rounds {
+                    Some(r) => format!("{} ({})", cond.name, r),
+                    None => cond.name.clone(),
+                })
+                .collect();
+            let style = if c.dead {
+                Style::new().fg(Color::DarkGray)
+            } else if c.is_down() {
+                Style::new().fg(Color::Red)
+            } else if !c.player {
+                Style::new().fg(Color::Yellow)
+            } else {
+                Style::new()
+            };
+            Row::new(vec![
+                turn.to_string(),
+                c.initiative.to_string(),
+                c.name.clone(),
+                hp,
+                c.armor_class.map(|ac| ac.to_string()).unwrap_or_default(),
+                conditions.join(", "),
+                c.concentration.clone().unwrap_or_default(),
+                c.status(),
+            ]).style(style)
+        });
+        let table = Table::new(rows, [
+            Constraint::Length(1),
+            Constraint::Length(4),
+            Constraint::Min(14),
+            Constraint::Length(11),
+            Constraint::Length(3),
+            Constraint::Min(16),
+            Constraint::Min(12),
+            Constraint::Min(10),
+        ])
+        .header(Row::new(vec!["", "Init", "Name", "HP", "AC", "Conditions", "Concentration", "Status"]).style(Style::new().add_modifier(Modifier::BOLD)))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::new().borders(Borders::ALL));
+        frame.render_stateful_widget(table, body, &mut self.table);
+
+        let visible = log.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self.combat.log.iter().rev().take(visible).rev().map(|l| Line::from(l.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::new().borders(Borders::ALL).title("Log")), log);
+
+        let footer_text = match &self.input {
+            Some((pending, text)) => format!("{}: {}_", pending.prompt(), text),
+            None => HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(footer_text).style(Style::new().fg(Color::Cyan)), footer);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), anyhow::Error> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                let keep_open = self.handle_key(key.code);
+                // Persist after every key so the combat survives the terminal closing
+                self.combat.save()?;
+                if !keep_open {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Opens the combat tracker screen for the combat in progress
+pub fn combat_screen() -> Result<(), anyhow::Error> {
+    let combat = Combat::load()?;
+    let turn = combat.turn;
+    let mut screen = CombatScreen { combat, table: TableState::default().with_selected(Some(turn)), input: None };
+    let mut terminal = ratatui::init();
+    let result = screen.run(&mut terminal);
+    ratatui::restore();
+    result
+}
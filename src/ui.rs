@@ -1,3 +1,42 @@
 // Use this file to define interactions with the user interface
 // Specifically on creating, editing, and using created objects
 
+use crossterm::style::Stylize;
+use std::io::Write;
+use crate::error::Error;
+use crate::logging;
+use log::{info, warn};
+
+/// Shows a question on stderr, keeping stdout for data, and leaves the cursor after it for the answer
+pub fn show_prompt(message: &str) -> std::io::Result<()> {
+    eprint!("{} ", logging::styled(format!("[INPUT] {}", message).yellow()));
+    std::io::stderr().flush()
+}
+
+/// Asks a question and returns the trimmed answer from stdin
+pub fn prompt(message: &str) -> Result<String, anyhow::Error> {
+    show_prompt(message)?;
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer)? == 0 {
+        return Err(anyhow::anyhow!("Input closed while waiting for: {}", message));
+    }
+    Ok(answer.trim().to_string())
+}
+
+/// Lists numbered options and keeps asking until a valid one is picked
+pub fn choose(message: &str, options: &[String]) -> Result<usize, anyhow::Error> {
+    if options.is_empty() {
+        return Err(Error::NotFound(format!("Nothing to choose from for: {}", message)).into());
+    }
+    info!("{}", message);
+    for (i, option) in options.iter().enumerate() {
+        eprintln!("  {}) {}", i + 1, option);
+    }
+    loop {
+        let answer = prompt(&format!("Choose 1-{}:", options.len()))?;
+        match answer.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= options.len() => return Ok(n - 1),
+            _ => warn!("Invalid choice, try again."),
+        }
+    }
+}
@@ -0,0 +1,483 @@
+// Schema and rules validation for homebrew content before it is journaled or pushed
+
+use crate::client::parse_line;
+use crate::dice::DiceExpr;
+use crate::error::Error;
+use crate::models::{self, ABILITIES};
+use crate::resource::ResourceKind;
+use crate::store::{self, StoredObject};
+use log::{error, warn};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+
+pub const SPELL_SCHOOLS: [&str; 8] = [
+    "abjuration", "conjuration", "divination", "enchantment",
+    "evocation", "illusion", "necromancy", "transmutation",
+];
+pub const ITEM_RARITIES: [&str; 7] = ["common", "uncommon", "rare", "very rare", "legendary", "artifact", "varies"];
+pub const HIT_DICE: [u64; 4] = [6, 8, 10, 12];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Dotted path to the offending field, e.g. "levels[3].spell_slots"
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+pub fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|i| i.severity == Severity::Error)
+}
+
+/// Cross-object facts needed by reference checks
+#[derive(Debug, Clone, Default)]
+pub struct ValidationContext {
+    pub class_names: Vec<String>,
+}
+
+impl ValidationContext {
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let class_names = store::load_all("Classes")?
+            .iter()
+            .map(|obj| obj.name().to_string())
+            .collect();
+        Ok(ValidationContext { class_names })
+    }
+
+    fn has_class(&self, name: &str) -> bool {
+        self.class_names.iter().any(|c| c.eq_ignore_ascii_case(name))
+    }
+}
+
+struct Checker {
+    issues: Vec<Issue>,
+}
+
+impl Checker {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(Issue { severity: Severity::Error, path: path.into(), message: message.into() });
+    }
+
+    fn warn(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(Issue { severity: Severity::Warning, path: path.into(), message: message.into() });
+    }
+
+    /// Checks that an optional integer field lies within bounds, reporting type mismatches as errors
+    fn int_in_range(&mut self, data: &Value, key: &str, path: &str, min: i64, max: i64) -> Option<i64> {
+        let value = data.get(key)?;
+        match value.as_i64() {
+            Some(n) if n < min || n > max => {
+                self.error(path, format!("must be between {} and {}, found {}", min, max, n));
+                Some(n)
+            }
+            Some(n) => Some(n),
+            None => {
+                self.error(path, format!("must be an integer, found {}", value));
+                None
+            }
+        }
+    }
+
+}
+
+/// Validates one object of the given type and returns every issue found
+pub fn validate(kind: &str, data: &Value, ctx: &ValidationContext) -> Vec<Issue> {
+    let mut c = Checker { issues: Vec::new() };
+
+    if !data.is_object() {
+        c.error("", "must be a JSON object");
+        return c.issues;
+    }
+    match data.get("name").and_then(|n| n.as_str()) {
+        Some(name) if !name.trim().is_empty() => {}
+        Some(_) => c.error("name", "must not be empty"),
+        None => c.error("name", "is required"),
+    }
+
+    if let Some(Err(e)) = ResourceKind::from_name(kind).map(|k| k.check_schema(data)) {
+        c.error("", format!("does not match the schema: {}", e));
+    }
+
+    match kind {
+        "Spells" => validate_spell(&mut c, data),
+        "Classes" => validate_class(&mut c, data),
+        "Subclasses" => validate_subclass(&mut c, data, ctx),
+        "Features" => validate_feature(&mut c, data, ctx),
+        "Items" => validate_item(&mut c, data),
+        "Races" => validate_race(&mut c, data),
+        "Creatures" => validate_creature(&mut c, data),
+        "Backgrounds" => validate_background(&mut c, data),
+        "Feats" => validate_feat(&mut c, data),
+        "Conditions" => validate_condition(&mut c, data),
+        "Characters" => validate_character(&mut c, data, ctx),
+        "Campaigns" => validate_campaign(&mut c, data),
+        "Sessions" => validate_session(&mut c, data),
+        "Encounters" => validate_encounter(&mut c, data),
+        _ => {}
+    }
+
+    c.issues
+}
+
+fn validate_spell(c: &mut Checker, data: &Value) {
+    let level = c.int_in_range(data, "level", "level", 0, 9);
+    if level.is_none() && data.get("level").is_none() {
+        c.error("level", "is required");
+    }
+
+    match data.get("school").and_then(|s| s.as_str()) {
+        Some(school) if !SPELL_SCHOOLS.contains(&school.to_lowercase().as_str()) => {
+            c.error("school", format!("'{}' is not one of {}", school, SPELL_SCHOOLS.join(", ")));
+        }
+        Some(_) => {}
+        None => c.warn("school", "is missing"),
+    }
+
+    let components: Vec<String> = data.get("components")
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|c| c.as_str().map(|s| s.to_uppercase())).collect())
+        .unwrap_or_default();
+    for (i, component) in components.iter().enumerate() {
+        if !["V", "S", "M"].contains(&component.as_str()) {
+            c.error(format!("components[{}]", i), format!("'{}' must be V, S or M", component));
+        }
+    }
+    let material = data.get("material").and_then(|m| m.as_str()).unwrap_or("").trim();
+    if components.iter().any(|c| c == "M") && material.is_empty() {
+        c.error("material", "is required when the M component is listed");
+    }
+    if !material.is_empty() && !components.iter().any(|c| c == "M") {
+        c.warn("components", "material is described but the M component is not listed");
+    }
+
+    let concentration = data.get("concentration").and_then(|v| v.as_bool()).unwrap_or(false);
+    let duration = data.get("duration").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+    if concentration && duration == "instantaneous" {
+        c.error("duration", "a concentration spell cannot be instantaneous");
+    }
+    if level == Some(0) {
+        if data.get("ritual").and_then(|v| v.as_bool()).unwrap_or(false) {
+            c.warn("ritual", "cantrips are never rituals");
+        }
+        if data.get("higher_levels").is_some_and(|v| !v.is_null()) {
+            c.warn("higher_levels", "cantrips cannot be cast at higher levels");
+        }
+    }
+}
+
+fn validate_class(c: &mut Checker, data: &Value) {
+    match data.get("hit_die").and_then(|v| v.as_u64()) {
+        Some(die) if !HIT_DICE.contains(&die) => c.error("hit_die", format!("d{} is not a class hit die (6, 8, 10 or 12)", die)),
+        Some(_) => {}
+        None => c.error("hit_die", "is required"),
+    }
+    c.int_in_range(data, "subclass_level", "subclass_level", 0, 20);
+
+    let mut seen = Vec::new();
+    for (i, level) in data.get("levels").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        let path = format!("levels[{}]", i);
+        if let Some(n) = c.int_in_range(level, "level", &format!("{}.level", path), 1, 20) {
+            if seen.contains(&n) {
+                c.warn(format!("{}.level", path), format!("level {} is listed more than once", n));
+            }
+            seen.push(n);
+        }
+        if let Some(slots) = level.get("spell_slots").and_then(|v| v.as_array()) {
+            if slots.len() > 9 {
+                c.error(format!("{}.spell_slots", path), "there are only 9 spell slot levels");
+            }
+        }
+    }
+
+    for ability in data.get("multiclass_prerequisites").and_then(|v| v.as_object()).into_iter().flat_map(|m| m.keys()) {
+        if models::AbilityScores::default().get(ability).is_none() {
+            c.error(format!("multiclass_prerequisites.{}", ability), "is not an ability score");
+        }
+    }
+}
+
+fn validate_subclass(c: &mut Checker, data: &Value, ctx: &ValidationContext) {
+    match data.get("class").and_then(|v| v.as_str()) {
+        Some(class) if !ctx.has_class(class) => c.error("class", format!("references unknown class '{}'", class)),
+        Some(_) => {}
+        None => c.error("class", "is required"),
+    }
+    for (i, feature) in data.get("features").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        c.int_in_range(feature, "level", &format!("features[{}].level", i), 1, 20);
+    }
+}
+
+fn validate_feature(c: &mut Checker, data: &Value, ctx: &ValidationContext) {
+    c.int_in_range(data, "level", "level", 1, 20);
+    if let Some(class) = data.get("class").and_then(|v| v.as_str()) {
+        if !ctx.has_class(class) {
+            c.warn("class", format!("references unknown class '{}'", class));
+        }
+    }
+}
+
+fn validate_item(c: &mut Checker, data: &Value) {
+    match data.get("rarity").and_then(|v| v.as_str()) {
+        Some(rarity) if !ITEM_RARITIES.contains(&rarity.to_lowercase().as_str()) => {
+            c.error("rarity", format!("'{}' is not one of {}", rarity, ITEM_RARITIES.join(", ")));
+        }
+        Some(_) => {}
+        None => c.warn("rarity", "is missing"),
+    }
+    if let Some(bonus) = data.get("bonus").and_then(|v| v.as_i64()) {
+        if !(0..=3).contains(&bonus) {
+            c.warn("bonus", format!("+{} is outside the usual +1 to +3 range", bonus));
+        }
+    }
+}
+
+fn validate_race(c: &mut Checker, data: &Value) {
+    if data.get("speed").and_then(|v| v.as_u64()) == Some(0) {
+        c.warn("speed", "a speed of 0 is unusual");
+    }
+    for ability in data.get("ability_bonuses").and_then(|v| v.as_object()).into_iter().flat_map(|m| m.keys()) {
+        if models::AbilityScores::default().get(ability).is_none() {
+            c.error(format!("ability_bonuses.{}", ability), "is not an ability score");
+        }
+    }
+}
+
+fn validate_background(c: &mut Checker, data: &Value) {
+    let skills = data.get("skill_proficiencies").and_then(|v| v.as_array()).map(|a| a.len()).unwrap_or(0);
+    if skills == 0 {
+        c.warn("skill_proficiencies", "backgrounds usually grant two skill proficiencies");
+    }
+    let feature = data.get("feature_name").and_then(|v| v.as_str()).unwrap_or("").trim();
+    let feature_text = data.get("feature_description").and_then(|v| v.as_str()).unwrap_or("").trim();
+    if feature.is_empty() && !feature_text.is_empty() {
+        c.error("feature_name", "is required when the feature is described");
+    }
+    if !feature.is_empty() && feature_text.is_empty() {
+        c.warn("feature_description", format!("the feature '{}' has no description", feature));
+    }
+}
+
+fn validate_feat(c: &mut Checker, data: &Value) {
+    let mut total = 0;
+    for (ability, amount) in data.get("ability_increases").and_then(|v| v.as_object()).into_iter().flatten() {
+        if models::AbilityScores::default().get(ability).is_none() {
+            c.error(format!("ability_increases.{}", ability), "is not an ability score");
+        }
+        total += amount.as_u64().unwrap_or(0);
+    }
+    for (i, ability) in data.get("ability_choices").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        if ability.as_str().and_then(|a| models::AbilityScores::default().get(a)).is_none() {
+            c.error(format!("ability_choices[{}]", i), format!("{} is not an ability score", ability));
+        }
+    }
+    if total > 2 {
+        c.warn("ability_increases", format!("+{} in total is more than a feat usually grants", total));
+    }
+    if data.get("description").and_then(|v| v.as_str()).is_none_or(|d| d.trim().is_empty())
+        && data.get("benefits").and_then(|v| v.as_array()).is_none_or(|b| b.is_empty())
+    {
+        c.warn("description", "the feat has neither a description nor benefits");
+    }
+}
+
+fn validate_condition(c: &mut Checker, data: &Value) {
+    if data.get("description").and_then(|v| v.as_str()).is_none_or(|d| d.trim().is_empty())
+        && data.get("effects").and_then(|v| v.as_array()).is_none_or(|e| e.is_empty())
+    {
+        c.error("effects", "a condition needs a description or at least one effect");
+    }
+}
+
+fn validate_creature(c: &mut Checker, data: &Value) {
+    match data.get("challenge_rating").and_then(|v| v.as_str()) {
+        Some(cr) if !models::CHALLENGE_XP.iter().any(|(rating, _)| *rating == cr.trim()) => {
+            c.error("challenge_rating", format!("'{}' is not a challenge rating (0, 1/8, 1/4, 1/2 or 1 to 30)", cr));
+        }
+        Some(_) => {}
+        None => c.error("challenge_rating", "is required"),
+    }
+    match data.get("size").and_then(|v| v.as_str()) {
+        Some(size) if !models::CREATURE_SIZES.contains(&size.to_lowercase().as_str()) => {
+            c.error("size", format!("'{}' is not one of {}", size, models::CREATURE_SIZES.join(", ")));
+        }
+        Some(_) => {}
+        None => c.warn("size", "is missing"),
+    }
+    c.int_in_range(data, "armor_class", "armor_class", 1, 30);
+    let hit_points = c.int_in_range(data, "hit_points", "hit_points", 1, 1000);
+    if hit_points.is_none() && data.get("hit_points").is_none() {
+        c.error("hit_points", "is required");
+    }
+    if let Some(dice) = data.get("hit_dice").and_then(|v| v.as_str()).filter(|d| !d.trim().is_empty()) {
+        match DiceExpr::parse(dice) {
+            Some(expr) => {
+                let average = expr.average().floor() as i64;
+                if hit_points.is_some_and(|hp| hp != average) {
+                    c.warn("hit_points", format!("{} does not match the {} average of {}", hit_points.unwrap_or(0), dice, average));
+                }
+            }
+            None => c.error("hit_dice", format!("'{}' is not a dice expression", dice)),
+        }
+    }
+    if let Some(abilities) = data.get("abilities") {
+        for ability in ABILITIES {
+            c.int_in_range(abilities, ability, &format!("abilities.{}", ability), 1, 30);
+        }
+    }
+    for ability in data.get("saving_throws").and_then(|v| v.as_object()).into_iter().flat_map(|m| m.keys()) {
+        if models::AbilityScores::default().get(ability).is_none() {
+            c.error(format!("saving_throws.{}", ability), "is not an ability score");
+        }
+    }
+    for group in ["traits", "actions", "bonus_actions", "reactions", "legendary_actions", "lair_actions"] {
+        for (i, action) in data.get(group).and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+            if action.get("name").and_then(|n| n.as_str()).is_none_or(|n| n.trim().is_empty()) {
+                c.error(format!("{}[{}].name", group, i), "must not be empty");
+            }
+        }
+    }
+    let legendary = data.get("legendary_actions").and_then(|v| v.as_array()).is_some_and(|a| !a.is_empty());
+    let per_round = data.get("legendary_actions_per_round").and_then(|v| v.as_u64()).unwrap_or(0);
+    if legendary && per_round == 0 {
+        c.warn("legendary_actions_per_round", "legendary actions are listed but none can be taken per round");
+    }
+    if !legendary && per_round > 0 {
+        c.warn("legendary_actions", "legendary actions per round is set but no legendary actions are listed");
+    }
+}
+
+fn validate_campaign(c: &mut Checker, data: &Value) {
+    let members = data.get("members").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    if !members.is_empty() && !members.iter().any(|m| m.get("role").and_then(|r| r.as_str()) == Some("dm")) {
+        c.warn("members", "campaign has no DM");
+    }
+}
+
+fn validate_session(c: &mut Checker, data: &Value) {
+    if data.get("campaign").and_then(|v| v.as_str()).is_none_or(|v| v.is_empty()) {
+        c.error("campaign", "is required");
+    }
+    c.int_in_range(data, "number", "number", 1, 10_000);
+    for (i, entry) in data.get("entries").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        if entry.get("text").and_then(|v| v.as_str()).is_none_or(|t| t.trim().is_empty()) {
+            c.error(format!("entries[{}].text", i), "must not be empty");
+        }
+    }
+}
+
+fn validate_encounter(c: &mut Checker, data: &Value) {
+    for (i, member) in data.get("party").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        c.int_in_range(member, "level", &format!("party[{}].level", i), 1, 20);
+    }
+    for (i, entry) in data.get("creatures").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        c.int_in_range(entry, "count", &format!("creatures[{}].count", i), 1, 1000);
+        if entry.get("creature").and_then(|v| v.as_str()).is_none_or(|v| v.is_empty()) {
+            c.error(format!("creatures[{}].creature", i), "is required");
+        }
+    }
+}
+
+fn validate_character(c: &mut Checker, data: &Value, ctx: &ValidationContext) {
+    if let Some(abilities) = data.get("abilities") {
+        for ability in ABILITIES {
+            c.int_in_range(abilities, ability, &format!("abilities.{}", ability), 1, 30);
+        }
+    }
+    let mut total = 0;
+    for (i, entry) in data.get("classes").and_then(|v| v.as_array()).into_iter().flatten().enumerate() {
+        total += c.int_in_range(entry, "level", &format!("classes[{}].level", i), 1, 20).unwrap_or(0);
+        if let Some(class) = entry.get("class").and_then(|v| v.as_str()) {
+            if !ctx.has_class(class) {
+                c.warn(format!("classes[{}].class", i), format!("references unknown class '{}'", class));
+            }
+        }
+    }
+    if total > 20 {
+        c.error("classes", format!("total character level {} exceeds 20", total));
+    }
+}
+
+/// Logs each issue under the object's label, at error or warn level by severity
+pub fn print_issues(label: &str, issues: &[Issue]) {
+    for issue in issues {
+        match issue.severity {
+            Severity::Error => error!("{}: {}", label, issue),
+            Severity::Warning => warn!("{}: {}", label, issue),
+        }
+    }
+}
+
+/// Validates an object about to be written by the store, failing on errors and logging warnings
+pub fn check_before_write(kind: &str, data: &Value) -> Result<(), anyhow::Error> {
+    check_with(kind, data, &ValidationContext::load()?)
+}
+
+/// `check_before_write` against a context the caller already loaded
+pub fn check_with(kind: &str, data: &Value, ctx: &ValidationContext) -> Result<(), anyhow::Error> {
+    let issues = validate(kind, data, ctx);
+    let label = format!("{}/{}", kind, data.get("name").and_then(|n| n.as_str()).unwrap_or("[unnamed]"));
+    print_issues(&label, &issues);
+    if has_errors(&issues) {
+        return Err(Error::Validation(format!("{} failed validation, nothing was saved.", label)).into());
+    }
+    Ok(())
+}
+
+/// Validates every cached object of the given types, returning (objects checked, errors, warnings)
+pub fn validate_cache(kinds: &[&str]) -> Result<(usize, usize, usize), anyhow::Error> {
+    let ctx = ValidationContext::load()?;
+    let (mut checked, mut errors, mut warnings) = (0, 0, 0);
+    for kind in kinds {
+        let objects: Vec<StoredObject> = store::load_all(kind)?;
+        for obj in objects {
+            let issues = validate(kind, &obj.data, &ctx);
+            print_issues(&format!("{}/{}", kind, obj.id), &issues);
+            errors += issues.iter().filter(|i| i.severity == Severity::Error).count();
+            warnings += issues.iter().filter(|i| i.severity == Severity::Warning).count();
+            checked += 1;
+        }
+    }
+    Ok((checked, errors, warnings))
+}
+
+/// Validates the data carried by every POST/PUT line of a type's journal, returning the error count
+pub fn validate_journal(kind: &str, ctx: &ValidationContext) -> Result<usize, anyhow::Error> {
+    let path = store::journal_path(kind)?;
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let mut errors = 0;
+    for (n, line) in fs::read_to_string(&path)?.lines().enumerate() {
+        let Some((method, _, Some(data))) = parse_line(line.trim()) else { continue };
+        if method != "POST" && method != "PUT" {
+            continue;
+        }
+        let label = format!("{} journal line {}", kind, n + 1);
+        let issues = match serde_json::from_str::<Value>(&data) {
+            Ok(value) => validate(kind, &value, ctx),
+            Err(e) => vec![Issue { severity: Severity::Error, path: String::new(), message: format!("data is not valid JSON: {}", e) }],
+        };
+        print_issues(&label, &issues);
+        errors += issues.iter().filter(|i| i.severity == Severity::Error).count();
+    }
+
+    Ok(errors)
+}